@@ -0,0 +1,146 @@
+use std::{cell::Cell, fmt, str::FromStr};
+
+use alloy::primitives::U256;
+use serde::{de, Deserializer};
+
+thread_local! {
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with amounts deserialized by the strict parser when `strict` is set.
+///
+/// The choice only holds for the current thread and is undone once `f` returns, so `f`
+/// must deserialize synchronously.
+pub fn with_parser<T>(strict: bool, f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            STRICT.set(self.0);
+        }
+    }
+
+    let _restore = Restore(STRICT.replace(strict));
+    f()
+}
+
+/// Deserializes an amount from a JSON number or a string.
+///
+/// Strings may be decimal (`"1000000"`), `0x`-hex (`"0xf4240"`), separated with underscores (`"1_000_000"`)
+/// or in scientific notation (`"1e6"`, `"1.5e18"`) as long as the result is a whole number.
+/// With strict amounts only plain decimal and `0x`-hex strings are accepted.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    deserializer.deserialize_any(AmountVisitor)
+}
+
+struct AmountVisitor;
+
+impl de::Visitor<'_> for AmountVisitor {
+    type Value = U256;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a non-negative integer amount")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<U256, E> {
+        Ok(U256::from(v))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<U256, E> {
+        Ok(U256::from(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<U256, E> {
+        let parsed = if STRICT.get() {
+            parse_strict(v)
+        } else {
+            parse(v)
+        };
+
+        parsed.map_err(|reason| E::custom(format!("invalid amount {v:?}: {reason}")))
+    }
+}
+
+fn parse_strict(s: &str) -> Result<U256, &'static str> {
+    if s.is_empty() || s.contains('_') {
+        return Err("expected a decimal or 0x-prefixed hex integer");
+    }
+
+    U256::from_str(s).map_err(|_| "expected a decimal or 0x-prefixed hex integer")
+}
+
+fn parse(s: &str) -> Result<U256, &'static str> {
+    if s.is_empty() {
+        return Err("empty amount");
+    }
+
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        let hex = strip_separators(hex, u8::is_ascii_hexdigit)?;
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err("invalid hex digits");
+        }
+        return U256::from_str_radix(&hex, 16).map_err(|_| "does not fit in 256 bits");
+    }
+
+    let s = strip_separators(s, u8::is_ascii_digit)?;
+    match s.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => parse_scientific(mantissa, exponent),
+        None => parse_decimal(&s),
+    }
+}
+
+/// Drops the underscores from `s`, each of which has to sit between two digits.
+fn strip_separators(s: &str, is_digit: fn(&u8) -> bool) -> Result<String, &'static str> {
+    let bytes = s.as_bytes();
+    let separates_digits =
+        |i: usize| i > 0 && is_digit(&bytes[i - 1]) && bytes.get(i + 1).is_some_and(is_digit);
+    if !s.match_indices('_').all(|(i, _)| separates_digits(i)) {
+        return Err("underscores may only separate digits");
+    }
+
+    Ok(s.replace('_', ""))
+}
+
+fn parse_decimal(s: &str) -> Result<U256, &'static str> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("expected a non-negative integer");
+    }
+
+    U256::from_str_radix(s, 10).map_err(|_| "does not fit in 256 bits")
+}
+
+fn parse_scientific(mantissa: &str, exponent: &str) -> Result<U256, &'static str> {
+    let exponent = exponent.strip_prefix('+').unwrap_or(exponent);
+    if exponent.is_empty() || !exponent.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("expected a non-negative integer exponent");
+    }
+    let exponent: usize = exponent.parse().map_err(|_| "exponent is too large")?;
+
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return Err("missing mantissa");
+    }
+    if !integer
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err("invalid mantissa");
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    let Some(zeros) = exponent.checked_sub(fraction.len()) else {
+        return Err("not a whole number");
+    };
+
+    let digits = format!("0{integer}{fraction}");
+    let value = U256::from_str_radix(&digits, 10).map_err(|_| "does not fit in 256 bits")?;
+    if value.is_zero() {
+        return Ok(value);
+    }
+
+    U256::from(10)
+        .checked_pow(U256::from(zeros))
+        .and_then(|scale| value.checked_mul(scale))
+        .ok_or("does not fit in 256 bits")
+}
@@ -1,20 +1,528 @@
-use alloy::{primitives::Address, transports::http::reqwest::Url};
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+};
+
+use alloy::{
+    primitives::{Address, B256},
+    transports::http::reqwest::Url,
+};
 use config::{Config, ConfigError, Environment};
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct AppConfig {
+    /// Node and contract of the default chain, used by requests without a chain id
     pub rpc_url: Url,
+    /// Nodes of the default chain tried in order when `rpc_url` fails
+    #[serde(default)]
+    pub fallback_rpc_urls: Vec<Url>,
     pub contract_address: Address,
-    pub tx_signer: String,
+    /// Additional chains by chain id, e.g. `CHAINS__137__RPC_URL` and `CHAINS__137__CONTRACT_ADDRESS`
+    #[serde(default)]
+    pub chains: HashMap<u64, ChainConfig>,
+    #[serde(default)]
+    pub signer_type: SignerType,
+    /// Private key of the local signer
+    pub tx_signer: Option<String>,
+    /// Key id or ARN of the `aws_kms` signer
+    pub kms_key_id: Option<String>,
+    /// AWS region of the KMS key, resolved from the default AWS provider chain if not set
+    pub kms_region: Option<String>,
     pub port: u16,
+    /// How long in-flight requests may finish after SIGTERM or SIGINT before they are dropped
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+    /// Known token decimals used to pre-seed the decimals cache,
+    /// e.g. `TOKEN_DECIMALS__0XA0B8...=6`
+    #[serde(default)]
+    pub token_decimals: HashMap<Address, u8>,
+    /// Treat identical batch requests without an `Idempotency-Key` within this many seconds as one,
+    /// disabled if not set
+    pub implicit_dedup_secs: Option<u64>,
+    /// How long finished background jobs stay pollable
+    #[serde(default = "default_job_ttl_secs")]
+    pub job_ttl_secs: u64,
+    /// Confirmations to wait for before responding, 1 responds once the transaction is mined
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    /// Give up waiting for confirmations after this long, the transaction may still be mined then
+    pub confirmation_timeout_secs: Option<u64>,
+    /// Gas limit used when `estimate_gas` fails, estimation errors are fatal if unset
+    pub gas_fallback: Option<GasFallback>,
+    /// Keccak256 hashes of token bytecode the service may interact with, any token is allowed if unset
+    pub verified_token_codehashes: Option<HashSet<B256>>,
+    /// Simulate state-changing requests with `eth_call` instead of broadcasting them
+    #[serde(default)]
+    pub simulate_only: bool,
+    /// What to do at startup with transactions a previous run left pending
+    #[serde(default)]
+    pub pending_recovery: PendingRecovery,
+    /// Upper bound of the startup recovery per chain, the server starts regardless once it passes
+    #[serde(default = "default_recovery_timeout_secs")]
+    pub recovery_timeout_secs: u64,
+    /// Re-read an ERC20 disperse spender's balance if it is older than this when the disperse is built
+    pub max_quote_age_secs: Option<u64>,
+    /// What to do if a collect spender's allowance dropped after it was first read
+    #[serde(default)]
+    pub collect_allowance_recheck: AllowanceRecheck,
+    /// Preset of checks applied to the recipients of every disperse, transfer and collect
+    #[serde(default)]
+    pub recipient_policy: RecipientPolicy,
+    /// Recipients rejected by the `lenient` and `strict` policies
+    #[serde(default)]
+    pub recipient_denylist: HashSet<Address>,
+    /// Allow ERC20 disperses whose spender isn't the caller, the caller then relays the spender's tokens
+    #[serde(default = "default_relayed_disperse")]
+    pub relayed_disperse: bool,
+    /// What to do with disperse recipients that are contracts, overrides the recipient policy
+    pub disperse_contract_recipients: Option<ContractRecipients>,
+    /// ETH sent to every ERC20 disperse recipient holding less than this before the disperse, disabled if not set
+    pub recipient_topup_wei: Option<u64>,
+    /// Warn in ETH disperse responses when the caller is left with less than this, disabled if not set
+    pub low_balance_warning_wei: Option<u64>,
+    /// Disperses to more recipients are split into several transactions sent one by one
+    pub max_recipients_per_tx: Option<NonZeroUsize>,
+    /// Disperses and collects with more recipients or spenders are rejected
+    #[serde(default = "default_max_recipients")]
+    pub max_recipients: usize,
+    /// Request bodies above this size are rejected before they are parsed
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Flag recipients and tokens whose contract was deployed within this many blocks, disabled if not set
+    pub new_contract_window_blocks: Option<u64>,
+    /// Whether contracts caught by `new_contract_window_blocks` only add a warning or fail the request
+    #[serde(default)]
+    pub new_contract_policy: NewContractPolicy,
+    /// What to do with disperse recipients and collect spenders whose amount is or rounds down to zero
+    #[serde(default)]
+    pub zero_amounts: ZeroAmounts,
+    /// What to do with a collect spender whose permit is expired or invalid
+    #[serde(default)]
+    pub collect_permit_failure: PermitFailure,
+    /// The service holds the keys of collect spenders and approves the contract from them when their
+    /// allowance is short
+    #[serde(default)]
+    pub custodial_spenders: bool,
+    /// Private keys of the custodial spenders, registered with the wallet next to the transaction signer
+    #[serde(default)]
+    pub spender_keys: Vec<String>,
+    /// Reject ERC20 requests with an absolute amount above the token's total supply
+    #[serde(default)]
+    pub check_total_supply: bool,
+    /// Custom client-facing messages, e.g. `ERROR_TEMPLATES__INSUFFICIENT_FUNDS`
+    #[serde(default)]
+    pub error_templates: ErrorTemplates,
+    /// Debug only: return raw JSON-RPC errors of the node in the `X-RPC-Error` header
+    #[serde(default)]
+    pub expose_rpc_errors: bool,
+    /// Connect to the node and prime caches at startup instead of on the first request
+    #[serde(default)]
+    pub warm_up: bool,
+    /// Tokens whose decimals are fetched during warm-up
+    #[serde(default)]
+    pub warm_up_tokens: Vec<Address>,
+    /// Attach an `eth_createAccessList` access list to transactions, disable for nodes without the method
+    #[serde(default = "default_use_access_lists")]
+    pub use_access_lists: bool,
+    /// Maximum number of in-flight RPC requests to the node, unlimited if not set
+    pub max_concurrent_rpc: Option<NonZeroUsize>,
+    /// How often the provider polls for new blocks while waiting for receipts,
+    /// alloy's default (250ms for local nodes, 7s otherwise) if not set
+    pub poll_interval_ms: Option<u64>,
+    /// Fail RPC requests to the node that take longer than this, unlimited if not set
+    pub rpc_timeout_ms: Option<u64>,
+    /// Resending of RPC requests that failed with a transient error
+    #[serde(default)]
+    pub rpc_retry: RpcRetry,
+    /// Fail API requests that take longer than this with `504`, unlimited if not set
+    pub request_timeout_secs: Option<u64>,
+    /// Wrap all JSON responses in `{ "data", "error", "meta" }`
+    #[serde(default)]
+    pub response_envelope: bool,
+    /// Replace addresses, amounts and other request data in logs with short hashes
+    #[serde(default)]
+    pub redact_logs: bool,
+    /// Only accept plain decimal and `0x`-hex amount strings, no underscores or scientific notation
+    #[serde(default)]
+    pub strict_amounts: bool,
+    /// Gas in gwei a caller may spend per window, new transactions are rejected once exceeded
+    pub caller_gas_budget_gwei: Option<u64>,
+    #[serde(default = "default_caller_budget_window_secs")]
+    pub caller_budget_window_secs: u64,
+    /// Bearer token required by `GET /api/stats`, the endpoint is only served if set
+    pub stats_token: Option<String>,
+    /// How long sent transactions are kept for `GET /api/stats`
+    #[serde(default = "default_stats_window_secs")]
+    pub stats_window_secs: u64,
+    /// Enables `POST /api/testnet/fund` if `testnet_faucet` is set as well, refused on known mainnets
+    #[serde(default)]
+    pub testnet_mode: bool,
+    pub testnet_faucet: Option<TestnetFaucet>,
+    /// Amount requested per `testnet/fund` call
+    #[serde(default = "default_testnet_fund_wei")]
+    pub testnet_fund_wei: u64,
+    /// Let requests force an error with the `X-Test-Fail` header, refused on known mainnets
+    #[serde(default)]
+    pub failure_injection: bool,
+    /// Block explorer transaction URL per chain id, e.g. `EXPLORER_URLS__1=https://etherscan.io/tx/`,
+    /// the tx hash is appended to it
+    #[serde(default)]
+    pub explorer_urls: HashMap<u64, Url>,
+    /// Multicall3 address per chain id for chains where it isn't deployed at the canonical address,
+    /// e.g. `MULTICALL3_ADDRESSES__324=0xF9cda624FBC7e059355ce98a31693d299FACd963`
+    #[serde(default)]
+    pub multicall3_addresses: HashMap<u64, Address>,
+    /// Redis instance transaction results are published to
+    pub notify_redis_url: Option<Url>,
+    #[serde(default = "default_notify_channel")]
+    pub notify_channel: String,
+}
+
+fn default_relayed_disperse() -> bool {
+    true
+}
+
+fn default_use_access_lists() -> bool {
+    true
+}
+
+fn default_max_recipients() -> usize {
+    500
+}
+
+fn default_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    30
+}
+
+fn default_recovery_timeout_secs() -> u64 {
+    300
+}
+
+fn default_confirmations() -> u64 {
+    1
+}
+
+fn default_caller_budget_window_secs() -> u64 {
+    86400
+}
+
+fn default_stats_window_secs() -> u64 {
+    86400
+}
+
+fn default_testnet_fund_wei() -> u64 {
+    10_000_000_000_000_000_000
+}
+
+fn default_notify_channel() -> String {
+    "disperse-collect.transactions".into()
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ChainConfig {
+    pub rpc_url: Url,
+    /// Nodes of the chain tried in order when `rpc_url` fails, comma-separated like `FALLBACK_RPC_URLS`
+    #[serde(default, deserialize_with = "deserialize_urls")]
+    pub fallback_rpc_urls: Vec<Url>,
+    pub contract_address: Address,
+}
+
+/// List of URLs, or a comma-separated string of them. Keys nested under a chain id can't be registered
+/// as list keys of the environment source, so they arrive as one string
+fn deserialize_urls<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Url>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Urls {
+        List(Vec<Url>),
+        Joined(String),
+    }
+
+    match Urls::deserialize(deserializer)? {
+        Urls::List(urls) => Ok(urls),
+        Urls::Joined(urls) => urls
+            .split(',')
+            .map(|url| url.trim().parse().map_err(de::Error::custom))
+            .collect(),
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllowanceRecheck {
+    /// Don't re-read allowances before sending
+    #[default]
+    Off,
+    /// Fail the request with `AllowanceChanged`
+    Fail,
+    /// Collect at most the current allowance
+    Clamp,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecipientPolicy {
+    /// No checks
+    #[default]
+    Off,
+    /// Reject the zero address and denylisted recipients
+    Lenient,
+    /// `lenient`, and reject contract recipients of disperses and addresses without an EIP-55 checksum
+    Strict,
+}
+
+impl RecipientPolicy {
+    /// Whether the zero address and `recipient_denylist` are rejected
+    pub fn rejects_invalid(self) -> bool {
+        self != Self::Off
+    }
+
+    pub fn requires_checksum(self) -> bool {
+        self == Self::Strict
+    }
+
+    fn contract_recipients(self) -> ContractRecipients {
+        match self {
+            Self::Off | Self::Lenient => ContractRecipients::Allow,
+            Self::Strict => ContractRecipients::Reject,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContractRecipients {
+    /// Don't check recipients
+    #[default]
+    Allow,
+    /// Leave contract recipients out of the disperse
+    Skip,
+    /// Fail the request with `ContractRecipient`
+    Reject,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewContractPolicy {
+    /// Add a warning to the response
+    #[default]
+    Warn,
+    /// Fail the request with `NewContract`
+    Reject,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PendingRecovery {
+    /// Log the nonce gap and start right away, new transactions queue behind the pending ones
+    #[default]
+    Alert,
+    /// Start once the pending transactions are mined, at most `confirmation_timeout_secs`
+    Wait,
+    /// Replace the pending transactions with zero-value self-transfers at a higher fee
+    Replace,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZeroAmounts {
+    /// Fail the request, with `InvalidFractionalAmount` for fractions
+    Error,
+    /// Leave the entry out of the transaction and report it in `skipped`
+    #[default]
+    Skip,
+    /// Keep the zero transfer in the transaction
+    Allow,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermitFailure {
+    /// Fail the request with `InvalidPermit`
+    #[default]
+    Fail,
+    /// Leave the spender out of the collect
+    Skip,
+}
+
+/// Message templates, `{name}` placeholders are replaced with the error's fields
+#[derive(Clone, Default, Deserialize)]
+pub struct ErrorTemplates {
+    /// `{address}`, `{required}`, `{available}`
+    pub insufficient_funds: Option<String>,
+    /// `{fraction}`, `{units}`
+    pub invalid_fractional_amount: Option<String>,
+    /// `{address}`
+    pub token_not_found: Option<String>,
+}
+
+/// Where test ETH comes from: `anvil` or the URL of a faucet
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum TestnetFaucet {
+    /// Set the balance with `anvil_setBalance`
+    Anvil,
+    /// POST `{ "address", "amount" }` to the faucet
+    Http(Url),
+}
+
+impl TryFrom<String> for TestnetFaucet {
+    type Error = url::ParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "anvil" => Ok(Self::Anvil),
+            url => url.parse().map(Self::Http),
+        }
+    }
+}
+
+/// Chain ids `testnet_mode` and `failure_injection` are refused on
+const MAINNET_CHAIN_IDS: &[u64] = &[
+    1,      // Ethereum
+    10,     // Optimism
+    56,     // BNB Smart Chain
+    100,    // Gnosis
+    137,    // Polygon
+    250,    // Fantom
+    324,    // zkSync Era
+    8453,   // Base
+    42161,  // Arbitrum One
+    42220,  // Celo
+    43114,  // Avalanche C-Chain
+    59144,  // Linea
+    534352, // Scroll
+];
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerType {
+    /// Private key from `tx_signer`
+    #[default]
+    Local,
+    /// Key held in AWS KMS, requires the `aws-kms` feature
+    AwsKms,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct GasFallback {
+    pub base: u64,
+    #[serde(default)]
+    pub per_recipient: u64,
+}
+
+impl GasFallback {
+    /// Calculates `base + per_recipient * recipients`
+    pub fn gas_limit(&self, recipients: usize) -> u128 {
+        u128::from(self.base) + u128::from(self.per_recipient) * recipients as u128
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct RpcRetry {
+    /// Retries after the first attempt, 0 disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub initial_backoff_ms: u64,
+}
+
+impl Default for RpcRetry {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 200,
+        }
+    }
+}
+
+fn default_job_ttl_secs() -> u64 {
+    3600
 }
 
 impl AppConfig {
+    /// What to do with disperse recipients that are contracts, set explicitly or by the recipient policy
+    pub fn contract_recipients(&self) -> ContractRecipients {
+        self.disperse_contract_recipients
+            .unwrap_or(self.recipient_policy.contract_recipients())
+    }
+
+    /// Whether `POST /api/testnet/fund` is served
+    pub fn testnet_faucet(&self) -> Option<&TestnetFaucet> {
+        self.testnet_faucet.as_ref().filter(|_| self.testnet_mode)
+    }
+
+    /// Fails if `testnet_mode` or `failure_injection` is enabled on a known mainnet
+    pub fn validate_chain(&self, chain_id: u64) -> Result<(), ConfigError> {
+        if !MAINNET_CHAIN_IDS.contains(&chain_id) {
+            return Ok(());
+        }
+
+        if self.testnet_mode {
+            return Err(ConfigError::Message(format!(
+                "TESTNET_MODE can't be enabled on mainnet chain {chain_id}"
+            )));
+        }
+
+        if self.failure_injection {
+            return Err(ConfigError::Message(format!(
+                "FAILURE_INJECTION can't be enabled on mainnet chain {chain_id}"
+            )));
+        }
+
+        Ok(())
+    }
+
     pub fn load() -> Result<Self, ConfigError> {
         Config::builder()
-            .add_source(Environment::default())
+            .add_source(
+                Environment::default()
+                    .separator("__")
+                    .try_parsing(true)
+                    .list_separator(",")
+                    .with_list_parse_key("fallback_rpc_urls")
+                    .with_list_parse_key("verified_token_codehashes")
+                    .with_list_parse_key("warm_up_tokens")
+                    .with_list_parse_key("recipient_denylist")
+                    .with_list_parse_key("spender_keys"),
+            )
             .build()?
             .try_deserialize()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::ChainConfig;
+
+    #[test]
+    fn should_parse_comma_separated_chain_fallbacks() {
+        let chain = |fallback_rpc_urls| {
+            serde_json::from_value::<ChainConfig>(json!({
+                "rpc_url": "https://rpc.example.org",
+                "contract_address": "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                "fallback_rpc_urls": fallback_rpc_urls,
+            }))
+            .unwrap()
+        };
+        let expected = ["https://a.example.org/", "https://b.example.org/"];
+
+        let joined = chain(json!("https://a.example.org, https://b.example.org"));
+        let listed = chain(json!(["https://a.example.org", "https://b.example.org"]));
+
+        for config in [joined, listed] {
+            let urls: Vec<_> = config
+                .fallback_rpc_urls
+                .iter()
+                .map(|u| u.as_str())
+                .collect();
+            assert_eq!(urls, expected);
+        }
+    }
+}
@@ -1,25 +1,84 @@
-use alloy::sol;
-use alloy::transports::http::{Client, Http};
+use alloy::{
+    primitives::{address, Address},
+    sol,
+};
 use DisperseCollect::DisperseCollectInstance;
+use IERC20Permit::IERC20PermitInstance;
+use IMulticall3::IMulticall3Instance;
 use IERC20::IERC20Instance;
 
-use crate::state::{AppNetwork, DefaultProvider};
+use crate::state::{AppNetwork, AppTransport, DefaultProvider};
 
 sol!(
     #[allow(missing_docs)]
-    #[sol(rpc)]
+    #[sol(rpc, abi)]
     IERC20,
     "abi/IERC20.json"
 );
 
-pub type Erc20Contract = IERC20Instance<Http<Client>, DefaultProvider, AppNetwork>;
+pub type Erc20Contract = IERC20Instance<AppTransport, DefaultProvider, AppNetwork>;
+
+// EIP-2612 extension, not part of the deployed contract artifacts
+sol!(
+    #[allow(missing_docs, clippy::too_many_arguments)]
+    #[sol(rpc, abi)]
+    interface IERC20Permit {
+        function permit(
+            address owner,
+            address spender,
+            uint256 value,
+            uint256 deadline,
+            uint8 v,
+            bytes32 r,
+            bytes32 s
+        ) external;
+    }
+);
+
+pub type Erc20PermitContract = IERC20PermitInstance<AppTransport, DefaultProvider, AppNetwork>;
 
 sol!(
     #[allow(missing_docs)]
-    #[sol(rpc)]
+    #[sol(rpc, abi)]
     DisperseCollect,
     "abi/DisperseCollect.json"
 );
 
+// Outcome events of non-atomic DisperseCollect variants, where a failing transfer doesn't revert the whole
+// batch. Such a variant emits `TransferFailed` for every transfer that failed, with the zero address as
+// `token` for ETH, the disperse recipient or collect spender as `account` and the revert data as `reason`.
+// Transfers without the event succeeded. The deployed contract is atomic and never emits it.
+sol!(
+    #[allow(missing_docs)]
+    interface IDisperseOutcomes {
+        event TransferFailed(address indexed token, address indexed account, uint256 amount, bytes reason);
+    }
+);
+
 pub type DisperseCollectContract =
-    DisperseCollectInstance<Http<Client>, DefaultProvider, AppNetwork>;
+    DisperseCollectInstance<AppTransport, DefaultProvider, AppNetwork>;
+
+// Subset of Multicall3, used to batch view calls into a single eth_call
+sol!(
+    #[allow(missing_docs)]
+    #[sol(rpc)]
+    interface IMulticall3 {
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+
+        struct Result {
+            bool success;
+            bytes returnData;
+        }
+
+        function aggregate3(Call3[] calldata calls) external payable returns (Result[] memory returnData);
+    }
+);
+
+pub type Multicall3Contract = IMulticall3Instance<AppTransport, DefaultProvider, AppNetwork>;
+
+/// Address Multicall3 is deployed at on most chains
+pub const MULTICALL3_ADDRESS: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
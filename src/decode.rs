@@ -0,0 +1,68 @@
+use std::sync::LazyLock;
+
+use alloy::{
+    dyn_abi::{DynSolValue, JsonAbiExt},
+    hex,
+    json_abi::Function,
+};
+use serde_json::Value;
+
+use crate::{
+    contracts::{DisperseCollect, IERC20Permit, IERC20},
+    dto::{DecodedArg, DecodedCall},
+};
+
+/// Functions of every contract the service calls
+static FUNCTIONS: LazyLock<Vec<Function>> = LazyLock::new(|| {
+    [
+        DisperseCollect::abi::functions(),
+        IERC20::abi::functions(),
+        IERC20Permit::abi::functions(),
+    ]
+    .into_iter()
+    .flat_map(|functions| functions.into_values().flatten())
+    .collect()
+});
+
+/// Decodes calldata of a known contract function, `None` for plain transfers and unknown selectors
+pub fn decode_call(input: &[u8]) -> Option<DecodedCall> {
+    let (selector, data) = input.split_first_chunk::<4>()?;
+
+    let function = FUNCTIONS.iter().find(|f| f.selector() == *selector)?;
+    let values = function.abi_decode_input(data, true).ok()?;
+
+    let args = function
+        .inputs
+        .iter()
+        .zip(values)
+        .map(|(param, value)| DecodedArg {
+            name: param.name.clone(),
+            ty: param.ty.clone(),
+            value: to_json(value),
+        })
+        .collect();
+
+    Some(DecodedCall {
+        function: function.name.clone(),
+        args,
+    })
+}
+
+/// Numbers are strings like everywhere else in the API
+fn to_json(value: DynSolValue) -> Value {
+    match value {
+        DynSolValue::Bool(b) => b.into(),
+        DynSolValue::Int(i, _) => i.to_string().into(),
+        DynSolValue::Uint(u, _) => u.to_string().into(),
+        DynSolValue::FixedBytes(word, size) => hex::encode_prefixed(&word[..size]).into(),
+        DynSolValue::Address(address) => address.to_checksum(None).into(),
+        DynSolValue::Function(function) => hex::encode_prefixed(function).into(),
+        DynSolValue::Bytes(bytes) => hex::encode_prefixed(bytes).into(),
+        DynSolValue::String(s) => s.into(),
+        DynSolValue::Array(values)
+        | DynSolValue::FixedArray(values)
+        | DynSolValue::Tuple(values) => values.into_iter().map(to_json).collect(),
+        #[allow(unreachable_patterns)]
+        _ => Value::Null,
+    }
+}
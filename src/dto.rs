@@ -1,7 +1,25 @@
-use std::{borrow::Cow, collections::BTreeMap, fmt::Display};
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display, num::NonZeroUsize, str::FromStr};
 
-use alloy::primitives::{Address, B256, U256};
-use serde::{Deserialize, Serialize};
+use alloy::{
+    primitives::{Address, Bytes, B256, U256},
+    rpc::types::Log,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::mpsc::UnboundedSender;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    amount,
+    config::ContractRecipients,
+    recipient::{self, Recipients},
+};
+
+/// Response of an operation that can run as a background job
+pub trait BatchResponse {
+    /// Transactions sent by the operation
+    fn tx_hashes(&self) -> Vec<B256>;
+}
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -9,26 +27,304 @@ pub struct DisperseCollectResponse {
     #[serde(flatten)]
     pub tx: TransactionResponse,
     pub transfers: BTreeMap<Address, U256>,
+    /// Requested entries that were left out of the transaction
+    pub skipped: Vec<SkippedEntry>,
+    /// Transfers the contract reported as failed without reverting the transaction, not part of `transfers`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<FailedTransfer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<DisperseBreakdown>,
+    /// Collects only: what each spender could contribute, only set with `verbose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spenders: Option<BTreeMap<Address, SpenderBreakdown>>,
+    /// ETH sent to recipients before an ERC20 disperse
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_up: Option<TopUpResponse>,
+    /// Predicted balances, only set if the transaction was simulated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<BalanceDiff>,
+    /// Informational notes about the result, e.g. a low caller balance after the transaction
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedTransfer {
+    /// Disperse recipient or collect spender
+    pub address: Address,
+    pub amount: U256,
+    /// Revert data of the failed transfer
+    pub reason: Bytes,
+}
+
+/// Balances before and after a transaction, predicted from the quoted amounts
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceDiff {
+    /// Not set for ETH
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Address>,
+    pub balances: BTreeMap<Address, BalanceChange>,
+    pub note: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceChange {
+    pub before: U256,
+    pub after: U256,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopUpResponse {
+    #[serde(flatten)]
+    pub tx: TransactionResponse,
+    pub transfers: BTreeMap<Address, U256>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedEntry {
+    pub address: Address,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Requested or computed amount is zero, the transfer would be a no-op
+    ZeroAmount,
+    /// Collect target was already met by preceding spenders
+    TargetReached,
+    /// Spender's permit is expired or invalid
+    InvalidPermit,
+    /// Recipient is a contract and only externally owned accounts are allowed
+    IsContract,
+}
+
+/// Trace of how disperse amounts were derived from the balance
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisperseBreakdown {
+    /// Balance fractions were computed against
+    pub balance: U256,
+    pub block_number: u64,
+    pub total: U256,
+    pub recipients: BTreeMap<Address, RecipientBreakdown>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientBreakdown {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fraction: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<U256>,
+    pub amount: U256,
+}
+
+/// Trace of how much a collect spender contributed
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpenderBreakdown {
+    pub balance: U256,
+    pub allowance: U256,
+    /// `min(balance, allowance)`
+    pub available: U256,
+    /// Zero if the spender was skipped or its transaction wasn't sent
+    pub collected: U256,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionResponse {
     pub tx_hash: B256,
+    /// Zero if the transaction was only simulated
+    pub gas_used: u64,
+    pub effective_gas_price: U256,
+    /// `gas_used * effective_gas_price` in wei
+    pub fee: U256,
+    /// Set when the transaction was only simulated, `tx_hash` is zero then
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub simulated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_cost: Option<TxCost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedCall>,
+    /// Block explorer page of the transaction, if an explorer is configured for the chain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explorer_url: Option<Url>,
+    /// Caller's balance of the transferred asset once the transfer is mined
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_after: Option<U256>,
+    /// Position of the transaction in its block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_params: Option<GasParams>,
+    /// Logs emitted by the mined transaction
+    #[serde(skip)]
+    pub logs: Vec<Log>,
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+/// Contract function the transaction calls
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedCall {
+    pub function: String,
+    pub args: Vec<DecodedArg>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedArg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub value: serde_json::Value,
+}
+
+/// Gas settings the transaction was signed with, after fillers and fallbacks were applied
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasParams {
+    pub gas_limit: u128,
+    /// EIP-1559 transactions only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fee_per_gas: Option<u128>,
+    /// EIP-1559 transactions only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_priority_fee_per_gas: Option<u128>,
+    /// Legacy and EIP-2930 transactions only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_price: Option<u128>,
+}
+
+/// `gasUsed * effectiveGasPrice` of a mined transaction
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxCost {
+    pub wei: U256,
+    /// Cost in the native currency, e.g. `0.000021` ETH
+    pub formatted: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase", untagged)]
 pub enum FractionOrAmount {
     Fraction(FractionalAmount),
-    Amount { amount: U256 },
+    Amount {
+        #[serde(deserialize_with = "amount::deserialize")]
+        amount: U256,
+    },
+    /// Share of the balance in 1/10000ths, all basis points of a disperse sum to at most 10000
+    BasisPoints {
+        bps: u16,
+    },
+    /// Share of the balance relative to the other recipients' weights, disperses only
+    Weighted {
+        #[serde(deserialize_with = "amount::deserialize")]
+        weight: U256,
+    },
+    /// Human-denominated amount, e.g. `"1.5"` USDC
+    Decimal {
+        value: DecimalAmount,
+        /// Decimals of the token, read from the token (18 for ETH) if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        decimals: Option<u8>,
+    },
 }
 
-#[derive(Deserialize, Debug, Clone, Copy)]
+/// Non-negative decimal number, kept exact as `digits / 10^scale`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalAmount {
+    digits: U256,
+    /// Fractional digits, without trailing zeros
+    scale: u8,
+}
+
+impl DecimalAmount {
+    /// Amount in base units of a token with `decimals`, `None` if it has more fractional digits
+    /// than `decimals` or doesn't fit in 256 bits
+    pub fn to_base_units(self, decimals: u8) -> Option<U256> {
+        let shift = decimals.checked_sub(self.scale)?;
+
+        U256::from(10)
+            .checked_pow(U256::from(shift))?
+            .checked_mul(self.digits)
+    }
+
+    /// Whether the amount has more fractional digits than `decimals`
+    pub fn exceeds_precision(self, decimals: u8) -> bool {
+        self.scale > decimals
+    }
+}
+
+impl FromStr for DecimalAmount {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if integer.is_empty() || (s.contains('.') && fraction.is_empty()) {
+            return Err("expected digits on both sides of the decimal point");
+        }
+        if !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return Err("expected a non-negative decimal number");
+        }
+
+        // trailing zeros don't add precision, "1.50" fits a token with one decimal
+        let fraction = fraction.trim_end_matches('0');
+        let scale = u8::try_from(fraction.len()).map_err(|_| "too many fractional digits")?;
+        let digits = U256::from_str_radix(&format!("{integer}{fraction}"), 10)
+            .map_err(|_| "does not fit in 256 bits")?;
+
+        Ok(Self { digits, scale })
+    }
+}
+
+impl Display for DecimalAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let digits = format!("{:0>width$}", self.digits, width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+
+        match fraction {
+            "" => write!(f, "{integer}"),
+            fraction => write!(f, "{integer}.{fraction}"),
+        }
+    }
+}
+
+impl Serialize for DecimalAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DecimalAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = Cow::<str>::deserialize(deserializer)?;
+
+        s.parse()
+            .map_err(|reason| serde::de::Error::custom(format!("invalid decimal {s:?}: {reason}")))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 #[serde(rename_all = "camelCase")]
 pub struct FractionalAmount {
+    #[serde(alias = "numerator", deserialize_with = "amount::deserialize")]
     pub fraction: U256,
-    #[serde(default = "default_units")]
+    #[serde(
+        default = "default_units",
+        alias = "denominator",
+        deserialize_with = "amount::deserialize"
+    )]
     pub units: U256,
 }
 
@@ -42,76 +338,729 @@ fn default_units() -> U256 {
     U256::from(100)
 }
 
+impl FractionOrAmount {
+    /// Fraction of the balance the amount is, `None` for absolute amounts
+    pub fn fraction(&self) -> Option<FractionalAmount> {
+        match self {
+            Self::Fraction(f) => Some(*f),
+            Self::BasisPoints { bps } => Some(FractionalAmount::from_basis_points(*bps)),
+            Self::Amount { .. } | Self::Decimal { .. } | Self::Weighted { .. } => None,
+        }
+    }
+}
+
 impl FractionalAmount {
+    pub const BASIS_POINTS: u16 = 10_000;
+
+    pub fn from_basis_points(bps: u16) -> Self {
+        Self {
+            fraction: U256::from(bps),
+            units: U256::from(Self::BASIS_POINTS),
+        }
+    }
+
     /// Calculates `fraction * total / units`
     pub fn to_absolute(self, total: U256) -> Option<U256> {
         total.checked_mul(self.fraction)?.checked_div(self.units)
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectErc20Request {
     pub caller: Address,
-    pub recipient: Address,
+    #[serde(deserialize_with = "recipient::deserialize")]
+    pub recipient: Recipients<Address>,
+    pub token: Address,
+    pub spenders: BTreeMap<Address, CollectSpender>,
+    /// Split the collect into transactions of at most this many spenders
+    #[serde(default)]
+    pub chunk_size: Option<NonZeroUsize>,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+    /// Same as the `Idempotency-Key` header, which takes precedence. Not part of the request hash
+    #[serde(default, skip_serializing)]
+    pub idempotency_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectSpender {
+    #[serde(flatten)]
+    pub amount: FractionOrAmount,
+    /// Grants the contract allowance before the collect
+    pub permit: Option<PermitSignature>,
+}
+
+/// EIP-2612 permit signed by the spender for the contract
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermitSignature {
+    pub value: U256,
+    pub deadline: U256,
+    pub v: u8,
+    pub r: B256,
+    pub s: B256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectErc20TargetRequest {
+    pub caller: Address,
+    #[serde(deserialize_with = "recipient::deserialize")]
+    pub recipient: Recipients<Address>,
     pub token: Address,
-    pub spenders: BTreeMap<Address, FractionOrAmount>,
+    pub spenders: Vec<Address>,
+    #[serde(deserialize_with = "amount::deserialize")]
+    pub target_total: U256,
+    #[serde(default)]
+    pub order: CollectOrder,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+    /// Same as the `Idempotency-Key` header, which takes precedence. Not part of the request hash
+    #[serde(default, skip_serializing)]
+    pub idempotency_key: Option<String>,
+}
+
+/// Order in which spenders are drained by [`CollectErc20TargetRequest`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CollectOrder {
+    /// Order of the `spenders` list
+    #[default]
+    Input,
+    /// Largest available amount first
+    Available,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectChunksResponse {
+    pub chunks: Vec<ChunkResponse>,
+    pub skipped: Vec<SkippedEntry>,
+    /// Same as [`DisperseCollectResponse::spenders`], `collected` sums up all sent chunks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spenders: Option<BTreeMap<Address, SpenderBreakdown>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl BatchResponse for CollectChunksResponse {
+    fn tx_hashes(&self) -> Vec<B256> {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| chunk.tx.as_ref())
+            .map(|tx| tx.tx_hash)
+            .collect()
+    }
+}
+
+/// Disperse split into several transactions
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisperseChunksResponse {
+    pub chunks: Vec<ChunkResponse>,
+    /// Transfers of the chunks that were sent
+    pub transfers: BTreeMap<Address, U256>,
+    pub skipped: Vec<SkippedEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<FailedTransfer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_up: Option<TopUpResponse>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl BatchResponse for DisperseChunksResponse {
+    fn tx_hashes(&self) -> Vec<B256> {
+        self.chunks
+            .iter()
+            .filter_map(|chunk| chunk.tx.as_ref())
+            .map(|tx| tx.tx_hash)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkResponse {
+    /// Not present if the chunk failed or wasn't sent
+    #[serde(flatten)]
+    pub tx: Option<TransactionResponse>,
+    pub transfers: BTreeMap<Address, U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectErc20Response(pub DisperseCollectResponse);
 
-#[derive(Debug, Clone, Deserialize)]
+impl BatchResponse for CollectErc20Response {
+    fn tx_hashes(&self) -> Vec<B256> {
+        vec![self.0.tx.tx_hash]
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DisperseEthRequest {
-    pub recipients: BTreeMap<Address, FractionOrAmount>,
+    #[serde(deserialize_with = "recipient::deserialize_map")]
+    pub recipients: Recipients<BTreeMap<Address, FractionOrAmount>>,
+    /// Receives what's left of the balance after the other amounts, e.g. rounding dust of fractions
+    pub remainder_to: Option<Address>,
     pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+    /// Same as the `Idempotency-Key` header, which takes precedence. Not part of the request hash
+    #[serde(default, skip_serializing)]
+    pub idempotency_key: Option<String>,
+    /// Blobs attached to the disperse transaction
+    #[cfg(feature = "blobs")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<BlobFields>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DisperseEthResponse(pub DisperseCollectResponse);
 
-#[derive(Debug, Clone, Deserialize)]
+impl BatchResponse for DisperseEthResponse {
+    fn tx_hashes(&self) -> Vec<B256> {
+        vec![self.0.tx.tx_hash]
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DisperseErc20Request {
-    pub recipients: BTreeMap<Address, FractionOrAmount>,
+    #[serde(deserialize_with = "recipient::deserialize_map")]
+    pub recipients: Recipients<BTreeMap<Address, FractionOrAmount>>,
+    /// Receives what's left of the available tokens after the other amounts
+    pub remainder_to: Option<Address>,
     pub token: Address,
     pub spender: Address,
     pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+    /// Same as the `Idempotency-Key` header, which takes precedence. Not part of the request hash
+    #[serde(default, skip_serializing)]
+    pub idempotency_key: Option<String>,
+    /// Blobs attached to the disperse transaction
+    #[cfg(feature = "blobs")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob: Option<BlobFields>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DisperseErc20Response(pub DisperseCollectResponse);
 
+impl BatchResponse for DisperseErc20Response {
+    fn tx_hashes(&self) -> Vec<B256> {
+        vec![self.0.tx.tx_hash]
+    }
+}
+
+/// Disperse of `total` split equally among `recipients`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisperseEthEqualRequest {
+    #[serde(deserialize_with = "recipient::deserialize_vec")]
+    pub recipients: Recipients<Vec<Address>>,
+    pub total: FractionOrAmount,
+    /// Receives the remainder of the division, the first recipient if not set
+    pub remainder_to: Option<Address>,
+    pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+    /// Same as the `Idempotency-Key` header, which takes precedence
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
+/// ERC20 counterpart of [`DisperseEthEqualRequest`]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisperseErc20EqualRequest {
+    #[serde(deserialize_with = "recipient::deserialize_vec")]
+    pub recipients: Recipients<Vec<Address>>,
+    pub total: FractionOrAmount,
+    /// Receives the remainder of the division, the first recipient if not set
+    pub remainder_to: Option<Address>,
+    pub token: Address,
+    pub spender: Address,
+    pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+    /// Same as the `Idempotency-Key` header, which takes precedence
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct TransferRequest {
-    pub recipient: Address,
+    #[serde(deserialize_with = "recipient::deserialize")]
+    pub recipient: Recipients<Address>,
     pub value: FractionOrAmount,
     pub token: Option<Address>,
     pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    #[serde(rename = "chainId")]
+    pub chain_id: Option<u64>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApproveRequest {
     pub spender: Address,
     pub amount: FractionOrAmount,
     pub token: Address,
     pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    #[serde(rename = "chainId", skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsureApprovalResponse {
+    /// Allowance before the request
+    pub allowance: U256,
+    /// Only present if the allowance was too low and an approval was sent
+    #[serde(flatten)]
+    pub tx: Option<TransactionResponse>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovePreviewRequest {
+    pub token: Address,
+    /// Owner of the tokens
+    pub caller: Address,
+    /// Approved address, the disperse contract if not set
+    pub spender: Option<Address>,
+    /// Disperse the approval is meant for, its total is compared against the allowance
+    pub recipients: Option<BTreeMap<Address, FractionOrAmount>>,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovePreviewResponse {
+    pub spender: Address,
+    /// Current allowance of the spender
+    pub allowance: U256,
+    /// Total the disperse would transfer, only with `recipients`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<U256>,
+    /// Amount the allowance falls short of `required` by, zero if it already covers it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub additional_amount: Option<U256>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallerUsageResponse {
+    pub caller: Address,
+    /// Gas spent in the current budget window
+    pub spent_wei: U256,
+    pub transactions: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_wei: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_wei: Option<U256>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsQuery {
+    /// Aggregate over the last this many seconds, at most and by default `stats_window_secs`
+    pub window_secs: Option<u64>,
+}
+
+/// Aggregates over the transactions sent in a window
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsResponse {
+    pub window_secs: u64,
+    pub transactions: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// `succeeded / transactions`, not set without transactions
+    pub success_rate: Option<f64>,
+    /// Gas used by succeeded transactions
+    pub gas_used: u64,
+    /// Fees paid by succeeded transactions in wei
+    pub fees: U256,
+    /// Average time from sending a transaction until it was confirmed
+    pub average_confirmation_ms: Option<u64>,
+    /// Transactions per operation
+    pub operations: BTreeMap<&'static str, u64>,
+    /// Value moved by succeeded transactions per token
+    pub volume: Vec<TokenVolume>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenVolume {
+    /// Not set for ETH
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Address>,
+    pub amount: U256,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequiredBalanceRequest {
+    pub recipients: BTreeMap<Address, FractionOrAmount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequiredBalanceResponse {
+    /// Smallest spender balance the disperse succeeds with
+    pub required: U256,
+    /// Sum of the absolute amounts
+    pub fixed_total: U256,
+    /// Whether there are fractional amounts, the dispersed total then grows with the balance
+    pub scales_with_balance: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteRequest {
+    /// Operations in the order they would be sent
+    pub steps: Vec<QuoteStep>,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+}
+
+/// Planned operation, the body of the corresponding endpoint tagged with `operation`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "operation", rename_all = "camelCase")]
+pub enum QuoteStep {
+    Approve(ApproveRequest),
+    Transfer(TransferRequest),
+    DisperseEth(DisperseEthRequest),
+    DisperseErc20(DisperseErc20Request),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteResponse {
+    pub steps: Vec<StepQuote>,
+    /// Sum of the estimated gas of all steps
+    pub total_gas: u128,
+    /// `totalGas * maxFeePerGas`, the most the plan may cost in fees
+    pub max_cost: TxCost,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepQuote {
+    /// Whether the step is expected to succeed if the previous steps do
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas: Option<u128>,
+    /// ETH (or tokens for ERC20 steps) the sender must hold, not counting gas
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_balance: Option<U256>,
+    /// Allowance of the contract an ERC20 disperse needs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required_allowance: Option<U256>,
+    /// Earlier approve step this step relies on, the step is not simulated then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    /// One entry per check and chain
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessCheck {
+    /// `rpc` or `contract`
+    pub check: &'static str,
+    pub chain_id: u64,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Guardrails of this deployment, for validating requests before sending them
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesResponse {
+    pub endpoints: Vec<&'static str>,
+    /// Chain ids requests can be sent to
+    pub chains: Vec<u64>,
+    pub default_chain: u64,
+    pub limits: Limits,
+    pub simulate_only: bool,
+    /// Only tokens with verified bytecode are accepted
+    pub verified_tokens_only: bool,
+    pub contract_recipients: ContractRecipients,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Limits {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caller_gas_budget_wei: Option<U256>,
+    pub caller_budget_window_secs: u64,
+    pub max_recipients: usize,
+    pub max_body_bytes: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestnetFundRequest {
+    /// Caller whose signer is funded
+    pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestnetFundResponse {
+    pub signer: Address,
+    pub amount: U256,
+    /// Balance after the top-up, not known for faucets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance: Option<U256>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhichSignerRequest {
+    pub caller: Address,
+    /// Chain to run on, the default chain if not set
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhichSignerResponse {
+    pub signer: Address,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchOptions {
+    /// Process the batch in a background job instead of waiting for it
+    #[serde(default, rename = "async")]
+    pub run_async: bool,
+    /// Include a breakdown of how amounts were computed, per recipient for disperses and per spender for collects
+    #[serde(default)]
+    pub verbose: bool,
+    /// Build `transfers` from the emitted ERC20 `Transfer` events instead of the requested amounts
+    #[serde(default)]
+    pub transfers_from_events: bool,
+    /// Unix timestamp in seconds after which an unfinished background job is abandoned
+    pub deadline: Option<u64>,
+}
+
+/// Query parameters shared by all transaction endpoints
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxOptions {
+    /// Include [`TxCost`] in the response
+    #[serde(default)]
+    pub include_cost: bool,
+    /// Transfers only: include the caller's balance after the transfer, costs an extra RPC call
+    #[serde(default)]
+    pub include_balance: bool,
+    /// Include the [`DecodedCall`] in the response
+    #[serde(default)]
+    pub decode: bool,
+    /// Include the position of the transaction in its block
+    #[serde(default)]
+    pub include_index: bool,
+    /// Include the [`GasParams`] the transaction was signed with
+    #[serde(default)]
+    pub include_gas_params: bool,
+    /// Simulate the transaction with `eth_call` instead of broadcasting it, like `simulate_only` for one request
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Blocks the transaction must be buried under before responding, overrides `confirmations`
+    pub confirmations: Option<u64>,
+    /// EIP-1559 fee caps in wei replacing the estimated ones, both or neither must be set
+    pub max_fee_per_gas: Option<u64>,
+    pub max_priority_fee_per_gas: Option<u64>,
+    /// Respond with server-sent events, the hash is sent as soon as the transaction is broadcast
+    #[serde(default)]
+    pub stream: bool,
+    /// Receives the hash of every broadcast transaction, set by the handler when streaming
+    #[serde(skip)]
+    pub broadcast_sink: Option<UnboundedSender<B256>>,
+    /// Simulated transactions skip the `eth_call`, set when it depends on earlier simulated transactions
+    #[serde(skip)]
+    pub skip_simulation: bool,
+    /// Blobs of the request body, set by the handler
+    #[cfg(feature = "blobs")]
+    #[serde(skip)]
+    pub blob: Option<BlobFields>,
+}
+
+/// EIP-4844 fields passed through to the transaction. Blob data isn't readable by the EVM, the
+/// contract only sees the versioned hashes through `BLOBHASH` and has to verify the contents itself
+#[cfg(feature = "blobs")]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobFields {
+    /// Checked against the sidecar if both are set, enough on their own for simulations
+    #[serde(default)]
+    pub blob_versioned_hashes: Vec<B256>,
+    /// Estimated from the latest block if not set
+    pub max_fee_per_blob_gas: Option<u64>,
+    /// Blobs, commitments and proofs, required to broadcast the transaction
+    pub sidecar: Option<alloy::consensus::BlobTransactionSidecar>,
+}
+
+#[cfg(feature = "blobs")]
+impl std::fmt::Debug for BlobFields {
+    // a blob is 128 KiB, logging requests shouldn't print them
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlobFields")
+            .field("blob_versioned_hashes", &self.blob_versioned_hashes)
+            .field("max_fee_per_blob_gas", &self.max_fee_per_blob_gas)
+            .field("blobs", &self.sidecar.as_ref().map(|s| s.blobs.len()))
+            .finish()
+    }
+}
+
+/// First event of a streamed response
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BroadcastEvent {
+    pub tx_hash: B256,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobCreatedResponse {
+    pub job_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenResponse {
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+}
+
+/// Query parameters of `GET /balance`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceQuery {
+    pub address: Address,
+    /// ERC20 token, native ETH if not set
+    pub token: Option<Address>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceResponse {
+    pub balance: U256,
+}
+
+/// Query parameters of `GET /allowance`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowanceQuery {
+    pub owner: Address,
+    pub spender: Address,
+    pub token: Address,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowanceResponse {
+    pub allowance: U256,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionStatusResponse {
+    pub status: TransactionStatus,
+    /// Receipt fields, not set while the transaction is pending
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_used: Option<u64>,
+    /// Blocks mined on top of the transaction's block
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmations: Option<u64>,
+    /// Position of the transaction in its block, only with `include_index`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_index: Option<u64>,
+}
+
+/// Chain of a `GET` request, the default chain if not set
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainQuery {
+    pub chain_id: Option<u64>,
+}
+
+/// Query parameters of `GET /tx/:hash`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionStatusOptions {
+    #[serde(default)]
+    pub include_index: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionStatus {
+    /// No receipt yet, the transaction is unknown to the node or not mined
+    Pending,
+    Success,
+    Reverted,
 }
 
 #[derive(Serialize)]
 pub struct ErrorResponse<'a> {
     pub error: Cow<'a, str>,
+    /// Whether the same request may succeed if sent again
+    pub retryable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<&'a ApproveSuggestion>,
+    /// ETH top-up already sent to the recipients before the request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_up: Option<&'a TopUpResponse>,
+}
+
+/// Approval that would let a failed request succeed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApproveSuggestion {
+    pub additional_amount: U256,
+    /// Ready-to-use body for `POST /api/approve`
+    pub approve: ApproveRequest,
 }
 
 #[cfg(test)]
 mod tests {
     use alloy::primitives::U256;
 
-    use super::FractionalAmount;
+    use super::{
+        amount, CollectErc20TargetRequest, CollectSpender, DecimalAmount, FractionOrAmount,
+        FractionalAmount,
+    };
 
     #[test]
     fn should_calculate_correct_amount() {
@@ -122,4 +1071,239 @@ mod tests {
 
         assert_eq!(U256::from(11), f.to_absolute(U256::from(100)).unwrap());
     }
+
+    #[test]
+    fn should_accept_numerator_and_denominator_aliases() {
+        let f: FractionalAmount =
+            serde_json::from_str(r#"{ "fraction": "3", "units": "10" }"#).unwrap();
+        assert_eq!((f.fraction, f.units), (U256::from(3), U256::from(10)));
+
+        let f: FractionalAmount =
+            serde_json::from_str(r#"{ "numerator": "3", "denominator": "10" }"#).unwrap();
+        assert_eq!((f.fraction, f.units), (U256::from(3), U256::from(10)));
+
+        let amount: FractionOrAmount =
+            serde_json::from_str(r#"{ "numerator": "3", "denominator": "10" }"#).unwrap();
+        assert!(matches!(amount, FractionOrAmount::Fraction(f) if f.units == U256::from(10)));
+    }
+
+    #[test]
+    fn should_serialize_canonical_field_names() {
+        let f: FractionalAmount =
+            serde_json::from_str(r#"{ "numerator": "3", "denominator": "10" }"#).unwrap();
+        let json = serde_json::to_value(f).unwrap();
+
+        assert!(json.get("fraction").is_some() && json.get("units").is_some());
+    }
+
+    #[test]
+    fn should_parse_collect_spender_with_and_without_permit() {
+        let spender: CollectSpender = serde_json::from_str(r#"{ "amount": "500" }"#).unwrap();
+        assert!(
+            matches!(spender.amount, FractionOrAmount::Amount { amount } if amount == U256::from(500))
+        );
+        assert!(spender.permit.is_none());
+
+        let spender: CollectSpender = serde_json::from_str(
+            r#"{
+                "fraction": "3",
+                "units": "10",
+                "permit": {
+                    "value": "1000",
+                    "deadline": "1700000000",
+                    "v": 27,
+                    "r": "0x1111111111111111111111111111111111111111111111111111111111111111",
+                    "s": "0x2222222222222222222222222222222222222222222222222222222222222222"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert!(matches!(spender.amount, FractionOrAmount::Fraction(_)));
+        assert_eq!(spender.permit.unwrap().value, U256::from(1000));
+    }
+
+    fn parse_amount(amount: &str) -> Result<U256, serde_json::Error> {
+        let json = serde_json::json!({ "amount": amount });
+        serde_json::from_value::<FractionOrAmount>(json).map(|amount| match amount {
+            FractionOrAmount::Amount { amount } => amount,
+            _ => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn should_accept_amount_formats() {
+        let million = U256::from(1_000_000);
+
+        for amount in [
+            "1000000",
+            "0xf4240",
+            "0XF4240",
+            "1_000_000",
+            "0xf_42_40",
+            "1e6",
+            "1E6",
+            "1e+6",
+            "10e5",
+            "1.0e6",
+            "0.001e9",
+            ".1e7",
+            "1.000_000e6",
+        ] {
+            assert_eq!(million, parse_amount(amount).unwrap(), "{amount}");
+        }
+
+        assert_eq!(
+            U256::from(1_500_000_000_000_000_000u64),
+            parse_amount("1.5e18").unwrap()
+        );
+        assert_eq!(U256::ZERO, parse_amount("0").unwrap());
+        assert_eq!(U256::ZERO, parse_amount("0e100000").unwrap());
+        assert_eq!(U256::MAX, parse_amount(&U256::MAX.to_string()).unwrap());
+        assert_eq!(
+            U256::from(10).pow(U256::from(77)),
+            parse_amount("1e77").unwrap()
+        );
+    }
+
+    #[test]
+    fn should_accept_numeric_amounts() {
+        let amount: FractionOrAmount = serde_json::from_str(r#"{ "amount": 1000 }"#).unwrap();
+        assert!(
+            matches!(amount, FractionOrAmount::Amount { amount } if amount == U256::from(1000))
+        );
+
+        let f: FractionalAmount =
+            serde_json::from_str(r#"{ "fraction": 3, "units": 10 }"#).unwrap();
+        assert_eq!((f.fraction, f.units), (U256::from(3), U256::from(10)));
+
+        assert!(serde_json::from_str::<FractionalAmount>(r#"{ "fraction": 1.5 }"#).is_err());
+        assert!(serde_json::from_str::<FractionalAmount>(r#"{ "fraction": -1 }"#).is_err());
+    }
+
+    #[test]
+    fn should_reject_invalid_amounts() {
+        for amount in [
+            "",
+            " 1000",
+            "1000 ",
+            "-1000",
+            "+1000",
+            "10.5",
+            "1.5",
+            "1.25e1",
+            "1e-6",
+            "1e",
+            "e6",
+            ".e6",
+            "1e6.5",
+            "1.2.3e6",
+            "_1000",
+            "1000_",
+            "1__000",
+            "1_e6",
+            "1e_6",
+            "0x_ff",
+            "0xff_",
+            "0_x1",
+            "1.5e0",
+            "0x",
+            "0xg",
+            "0x-1",
+            "2e77",
+            "1e100000000000000000000",
+            "116e75",
+            "one",
+        ] {
+            assert!(
+                parse_amount(amount).is_err(),
+                "{amount:?} should be rejected"
+            );
+        }
+
+        let too_large = format!("{}0", U256::MAX);
+        assert!(parse_amount(&too_large).is_err());
+        assert!(parse_amount(&format!("0x1{}", "0".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn should_accept_only_plain_amounts_when_strict() {
+        let strict = |amount: &str| amount::with_parser(true, || parse_amount(amount));
+
+        assert_eq!(U256::from(1_000_000), strict("1000000").unwrap());
+        assert_eq!(U256::from(1_000_000), strict("0xf4240").unwrap());
+        assert_eq!(U256::MAX, strict(&U256::MAX.to_string()).unwrap());
+
+        for amount in [
+            "",
+            "_",
+            "1_000_000",
+            "0x_ff",
+            "0_x1",
+            "1e6",
+            "1.5e0",
+            "1e",
+            "10.5",
+            "-1",
+            "2e77",
+            "1e100000000000000000000",
+        ] {
+            assert!(strict(amount).is_err(), "{amount:?} should be rejected");
+        }
+        assert!(strict(&format!("{}0", U256::MAX)).is_err());
+
+        // the lenient parser is back once the strict one is done
+        assert_eq!(U256::from(1_000_000), parse_amount("1e6").unwrap());
+    }
+
+    #[test]
+    fn should_scale_decimal_amounts_without_losing_precision() {
+        let amount: FractionOrAmount =
+            serde_json::from_str(r#"{ "value": "1.5", "decimals": 6 }"#).unwrap();
+        let FractionOrAmount::Decimal { value, decimals } = amount else {
+            panic!("expected a decimal amount, got {amount:?}");
+        };
+
+        assert_eq!(decimals, Some(6));
+        assert_eq!(value.to_string(), "1.5");
+        assert_eq!(value.to_base_units(6), Some(U256::from(1_500_000)));
+        assert_eq!(value.to_base_units(1), Some(U256::from(15)));
+
+        let value: DecimalAmount = "1.50".parse().unwrap();
+        assert!(!value.exceeds_precision(1));
+        assert_eq!(value.to_string(), "1.5");
+
+        let value: DecimalAmount = "0.0000001".parse().unwrap();
+        assert!(value.exceeds_precision(6));
+        assert_eq!(value.to_base_units(6), None);
+        assert_eq!(value.to_string(), "0.0000001");
+
+        for value in ["", ".5", "1.", "-1", "1e6", "1,5", "0x10"] {
+            assert!(value.parse::<DecimalAmount>().is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn should_parse_fraction_and_target_in_any_format() {
+        let f: FractionalAmount =
+            serde_json::from_str(r#"{ "fraction": "2.5e1", "units": "1_000" }"#).unwrap();
+        assert_eq!((f.fraction, f.units), (U256::from(25), U256::from(1000)));
+
+        let f: FractionalAmount = serde_json::from_str(r#"{ "numerator": "1e1" }"#).unwrap();
+        assert_eq!((f.fraction, f.units), (U256::from(10), U256::from(100)));
+
+        let request: CollectErc20TargetRequest = serde_json::from_str(
+            r#"{
+                "caller": "0x0000000000000000000000000000000000000001",
+                "recipient": "0x0000000000000000000000000000000000000002",
+                "token": "0x0000000000000000000000000000000000000003",
+                "spenders": [],
+                "targetTotal": "2.5e18"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            U256::from(2_500_000_000_000_000_000u64),
+            request.target_total
+        );
+    }
 }
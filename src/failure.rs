@@ -0,0 +1,272 @@
+use alloy::{
+    primitives::{Address, B256, U256},
+    transports::TransportErrorKind,
+};
+use anyhow::anyhow;
+use axum::{
+    extract::{Request, State},
+    http::HeaderName,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+use crate::{
+    dto::{DecimalAmount, FractionalAmount},
+    routes::ApiError,
+    service::{AllowanceShortfall, DcError, InvalidFractionalAmountError},
+};
+
+pub static X_TEST_FAIL: HeaderName = HeaderName::from_static("x-test-fail");
+
+/// Fails requests carrying `X-Test-Fail: <kind>` with a [`DcError`] of that kind before they reach
+/// the handler, so clients can exercise their error handling without touching the chain.
+///
+/// Passes every request through unless `failure_injection` is enabled.
+pub async fn inject_failure(State(enabled): State<bool>, request: Request, next: Next) -> Response {
+    let Some(kind) = request.headers().get(&X_TEST_FAIL).filter(|_| enabled) else {
+        return next.run(request).await;
+    };
+    let kind = kind.to_str().unwrap_or_default();
+
+    let mut samples = samples();
+    let Some(position) = samples.iter().position(|e| e.kind() == kind) else {
+        let kinds: Vec<_> = samples.iter().map(DcError::kind).collect();
+        return ApiError::InvalidRequest(format!(
+            "unknown X-Test-Fail error {kind:?}, expected one of: {}",
+            kinds.join(", ")
+        ))
+        .into_response();
+    };
+
+    warn!("Injecting {kind} failure requested by X-Test-Fail");
+
+    ApiError::from(samples.swap_remove(position)).into_response()
+}
+
+/// One error of every kind, filled with placeholder values
+fn samples() -> Vec<DcError> {
+    vec![
+        DcError::InsufficientFunds {
+            required: U256::from(2),
+            available: U256::from(1),
+            address: Address::ZERO,
+        },
+        DcError::InsufficientAllowance(Box::new(AllowanceShortfall {
+            owner: Address::ZERO,
+            spender: Address::ZERO,
+            token: Address::ZERO,
+            required: U256::from(2),
+            allowance: U256::from(1),
+            chain_id: 0,
+        })),
+        DcError::RelayedInsufficientAllowance {
+            owner: Address::ZERO,
+            caller: Address::ZERO,
+            required: U256::from(2),
+            allowance: U256::from(1),
+        },
+        DcError::RelayedDisperse {
+            caller: Address::ZERO,
+            spender: Address::ZERO,
+        },
+        DcError::AllowanceChanged {
+            address: Address::ZERO,
+            required: U256::from(2),
+            allowance: U256::from(1),
+        },
+        DcError::TargetNotReached {
+            target: U256::from(2),
+            available: U256::from(1),
+        },
+        DcError::InvalidPermit {
+            owner: Address::ZERO,
+            reason: "injected failure".into(),
+        },
+        DcError::InvalidFractionalAmount(InvalidFractionalAmountError(FractionalAmount {
+            fraction: U256::from(2),
+            units: U256::from(1),
+        })),
+        DcError::InvalidDecimalAmount {
+            value: "0.5".parse::<DecimalAmount>().unwrap(),
+            decimals: 0,
+        },
+        DcError::MissingDecimals("0.5".parse::<DecimalAmount>().unwrap()),
+        DcError::BasisPointsExceeded(10_001),
+        DcError::ZeroTotalWeight,
+        DcError::UnexpectedWeight,
+        DcError::TokenNotFound(Address::ZERO),
+        DcError::Transport(TransportErrorKind::BackendGone),
+        DcError::unexpected(anyhow!("injected failure")),
+        DcError::SignerNotFound(Address::ZERO),
+        DcError::SpenderSignersNotFound(vec![Address::ZERO]),
+        DcError::CallerIsContract(Address::ZERO),
+        DcError::GasBudgetExceeded {
+            caller: Address::ZERO,
+            spent: U256::from(2),
+            budget: U256::from(1),
+        },
+        DcError::ContractRecipient(Address::ZERO),
+        DcError::UnknownChain(0),
+        DcError::ZeroAddressRecipient,
+        DcError::UnchecksummedRecipient("injected failure".into()),
+        DcError::NoRecipients,
+        DcError::NoSpenders,
+        DcError::TooManyRecipients {
+            field: "recipients",
+            count: 2,
+            max: 1,
+        },
+        DcError::DeniedRecipient(Address::ZERO),
+        DcError::NewContract {
+            address: Address::ZERO,
+            window: 0,
+        },
+        DcError::SimulationReverted("injected failure".into()),
+        DcError::UnverifiedToken {
+            token: Address::ZERO,
+            code_hash: B256::ZERO,
+        },
+        DcError::ExceedsTotalSupply {
+            token: Address::ZERO,
+            amount: U256::from(2),
+            total_supply: U256::from(1),
+        },
+        DcError::BalanceUnreachable,
+        DcError::AmountOverflow,
+        DcError::ZeroAmount(Address::ZERO),
+        DcError::PartialSplitRemainder,
+        DcError::InvalidFeeOverride("injected failure"),
+        DcError::InvalidBlobFields("injected failure"),
+        DcError::TransactionReverted {
+            tx_hash: B256::ZERO,
+            reason: Some("injected failure".into()),
+        },
+        DcError::ConfirmationTimeout {
+            tx_hash: B256::ZERO,
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use axum::{
+        body::Body, extract::Request, http::StatusCode, middleware::from_fn_with_state,
+        response::Response, routing::get, Router,
+    };
+    use tower::ServiceExt;
+
+    use super::{inject_failure, samples, X_TEST_FAIL};
+    use crate::service::DcError;
+
+    async fn call(enabled: bool, kind: Option<&str>) -> Response {
+        let app = Router::new()
+            .route("/", get(|| async { "handled" }))
+            .layer(from_fn_with_state(enabled, inject_failure));
+
+        let mut request = Request::get("/");
+        if let Some(kind) = kind {
+            request = request.header(&X_TEST_FAIL, kind);
+        }
+
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn should_have_a_sample_of_every_kind() {
+        // exhaustive on purpose, a new variant has to be listed here and then fails the test
+        // until it has a sample
+        let variant = |e: &DcError| match e {
+            DcError::InsufficientFunds { .. } => 0,
+            DcError::InsufficientAllowance(_) => 1,
+            DcError::RelayedInsufficientAllowance { .. } => 2,
+            DcError::RelayedDisperse { .. } => 3,
+            DcError::AllowanceChanged { .. } => 4,
+            DcError::TargetNotReached { .. } => 5,
+            DcError::InvalidPermit { .. } => 6,
+            DcError::InvalidFractionalAmount(_) => 7,
+            DcError::TokenNotFound(_) => 8,
+            DcError::Transport(_) => 9,
+            DcError::Unexpected(_) => 10,
+            DcError::SignerNotFound(_) => 11,
+            DcError::InvalidDecimalAmount { .. } => 12,
+            DcError::MissingDecimals(_) => 13,
+            DcError::BasisPointsExceeded(_) => 14,
+            DcError::ZeroTotalWeight => 15,
+            DcError::UnexpectedWeight => 16,
+            DcError::SpenderSignersNotFound(_) => 17,
+            DcError::CallerIsContract(_) => 18,
+            DcError::GasBudgetExceeded { .. } => 19,
+            DcError::ContractRecipient(_) => 20,
+            DcError::UnknownChain(_) => 21,
+            DcError::ZeroAddressRecipient => 22,
+            DcError::UnchecksummedRecipient(_) => 23,
+            DcError::NoRecipients => 24,
+            DcError::NoSpenders => 25,
+            DcError::TooManyRecipients { .. } => 26,
+            DcError::DeniedRecipient(_) => 27,
+            DcError::NewContract { .. } => 28,
+            DcError::SimulationReverted(_) => 29,
+            DcError::UnverifiedToken { .. } => 30,
+            DcError::ExceedsTotalSupply { .. } => 31,
+            DcError::BalanceUnreachable => 32,
+            DcError::AmountOverflow => 33,
+            DcError::ZeroAmount(_) => 34,
+            DcError::PartialSplitRemainder => 35,
+            DcError::InvalidFeeOverride(_) => 36,
+            DcError::InvalidBlobFields(_) => 37,
+            DcError::TransactionReverted { .. } => 38,
+            DcError::ConfirmationTimeout { .. } => 39,
+            DcError::ToppedUp { .. } => unreachable!("a top-up reports the kind of its source"),
+        };
+
+        let samples = samples();
+        let covered: BTreeSet<_> = samples.iter().map(variant).collect();
+        assert_eq!(covered, (0..40).collect());
+
+        let kinds: BTreeSet<_> = samples.iter().map(DcError::kind).collect();
+        assert_eq!(kinds.len(), samples.len());
+    }
+
+    #[tokio::test]
+    async fn should_fail_with_the_requested_kind() {
+        let response = call(true, Some("denied_recipient")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = call(true, Some("insufficient_funds")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = call(true, Some("transport")).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let response = call(true, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn should_reject_an_unknown_kind() {
+        let response = call(true, Some("no_such_kind")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("unknown X-Test-Fail error \"no_such_kind\""));
+    }
+
+    #[tokio::test]
+    async fn should_pass_requests_through_when_disabled() {
+        for kind in [Some("denied_recipient"), Some("no_such_kind"), None] {
+            let response = call(false, kind).await;
+            assert_eq!(response.status(), StatusCode::OK, "{kind:?}");
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use alloy::primitives::B256;
+use axum::http::StatusCode;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Pending,
+    Completed,
+    Failed,
+    /// Deadline passed before the operation finished, it is no longer tracked
+    Abandoned,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<B256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub job_id: Uuid,
+    pub status: JobStatus,
+    pub completed: usize,
+    pub total: usize,
+    pub items: Vec<JobItem>,
+    /// Final response of the operation, same as the synchronous one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    /// Status of the error the job failed with, replayed to repeated requests
+    #[serde(skip)]
+    pub error_status: Option<StatusCode>,
+}
+
+/// Why [`JobStore::create_keyed`] didn't create a job
+#[derive(Debug)]
+pub enum KeyConflict {
+    /// The job already created for the key
+    Existing(Job),
+    /// The key was used for a request with a different hash
+    Mismatch,
+}
+
+struct JobEntry {
+    job: Job,
+    /// `Idempotency-Key` and request hash of the request that started the job
+    key: Option<(String, B256)>,
+    created_at: Instant,
+    updated_at: Instant,
+}
+
+/// In-memory store of background jobs. Entries expire `ttl` after their last update.
+#[derive(Clone)]
+pub struct JobStore {
+    jobs: Arc<RwLock<HashMap<Uuid, JobEntry>>>,
+    ttl: Duration,
+}
+
+impl JobStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            jobs: Default::default(),
+            ttl,
+        }
+    }
+
+    pub fn create(&self, total: usize) -> Uuid {
+        let mut jobs = self.jobs.write().unwrap_or_else(|e| e.into_inner());
+        jobs.retain(|_, e| e.updated_at.elapsed() < self.ttl);
+
+        Self::insert(&mut jobs, None, total)
+    }
+
+    /// Creates a job for `key`, or returns the job already created for it if it was created for
+    /// the same `request_hash`. With a `window`, jobs created longer ago than that are ignored
+    pub fn create_keyed(
+        &self,
+        key: String,
+        request_hash: B256,
+        total: usize,
+        window: Option<Duration>,
+    ) -> Result<Uuid, KeyConflict> {
+        let mut jobs = self.jobs.write().unwrap_or_else(|e| e.into_inner());
+        jobs.retain(|_, e| e.updated_at.elapsed() < self.ttl);
+
+        let existing = jobs.values().find_map(|e| {
+            e.key
+                .as_ref()
+                .filter(|(existing, _)| *existing == key)
+                .filter(|_| window.is_none_or(|window| e.created_at.elapsed() < window))
+                .map(|(_, hash)| (e, *hash))
+        });
+
+        match existing {
+            Some((entry, hash)) if hash == request_hash => {
+                Err(KeyConflict::Existing(entry.job.clone()))
+            }
+            Some(_) => Err(KeyConflict::Mismatch),
+            None => Ok(Self::insert(&mut jobs, Some((key, request_hash)), total)),
+        }
+    }
+
+    fn insert(
+        jobs: &mut HashMap<Uuid, JobEntry>,
+        key: Option<(String, B256)>,
+        total: usize,
+    ) -> Uuid {
+        let job_id = Uuid::new_v4();
+        let job = Job {
+            job_id,
+            status: JobStatus::Pending,
+            completed: 0,
+            total,
+            items: Vec::with_capacity(total),
+            result: None,
+            error_status: None,
+        };
+
+        jobs.insert(
+            job_id,
+            JobEntry {
+                job,
+                key,
+                created_at: Instant::now(),
+                updated_at: Instant::now(),
+            },
+        );
+
+        job_id
+    }
+
+    pub fn get(&self, job_id: &Uuid) -> Option<Job> {
+        self.jobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(job_id)
+            .filter(|e| e.updated_at.elapsed() < self.ttl)
+            .map(|e| e.job.clone())
+    }
+
+    pub fn update(&self, job_id: &Uuid, f: impl FnOnce(&mut Job)) {
+        let mut jobs = self.jobs.write().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(entry) = jobs.get_mut(job_id) {
+            f(&mut entry.job);
+            entry.updated_at = Instant::now();
+        }
+    }
+}
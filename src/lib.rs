@@ -1,37 +1,173 @@
-use axum::Router;
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware::{from_fn, from_fn_with_state, map_response},
+    routing::get,
+    Router,
+};
 use routes::api_routes;
 use state::AppState;
-use tokio::net::TcpListener;
-use tower_http::trace::TraceLayer;
-use tracing::info;
+use tokio::{net::TcpListener, sync::Notify};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::{info, warn};
 
-use std::{future::Future, net::SocketAddr};
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
 
+mod amount;
 mod config;
 mod contracts;
+mod decode;
 mod dto;
+mod failure;
+mod jobs;
+mod metrics;
+mod middleware;
+mod nonce;
+mod notify;
+mod recipient;
+mod redact;
 mod routes;
 mod service;
 mod state;
+mod stats;
+mod templates;
+#[cfg(test)]
+mod testing;
+mod transport;
+mod usage;
 
 pub use config::AppConfig;
 
 pub async fn run(config: AppConfig) -> anyhow::Result<impl Future<Output = anyhow::Result<()>>> {
     let port = config.port;
+    let shutdown_grace = Duration::from_secs(config.shutdown_grace_secs);
+    let expose_rpc_errors = config.expose_rpc_errors;
+    let response_envelope = config.response_envelope;
+    let failure_injection = config.failure_injection;
+    let max_body_bytes = config.max_body_bytes;
+    let request_timeout = config.request_timeout_secs.map(Duration::from_secs);
+    let redact_logs = config.redact_logs;
 
-    let state = AppState::init(config)?;
-    let app = Router::new()
-        .nest("/api", api_routes(state))
-        .layer(TraceLayer::new_for_http());
+    let state = AppState::init(config).await?;
+
+    if state.config().warm_up {
+        service::warm_up(&state).await;
+    }
+
+    for chain_id in state.chain_ids() {
+        let chain = state.on_chain(Some(chain_id))?;
+        service::recover_pending(&chain).await;
+    }
+
+    let mut api = api_routes(state.clone()).layer(DefaultBodyLimit::max(max_body_bytes));
+
+    if failure_injection {
+        warn!(
+            "FAILURE INJECTION IS ENABLED: requests with an X-Test-Fail header fail on purpose, \
+            do not use in production"
+        );
+    }
+    api = api.layer(from_fn_with_state(
+        failure_injection,
+        failure::inject_failure,
+    ));
+
+    if let Some(timeout) = request_timeout {
+        api = api.layer(from_fn_with_state(timeout, middleware::enforce_deadline));
+    }
+
+    api = api.layer(from_fn_with_state(state, middleware::handle_failure));
+
+    let mut app = Router::new()
+        .nest("/api", api)
+        .route("/metrics", get(routes::handle_metrics));
+
+    if expose_rpc_errors {
+        warn!("Raw RPC errors are exposed in the X-RPC-Error header, do not use in production");
+        app = app.layer(map_response(middleware::expose_rpc_error));
+    }
+
+    if response_envelope {
+        app = app.layer(from_fn(middleware::wrap_in_envelope));
+    }
+
+    let app = app
+        .layer(PropagateRequestIdLayer::new(
+            middleware::X_REQUEST_ID.clone(),
+        ))
+        .layer(
+            TraceLayer::new_for_http().make_span_with(middleware::make_request_span(redact_logs)),
+        )
+        .layer(SetRequestIdLayer::new(
+            middleware::X_REQUEST_ID.clone(),
+            MakeRequestUuid,
+        ));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     info!("Listening on {}", addr);
 
     let app = async move {
-        axum::serve(TcpListener::bind(addr).await?, app.into_make_service())
-            .await
-            .map_err(Into::into)
+        let shutting_down = Arc::new(Notify::new());
+
+        let signal = {
+            let shutting_down = shutting_down.clone();
+            async move {
+                let reason = shutdown_signal().await;
+                info!(
+                    "Received {reason}, draining in-flight requests for up to {}s",
+                    shutdown_grace.as_secs()
+                );
+                shutting_down.notify_one();
+            }
+        };
+
+        let server = axum::serve(TcpListener::bind(addr).await?, app.into_make_service())
+            .with_graceful_shutdown(signal);
+
+        tokio::select! {
+            result = server => result.map_err(Into::into),
+            _ = async {
+                shutting_down.notified().await;
+                tokio::time::sleep(shutdown_grace).await;
+            } => {
+                warn!("Shutdown grace period elapsed, dropping in-flight requests");
+                Ok(())
+            }
+        }
     };
 
     Ok(app)
 }
+
+/// Resolves with the name of the first shutdown signal received
+async fn shutdown_signal() -> &'static str {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for SIGINT: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => "SIGINT",
+        _ = terminate => "SIGTERM",
+    }
+}
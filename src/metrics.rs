@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    future::Future,
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+use crate::service::DcError;
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const OPERATION_BUCKETS: &[f64] = &[
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0,
+];
+const GAS_BUCKETS: &[f64] = &[
+    21_000.0,
+    50_000.0,
+    100_000.0,
+    250_000.0,
+    500_000.0,
+    1_000_000.0,
+    2_500_000.0,
+    5_000_000.0,
+    10_000_000.0,
+    30_000_000.0,
+];
+
+pub const HTTP_REQUESTS: Counter = Counter {
+    name: "http_requests_total",
+    help: "API requests by route and response status",
+};
+pub const TRANSACTIONS: Counter = Counter {
+    name: "transactions_total",
+    help: "Submitted transactions by operation and result, failures are labeled with the error",
+};
+pub const TRANSACTION_GAS_USED: Histogram = Histogram {
+    name: "transaction_gas_used",
+    help: "Gas used by mined transactions",
+    buckets: GAS_BUCKETS,
+};
+pub const RPC_DURATION: Histogram = Histogram {
+    name: "rpc_request_duration_seconds",
+    help: "Latency of JSON-RPC requests to the node by method",
+    buckets: LATENCY_BUCKETS,
+};
+pub const OPERATION_DURATION: Histogram = Histogram {
+    name: "operation_duration_seconds",
+    help: "End-to-end duration of disperse, collect, transfer and approve operations",
+    buckets: OPERATION_BUCKETS,
+};
+
+type Labels = Vec<(&'static str, String)>;
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    buckets: &'static [f64],
+}
+
+impl Counter {
+    pub fn increment(&self, labels: &[(&'static str, &str)]) {
+        registry().with_series(self.name, self.help, None, labels, |series| {
+            series.count += 1;
+        });
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, labels: &[(&'static str, &str)], value: f64) {
+        let buckets = self.buckets;
+        registry().with_series(self.name, self.help, Some(buckets), labels, |series| {
+            series.count += 1;
+            series.sum += value;
+            series.buckets.resize(buckets.len(), 0);
+            for (count, _) in series
+                .buckets
+                .iter_mut()
+                .zip(buckets)
+                .filter(|(_, le)| value <= **le)
+            {
+                *count += 1;
+            }
+        });
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    count: u64,
+    sum: f64,
+    /// Cumulative counts per histogram bucket, empty for counters
+    buckets: Vec<u64>,
+}
+
+struct Family {
+    help: &'static str,
+    /// Bucket bounds of histograms, counters have none
+    buckets: Option<&'static [f64]>,
+    series: BTreeMap<Labels, Series>,
+}
+
+#[derive(Default)]
+struct Registry(Mutex<BTreeMap<&'static str, Family>>);
+
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+impl Registry {
+    fn with_series(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        buckets: Option<&'static [f64]>,
+        labels: &[(&'static str, &str)],
+        update: impl FnOnce(&mut Series),
+    ) {
+        let labels = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+        let mut families = self.0.lock().expect("metrics lock is never poisoned");
+        let family = families.entry(name).or_insert_with(|| Family {
+            help,
+            buckets,
+            series: BTreeMap::new(),
+        });
+
+        update(family.series.entry(labels).or_default());
+    }
+}
+
+/// Records the duration and result of `operation` in [`OPERATION_DURATION`]
+pub async fn timed<T>(
+    operation: &'static str,
+    future: impl Future<Output = Result<T, DcError>>,
+) -> Result<T, DcError> {
+    let started = Instant::now();
+    let result = future.await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(e) => e.kind(),
+    };
+
+    OPERATION_DURATION.record(
+        &[("operation", operation), ("result", outcome)],
+        started.elapsed().as_secs_f64(),
+    );
+
+    result
+}
+
+/// Renders all recorded metrics in the Prometheus text exposition format
+pub fn render() -> String {
+    let families = registry().0.lock().expect("metrics lock is never poisoned");
+    let mut out = String::new();
+
+    for (name, family) in families.iter() {
+        let kind = if family.buckets.is_some() {
+            "histogram"
+        } else {
+            "counter"
+        };
+        let _ = writeln!(out, "# HELP {name} {}", family.help);
+        let _ = writeln!(out, "# TYPE {name} {kind}");
+
+        for (labels, series) in &family.series {
+            let Some(buckets) = family.buckets else {
+                let _ = writeln!(
+                    out,
+                    "{name}{} {}",
+                    format_labels(labels, None),
+                    series.count
+                );
+                continue;
+            };
+
+            for (le, count) in buckets.iter().zip(&series.buckets) {
+                let le = le.to_string();
+                let labels = format_labels(labels, Some(&le));
+                let _ = writeln!(out, "{name}_bucket{labels} {count}");
+            }
+            let labels_inf = format_labels(labels, Some("+Inf"));
+            let labels = format_labels(labels, None);
+            let _ = writeln!(out, "{name}_bucket{labels_inf} {}", series.count);
+            let _ = writeln!(out, "{name}_sum{labels} {}", series.sum);
+            let _ = writeln!(out, "{name}_count{labels} {}", series.count);
+        }
+    }
+
+    out
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let pairs: Vec<_> = labels
+        .iter()
+        .map(|(k, v)| (*k, v.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+        .collect();
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,191 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body,
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tower_http::request_id::RequestId;
+use tracing::{debug_span, error, Span};
+
+use crate::{metrics, redact::Redacted, routes::ApiError, state::AppState};
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// JSON-RPC error returned by the node, attached to error responses as an extension
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcErrorDetails {
+    pub code: i64,
+    pub message: String,
+}
+
+/// Copies [`RpcErrorDetails`] into the `X-RPC-Error` header.
+///
+/// Leaks internal details of the node communication, intended for debugging only.
+pub async fn expose_rpc_error(mut response: Response) -> Response {
+    let Some(details) = response.extensions().get::<RpcErrorDetails>() else {
+        return response;
+    };
+
+    let header = format!("{}: {}", details.code, details.message);
+
+    if let Ok(value) = HeaderValue::from_str(&header) {
+        response.headers_mut().insert("x-rpc-error", value);
+    }
+
+    response
+}
+
+/// Span of a single request, tagged with its `X-Request-Id` for correlation
+pub fn make_request_span(redact_logs: bool) -> impl Fn(&Request) -> Span + Clone {
+    move |request| {
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .and_then(|id| id.header_value().to_str().ok())
+            .unwrap_or_default();
+
+        debug_span!(
+            "request",
+            method = %request.method(),
+            uri = %Redacted::new(request.uri(), redact_logs),
+            request_id,
+        )
+    }
+}
+
+/// Logs the [`ApiError`] a request failed with, hashed if `redact_logs` is set, and renders its message
+/// with the configured `error_templates`
+pub async fn handle_failure(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+
+    let Some(e) = response.extensions().get::<Arc<ApiError>>().cloned() else {
+        return response;
+    };
+
+    error!(
+        "Request failed with status {}: {:?}",
+        response.status(),
+        state.redacted(&e)
+    );
+    *response.body_mut() = Json(e.body(&state.config().error_templates))
+        .into_response()
+        .into_body();
+
+    response
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    data: Option<Value>,
+    error: Option<Value>,
+    meta: EnvelopeMeta,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EnvelopeMeta {
+    request_id: Option<String>,
+    /// Unix timestamp in milliseconds
+    timestamp: u128,
+}
+
+/// Fails requests that aren't handled within `timeout` with `504`, dropping the handler.
+///
+/// Transactions already broadcast are not affected and may still be mined.
+pub async fn enforce_deadline(
+    State(timeout): State<Duration>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            ApiError::Timeout(format!("request not handled within {timeout:?}")).into_response()
+        }
+    }
+}
+
+/// Counts requests in [`metrics::HTTP_REQUESTS`] by matched route, so path parameters don't add series
+pub async fn count_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_default();
+
+    let response = next.run(request).await;
+    metrics::HTTP_REQUESTS.increment(&[("route", &route), ("status", response.status().as_str())]);
+
+    response
+}
+
+/// Wraps responses into `{ "data": ..., "error": ..., "meta": ... }`, non-JSON successes are passed through
+pub async fn wrap_in_envelope(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|id| id.to_str().ok())
+        .map(Into::into);
+
+    let response = next.run(request).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+
+    // streams and other non-JSON successes are passed through as is
+    if !is_json && response.status().is_success() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+
+    let payload = match body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) if is_json => serde_json::from_slice::<Value>(&bytes).ok(),
+        // plain text rejections of axum extractors
+        Ok(bytes) => Some(serde_json::json!({
+            "error": String::from_utf8_lossy(&bytes),
+            "retryable": false,
+        })),
+        Err(_) => None,
+    };
+
+    let (data, error) = if parts.status.is_success() {
+        (payload, None)
+    } else {
+        (None, payload)
+    };
+
+    let envelope = Envelope {
+        data,
+        error,
+        meta: EnvelopeMeta {
+            request_id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+        },
+    };
+
+    // headers of the original body no longer apply
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::CONTENT_TYPE);
+
+    (parts, Json(envelope)).into_response()
+}
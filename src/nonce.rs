@@ -0,0 +1,70 @@
+use std::{collections::HashMap, sync::Arc};
+
+use alloy::{
+    primitives::Address,
+    providers::Provider,
+    rpc::types::BlockId,
+    transports::{RpcError, TransportErrorKind, TransportResult},
+};
+use tokio::sync::Mutex;
+
+use crate::state::DefaultProvider;
+
+/// Nonces of the local signers. Fetched from the node on first use and after a resync.
+#[derive(Clone, Default)]
+pub struct NonceManager {
+    nonces: Arc<Mutex<HashMap<Address, u64>>>,
+}
+
+impl NonceManager {
+    /// Reserves the next nonce of `address`
+    pub async fn next(&self, provider: &DefaultProvider, address: Address) -> TransportResult<u64> {
+        let mut nonces = self.nonces.lock().await;
+
+        let nonce = match nonces.get(&address) {
+            Some(nonce) => *nonce,
+            None => {
+                provider
+                    .get_transaction_count(address)
+                    .block_id(BlockId::pending())
+                    .await?
+            }
+        };
+
+        nonces.insert(address, nonce + 1);
+
+        Ok(nonce)
+    }
+
+    /// Forgets the nonce of `address`, the next one is fetched from the node again
+    pub async fn resync(&self, address: Address) {
+        self.nonces.lock().await.remove(&address);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceError {
+    /// The same transaction is already in the node's pool
+    AlreadyKnown,
+    /// The nonce was already used by a mined transaction
+    TooLow,
+    /// There is a gap before the nonce, the transaction won't be included
+    TooHigh,
+}
+
+impl NonceError {
+    /// Classifies a send error by the node's message, the wording is not standardized across clients
+    pub fn from_rpc_error(e: &RpcError<TransportErrorKind>) -> Option<Self> {
+        let message = e.as_error_resp()?.message.to_lowercase();
+
+        if message.contains("already known") || message.contains("known transaction") {
+            Some(Self::AlreadyKnown)
+        } else if message.contains("nonce too low") {
+            Some(Self::TooLow)
+        } else if message.contains("nonce too high") {
+            Some(Self::TooHigh)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use alloy::primitives::{Address, B256};
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisError};
+use serde::Serialize;
+use tokio::sync::OnceCell;
+use tracing::warn;
+
+use crate::{
+    dto::TransactionResponse,
+    redact::Redacted,
+    service::{DcError, Operation},
+};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus {
+    Success,
+    Simulated,
+    Failed,
+}
+
+/// Message published for every transaction the service sends
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionEvent {
+    pub operation: Operation,
+    pub caller: Address,
+    pub tx_hash: Option<B256>,
+    pub status: TransactionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TransactionEvent {
+    pub fn new(
+        operation: Operation,
+        caller: Address,
+        result: &Result<TransactionResponse, DcError>,
+    ) -> Self {
+        let (tx_hash, status, error) = match result {
+            Ok(r) if r.simulated => (None, TransactionStatus::Simulated, None),
+            Ok(r) => (Some(r.tx_hash), TransactionStatus::Success, None),
+            Err(e) => (None, TransactionStatus::Failed, Some(e.to_string())),
+        };
+
+        Self {
+            operation,
+            caller,
+            tx_hash,
+            status,
+            error,
+        }
+    }
+}
+
+/// Publishes [`TransactionEvent`]s to a Redis pub/sub channel
+pub struct Notifier {
+    client: Client,
+    channel: String,
+    connection: OnceCell<ConnectionManager>,
+    redact_logs: bool,
+}
+
+impl Notifier {
+    pub fn new(client: Client, channel: String, redact_logs: bool) -> Self {
+        Self {
+            client,
+            channel,
+            connection: OnceCell::new(),
+            redact_logs,
+        }
+    }
+
+    /// Publishes in the background, failures are only logged
+    pub fn publish(self: &Arc<Self>, event: TransactionEvent) {
+        let notifier = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = notifier.try_publish(&event).await {
+                warn!(
+                    "Failed to publish transaction event {:?}: {e}",
+                    Redacted::new(&event, notifier.redact_logs)
+                );
+            }
+        });
+    }
+
+    async fn try_publish(&self, event: &TransactionEvent) -> Result<(), RedisError> {
+        let mut connection = self
+            .connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await?
+            .clone();
+
+        let message = serde_json::to_string(event).expect("event is serializable");
+
+        connection.publish(&self.channel, message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{address, Address, B256};
+    use serde_json::json;
+
+    use super::{TransactionEvent, TransactionStatus};
+    use crate::service::{DcError, Operation};
+
+    const CALLER: Address = address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+    const TOKEN: Address = address!("5FbDB2315678afecb367f032d93F642f64180aa3");
+
+    #[test]
+    fn should_publish_failures_with_their_error() {
+        let error = DcError::TokenNotFound(TOKEN);
+        let message = error.to_string();
+
+        let event = TransactionEvent::new(Operation::DisperseErc20, CALLER, &Err(error));
+
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            json!({
+                "operation": "disperseErc20",
+                "caller": CALLER,
+                "txHash": null,
+                "status": "failed",
+                "error": message,
+            })
+        );
+    }
+
+    #[test]
+    fn should_leave_the_error_out_of_successful_events() {
+        let event = TransactionEvent {
+            operation: Operation::DisperseEth,
+            caller: CALLER,
+            tx_hash: Some(B256::repeat_byte(1)),
+            status: TransactionStatus::Success,
+            error: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            json!({
+                "operation": "disperseEth",
+                "caller": CALLER,
+                "txHash": B256::repeat_byte(1),
+                "status": "success",
+            })
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
+
+use alloy::primitives::Address;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Recipients of a request, remembering the first address that was sent without a valid EIP-55 checksum.
+///
+/// Recipients built by the service itself count as checksummed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recipients<T> {
+    value: T,
+    unchecksummed: Option<String>,
+}
+
+impl<T> Recipients<T> {
+    /// First address that wasn't sent with a valid EIP-55 checksum, as sent
+    pub fn unchecksummed(&self) -> Option<&str> {
+        self.unchecksummed.as_deref()
+    }
+
+    /// `value` in place of the recipients, keeping track of the unchecksummed address
+    pub fn replace<U>(self, value: U) -> Recipients<U> {
+        Recipients {
+            value,
+            unchecksummed: self.unchecksummed,
+        }
+    }
+}
+
+impl<T> From<T> for Recipients<T> {
+    fn from(value: T) -> Self {
+        Self {
+            value,
+            unchecksummed: None,
+        }
+    }
+}
+
+impl<T> Deref for Recipients<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Recipients<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Recipients<T>
+where
+    &'a T: IntoIterator,
+{
+    type Item = <&'a T as IntoIterator>::Item;
+    type IntoIter = <&'a T as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.value.into_iter()
+    }
+}
+
+impl<T: IntoIterator> IntoIterator for Recipients<T> {
+    type Item = T::Item;
+    type IntoIter = T::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.value.into_iter()
+    }
+}
+
+impl<T: Serialize> Serialize for Recipients<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+/// Deserializes a recipient address
+pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Recipients<Address>, D::Error> {
+    let address = String::deserialize(deserializer)?;
+    let checksummed = is_checksummed(&address);
+
+    Ok(Recipients {
+        value: parse(&address).map_err(de::Error::custom)?,
+        unchecksummed: (!checksummed).then_some(address),
+    })
+}
+
+/// Same as [`deserialize`] for the keys of a recipients map
+pub fn deserialize_map<'de, D, V>(
+    deserializer: D,
+) -> Result<Recipients<BTreeMap<Address, V>>, D::Error>
+where
+    D: Deserializer<'de>,
+    V: Deserialize<'de>,
+{
+    let recipients = BTreeMap::<String, V>::deserialize(deserializer)?;
+    let unchecksummed = recipients
+        .keys()
+        .find(|address| !is_checksummed(address))
+        .cloned();
+    let value = recipients
+        .into_iter()
+        .map(|(address, value)| Ok((parse(&address).map_err(de::Error::custom)?, value)))
+        .collect::<Result<_, D::Error>>()?;
+
+    Ok(Recipients {
+        value,
+        unchecksummed,
+    })
+}
+
+/// Same as [`deserialize`] for a list of recipients
+pub fn deserialize_vec<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Recipients<Vec<Address>>, D::Error> {
+    let recipients = Vec::<String>::deserialize(deserializer)?;
+    let unchecksummed = recipients
+        .iter()
+        .find(|address| !is_checksummed(address))
+        .cloned();
+    let value = recipients
+        .iter()
+        .map(|address| parse(address).map_err(de::Error::custom))
+        .collect::<Result<_, D::Error>>()?;
+
+    Ok(Recipients {
+        value,
+        unchecksummed,
+    })
+}
+
+fn parse(address: &str) -> Result<Address, String> {
+    Address::from_str(address).map_err(|e| format!("invalid recipient {address}: {e}"))
+}
+
+fn is_checksummed(address: &str) -> bool {
+    Address::parse_checksummed(address, None).is_ok()
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use alloy::primitives::keccak256;
+
+/// Formats the inner value as is, or as a short hash of it when `redact` is set.
+///
+/// Equal values produce equal hashes, so redacted log lines can still be correlated.
+pub struct Redacted<T> {
+    value: T,
+    redact: bool,
+}
+
+impl<T> Redacted<T> {
+    pub fn new(value: T, redact: bool) -> Self {
+        Self { value, redact }
+    }
+
+    fn write_hash(f: &mut fmt::Formatter<'_>, formatted: String) -> fmt::Result {
+        let hash = keccak256(formatted.as_bytes());
+        write!(f, "[redacted:{}]", alloy::hex::encode(&hash[..4]))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redact {
+            Self::write_hash(f, format!("{:?}", self.value))
+        } else {
+            self.value.fmt(f)
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.redact {
+            Self::write_hash(f, self.value.to_string())
+        } else {
+            self.value.fmt(f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{address, Address};
+    use serde_json::json;
+
+    use super::Redacted;
+    use crate::testing::{self, MockNode};
+
+    const CALLER: Address = address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+
+    #[test]
+    fn should_hash_values_when_enabled() {
+        let redacted = Redacted::new(CALLER, true).to_string();
+
+        assert!(redacted.starts_with("[redacted:"), "{redacted}");
+        assert!(!redacted.contains(&CALLER.to_string()[2..]));
+        assert_eq!(redacted, Redacted::new(CALLER, true).to_string());
+        assert_ne!(redacted, Redacted::new(Address::ZERO, true).to_string());
+        assert_ne!(
+            format!("{:?}", Redacted::new(CALLER, true)),
+            format!("{CALLER:?}")
+        );
+
+        assert_eq!(Redacted::new(CALLER, false).to_string(), CALLER.to_string());
+        assert_eq!(
+            format!("{:?}", Redacted::new(CALLER, false)),
+            format!("{CALLER:?}")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_redact_as_configured() {
+        let node = MockNode::new(|_, _| None);
+
+        let state = testing::state(&node, json!({ "redact_logs": true })).await;
+        assert_eq!(
+            state.redacted(CALLER).to_string(),
+            Redacted::new(CALLER, true).to_string()
+        );
+
+        let state = testing::state(&node, json!({})).await;
+        assert_eq!(state.redacted(CALLER).to_string(), CALLER.to_string());
+    }
+}
@@ -1,43 +1,195 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::post, Json, Router};
-use std::{borrow::Cow, sync::Arc};
-use tracing::error;
+use alloy::{
+    primitives::{keccak256, Address, B256},
+    transports::{RpcError, TransportErrorKind},
+};
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{
+        rejection::{JsonRejection, MissingJsonContentType},
+        FromRequest, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::from_fn,
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post, MethodRouter},
+    Json, Router,
+};
+use futures::{stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    borrow::Cow,
+    future::Future,
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver},
+    oneshot,
+};
+use tracing::{error, warn};
+use uuid::Uuid;
 
 use crate::{
+    amount,
+    config::{AppConfig, ErrorTemplates},
     dto::{
-        ApproveRequest, CollectErc20Request, CollectErc20Response, DisperseErc20Request,
-        DisperseErc20Response, DisperseEthRequest, DisperseEthResponse, ErrorResponse,
-        TransactionResponse, TransferRequest,
+        AllowanceQuery, AllowanceResponse, ApprovePreviewRequest, ApprovePreviewResponse,
+        ApproveRequest, ApproveSuggestion, BalanceQuery, BalanceResponse, BatchOptions,
+        BatchResponse, BroadcastEvent, CallerUsageResponse, CapabilitiesResponse, ChainQuery,
+        CollectErc20Request, CollectErc20TargetRequest, DisperseErc20EqualRequest,
+        DisperseErc20Request, DisperseEthEqualRequest, DisperseEthRequest, ErrorResponse,
+        FractionOrAmount, JobCreatedResponse, QuoteRequest, QuoteResponse, ReadinessResponse,
+        RequiredBalanceRequest, RequiredBalanceResponse, StatsQuery, StatsResponse,
+        TestnetFundRequest, TestnetFundResponse, TokenResponse, TopUpResponse,
+        TransactionStatusOptions, TransactionStatusResponse, TransferRequest, TxOptions,
+        WhichSignerRequest, WhichSignerResponse,
     },
+    jobs::{Job, JobItem, JobStatus, KeyConflict},
+    metrics,
+    middleware::{self, RpcErrorDetails},
+    redact::Redacted,
     service::{self, DcError},
     state::AppState,
+    templates::Template,
 };
 
 type Result<T> = std::result::Result<Json<T>, ApiError>;
 
+static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+static REQUEST_HASH: HeaderName = HeaderName::from_static("x-request-hash");
+
 #[derive(Debug, thiserror::Error)]
 pub enum ApiError {
     #[error("invalid request: {0}")]
     InvalidRequest(String),
+    /// Invalid request with a message that `ERROR_TEMPLATES` can replace
+    #[error("invalid request: {message}")]
+    Templated { message: String, template: Template },
+    #[error("invalid request: {message}")]
+    InsufficientAllowance {
+        message: String,
+        suggestion: ApproveSuggestion,
+    },
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("timeout: {0}")]
+    Timeout(String),
     #[error("unexpected error: {0}")]
     Internal(#[source] anyhow::Error),
+    /// Failure after recipients were already topped up with ETH, reported along with the top-up
+    #[error("{source}")]
+    ToppedUp {
+        source: Box<ApiError>,
+        top_up: Box<TopUpResponse>,
+    },
 }
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> axum::response::Response {
-        error!("Request failed with error: {self:?}");
+impl ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_)
+            | ApiError::Templated { .. }
+            | ApiError::InsufficientAllowance { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::ToppedUp { source, .. } => source.status_code(),
+        }
+    }
 
-        let (message, code) = match self {
-            ApiError::InvalidRequest(s) => (Cow::Owned(s), StatusCode::BAD_REQUEST),
+    /// JSON-RPC error returned by the node that caused an internal error
+    fn rpc_error(&self) -> Option<RpcErrorDetails> {
+        let e = match self {
+            ApiError::Internal(e) => e,
+            ApiError::ToppedUp { source, .. } => return source.rpc_error(),
+            _ => return None,
+        };
 
-            ApiError::Internal(_) => (
-                "internal server error".into(),
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ),
+        e.chain()
+            .find_map(|e| e.downcast_ref::<RpcError<TransportErrorKind>>())
+            .and_then(RpcError::as_error_resp)
+            .map(|payload| RpcErrorDetails {
+                code: payload.code,
+                message: payload.message.clone(),
+            })
+    }
+
+    /// Only failures talking to the node are worth retrying as is, other errors need a changed request
+    fn retryable(&self) -> bool {
+        match self {
+            ApiError::Internal(e) => e
+                .downcast_ref::<DcError>()
+                .is_some_and(DcError::is_retryable),
+            ApiError::ToppedUp { source, .. } => source.retryable(),
+            _ => false,
+        }
+    }
+
+    /// Client-facing message, rendered from `templates` if one applies. Internal details are not exposed
+    fn message(&self, templates: &ErrorTemplates) -> Cow<'_, str> {
+        match self {
+            ApiError::Templated { message, template } => template
+                .render(templates)
+                .map_or(Cow::Borrowed(message), Cow::Owned),
+            ApiError::InvalidRequest(s)
+            | ApiError::InsufficientAllowance { message: s, .. }
+            | ApiError::Unauthorized(s)
+            | ApiError::Forbidden(s)
+            | ApiError::NotFound(s)
+            | ApiError::Conflict(s)
+            | ApiError::Timeout(s) => Cow::Borrowed(s),
+            ApiError::Internal(_) => "internal server error".into(),
+            ApiError::ToppedUp { source, .. } => source.message(templates),
+        }
+    }
+
+    pub fn body(&self, templates: &ErrorTemplates) -> ErrorResponse<'_> {
+        let (e, top_up) = match self {
+            ApiError::ToppedUp { source, top_up } => (&**source, Some(&**top_up)),
+            e => (e, None),
+        };
+
+        let suggestion = match e {
+            ApiError::InsufficientAllowance { suggestion, .. } => Some(suggestion),
+            _ => None,
         };
 
-        let body = ErrorResponse { error: message };
+        ErrorResponse {
+            error: e.message(templates),
+            retryable: e.retryable(),
+            suggestion,
+            top_up,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        let rpc_error = self.rpc_error();
+        let body = self.body(&ErrorTemplates::default());
+        let mut response = (self.status_code(), Json(body)).into_response();
+
+        if let Some(rpc_error) = rpc_error {
+            response.extensions_mut().insert(rpc_error);
+        }
+        // logged and rendered with the configured templates by `middleware::handle_failure`
+        response.extensions_mut().insert(Arc::new(self));
 
-        (code, Json(body)).into_response()
+        response
     }
 }
 
@@ -45,69 +197,1370 @@ impl From<DcError> for ApiError {
     fn from(value: DcError) -> Self {
         match value {
             e @ DcError::InsufficientFunds { .. }
+            | e @ DcError::TargetNotReached { .. }
+            | e @ DcError::InvalidPermit { .. }
             | e @ DcError::InvalidFractionalAmount(_)
-            | e @ DcError::TokenNotFound(_) => Self::InvalidRequest(e.to_string()),
+            | e @ DcError::InvalidDecimalAmount { .. }
+            | e @ DcError::MissingDecimals(_)
+            | e @ DcError::BasisPointsExceeded(_)
+            | e @ DcError::ZeroTotalWeight
+            | e @ DcError::UnexpectedWeight
+            | e @ DcError::TokenNotFound(_)
+            | e @ DcError::CallerIsContract(_)
+            | e @ DcError::SpenderSignersNotFound(_)
+            | e @ DcError::ContractRecipient(_)
+            | e @ DcError::ZeroAddressRecipient
+            | e @ DcError::UnchecksummedRecipient(_)
+            | e @ DcError::NoRecipients
+            | e @ DcError::NoSpenders
+            | e @ DcError::TooManyRecipients { .. }
+            | e @ DcError::UnknownChain(_)
+            | e @ DcError::ExceedsTotalSupply { .. }
+            | e @ DcError::BalanceUnreachable
+            | e @ DcError::AmountOverflow
+            | e @ DcError::ZeroAmount(_)
+            | e @ DcError::PartialSplitRemainder
+            | e @ DcError::InvalidFeeOverride(_)
+            | e @ DcError::InvalidBlobFields(_)
+            | e @ DcError::TransactionReverted { .. }
+            | e @ DcError::RelayedInsufficientAllowance { .. }
+            | e @ DcError::SimulationReverted(_) => match Template::of(&e) {
+                Some(template) => Self::Templated {
+                    message: e.to_string(),
+                    template,
+                },
+                None => Self::InvalidRequest(e.to_string()),
+            },
+            e @ DcError::UnverifiedToken { .. }
+            | e @ DcError::GasBudgetExceeded { .. }
+            | e @ DcError::DeniedRecipient(_)
+            | e @ DcError::NewContract { .. }
+            | e @ DcError::RelayedDisperse { .. } => Self::Forbidden(e.to_string()),
+            e @ DcError::AllowanceChanged { .. } => Self::Conflict(e.to_string()),
+            e @ DcError::ConfirmationTimeout { .. } => Self::Timeout(e.to_string()),
+            DcError::InsufficientAllowance(shortfall) => Self::InsufficientAllowance {
+                message: DcError::InsufficientAllowance(shortfall.clone()).to_string(),
+                suggestion: ApproveSuggestion {
+                    additional_amount: shortfall.required - shortfall.allowance,
+                    approve: ApproveRequest {
+                        spender: shortfall.spender,
+                        amount: FractionOrAmount::Amount {
+                            amount: shortfall.required,
+                        },
+                        token: shortfall.token,
+                        caller: shortfall.owner,
+                        chain_id: Some(shortfall.chain_id),
+                    },
+                },
+            },
+            DcError::ToppedUp { source, top_up } => Self::ToppedUp {
+                source: Box::new((*source).into()),
+                top_up,
+            },
             e => Self::Internal(e.into()),
         }
     }
 }
 
+/// Every route of the API as `"<METHOD> <path>"` with its handler, `GET /capabilities` reports the
+/// same list
+fn endpoints(config: &AppConfig) -> Vec<(&'static str, MethodRouter<Arc<AppState>>)> {
+    let mut endpoints = vec![
+        ("POST /disperse-eth", post(handle_disperse_eth)),
+        ("POST /disperse-erc20", post(handle_disperse_erc20)),
+        ("POST /disperse-eth/equal", post(handle_disperse_eth_equal)),
+        (
+            "POST /disperse-erc20/equal",
+            post(handle_disperse_erc20_equal),
+        ),
+        ("POST /collect-erc20", post(handle_collect_erc20)),
+        (
+            "POST /collect-erc20/target",
+            post(handle_collect_erc20_target),
+        ),
+        ("POST /transfer", post(handle_transfer)),
+        ("POST /approve", post(handle_approve)),
+        ("POST /ensure-approval", post(handle_ensure_approval)),
+        ("POST /approve/preview", post(handle_approve_preview)),
+        ("POST /which-signer", post(handle_which_signer)),
+        ("POST /required-balance", post(handle_required_balance)),
+        ("POST /quote", post(handle_quote)),
+        ("GET /capabilities", get(handle_capabilities)),
+        ("GET /health", get(handle_health)),
+        ("GET /ready", get(handle_ready)),
+        ("GET /token/:address", get(handle_token)),
+        ("GET /balance", get(handle_balance)),
+        ("GET /allowance", get(handle_allowance)),
+        ("GET /tx/:hash", get(handle_transaction_status)),
+        ("GET /callers/:address/usage", get(handle_caller_usage)),
+        ("GET /jobs/:job_id", get(handle_job)),
+    ];
+
+    if config.testnet_faucet().is_some() {
+        endpoints.push(("POST /testnet/fund", post(handle_testnet_fund)));
+    }
+    if config.stats_token.is_some() {
+        endpoints.push(("GET /stats", get(handle_stats)));
+    }
+
+    endpoints
+}
+
+/// A JSON request body, parsed like [`Json`] but with the amount parser picked by
+/// `strict_amounts`.
+struct ApiJson<T>(T);
+
+#[async_trait]
+impl<T: DeserializeOwned> FromRequest<Arc<AppState>> for ApiJson<T> {
+    type Rejection = JsonRejection;
+
+    async fn from_request(
+        req: Request,
+        state: &Arc<AppState>,
+    ) -> std::result::Result<Self, JsonRejection> {
+        if !json_content_type(req.headers()) {
+            return Err(MissingJsonContentType::default().into());
+        }
+
+        let bytes = Bytes::from_request(req, state).await?;
+        let Json(value) =
+            amount::with_parser(state.config().strict_amounts, || Json::from_bytes(&bytes))?;
+
+        Ok(Self(value))
+    }
+}
+
+fn json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    let Some((kind, subtype)) = essence.split_once('/') else {
+        return false;
+    };
+
+    kind.eq_ignore_ascii_case("application")
+        && (subtype.eq_ignore_ascii_case("json") || subtype.to_ascii_lowercase().ends_with("+json"))
+}
+
 pub fn api_routes(state: Arc<AppState>) -> Router {
-    Router::new()
-        .route("/disperse-eth", post(handle_disperse_eth))
-        .route("/disperse-erc20", post(handle_disperse_erc20))
-        .route("/collect-erc20", post(handle_collect_erc20))
-        .route("/transfer", post(handle_transfer))
-        .route("/approve", post(handle_approve))
+    endpoints(state.config())
+        .into_iter()
+        .fold(Router::new(), |router, (endpoint, handler)| {
+            let (_, path) = endpoint.split_once(' ').expect("endpoint without a method");
+            router.route(path, handler)
+        })
+        .route_layer(from_fn(middleware::count_requests))
         .with_state(state)
 }
 
+/// Recorded metrics in the Prometheus text format, served outside of `/api`
+pub async fn handle_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+}
+
 async fn handle_disperse_eth(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<DisperseEthRequest>,
-) -> Result<DisperseEthResponse> {
-    service::disperse_eth(state.provider(), state.contract(), req)
+    Query(options): Query<BatchOptions>,
+    Query(tx_options): Query<TxOptions>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<DisperseEthRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let state = resolve_chain(&state, &mut req.chain_id)?;
+    ensure_max_recipients(&state, "recipients", req.recipients.len())?;
+    let request_hash = request_hash("disperse-eth", &req);
+    let idempotency_key = idempotency_key(&headers, req.idempotency_key.take());
+    #[cfg(feature = "blobs")]
+    let tx_options = TxOptions {
+        blob: req.blob.take(),
+        ..tx_options
+    };
+    let (tx_options, broadcasts) = batch_broadcast_channel(tx_options, &options);
+
+    if let Some(chunk_size) = chunk_size(&state, req.recipients.len()) {
+        #[cfg(feature = "blobs")]
+        if tx_options.blob.is_some() {
+            return Err(DcError::InvalidBlobFields(
+                "blobs can't be attached to a disperse split into several transactions",
+            )
+            .into());
+        }
+
+        let operation = {
+            let state = state.clone();
+            async move {
+                let disperse = service::disperse_eth_chunked(&state, req, chunk_size, tx_options);
+                metrics::timed("disperseEth", disperse).await
+            }
+        };
+
+        return run_batch(
+            &state,
+            options,
+            idempotency_key,
+            request_hash,
+            broadcasts,
+            operation,
+        )
+        .await;
+    }
+
+    let operation = {
+        let state = state.clone();
+        async move {
+            metrics::timed(
+                "disperseEth",
+                service::disperse_eth(&state, req, options.verbose, tx_options),
+            )
+            .await
+        }
+    };
+
+    run_batch(
+        &state,
+        options,
+        idempotency_key,
+        request_hash,
+        broadcasts,
+        operation,
+    )
+    .await
+}
+
+async fn handle_disperse_erc20(
+    State(state): State<Arc<AppState>>,
+    Query(options): Query<BatchOptions>,
+    Query(tx_options): Query<TxOptions>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<DisperseErc20Request>,
+) -> std::result::Result<Response, ApiError> {
+    let state = resolve_chain(&state, &mut req.chain_id)?;
+    ensure_max_recipients(&state, "recipients", req.recipients.len())?;
+    let request_hash = request_hash("disperse-erc20", &req);
+    let idempotency_key = idempotency_key(&headers, req.idempotency_key.take());
+    #[cfg(feature = "blobs")]
+    let tx_options = TxOptions {
+        blob: req.blob.take(),
+        ..tx_options
+    };
+    let (tx_options, broadcasts) = batch_broadcast_channel(tx_options, &options);
+
+    if let Some(chunk_size) = chunk_size(&state, req.recipients.len()) {
+        #[cfg(feature = "blobs")]
+        if tx_options.blob.is_some() {
+            return Err(DcError::InvalidBlobFields(
+                "blobs can't be attached to a disperse split into several transactions",
+            )
+            .into());
+        }
+
+        let operation = {
+            let state = state.clone();
+            async move {
+                let disperse = service::disperse_erc20_chunked(
+                    &state,
+                    req,
+                    chunk_size,
+                    options.transfers_from_events,
+                    tx_options,
+                );
+                metrics::timed("disperseErc20", disperse).await
+            }
+        };
+
+        return run_batch(
+            &state,
+            options,
+            idempotency_key,
+            request_hash,
+            broadcasts,
+            operation,
+        )
+        .await;
+    }
+
+    let operation = {
+        let state = state.clone();
+        async move {
+            let disperse = service::disperse_erc20(
+                &state,
+                req,
+                options.verbose,
+                options.transfers_from_events,
+                tx_options,
+            );
+            metrics::timed("disperseErc20", disperse).await
+        }
+    };
+
+    run_batch(
+        &state,
+        options,
+        idempotency_key,
+        request_hash,
+        broadcasts,
+        operation,
+    )
+    .await
+}
+
+/// Rejects batches with more than `max_recipients` entries, `field` names them in the error
+fn ensure_max_recipients(
+    state: &AppState,
+    field: &'static str,
+    count: usize,
+) -> std::result::Result<(), DcError> {
+    let max = state.config().max_recipients;
+    if count > max {
+        return Err(DcError::TooManyRecipients { field, count, max });
+    }
+
+    Ok(())
+}
+
+/// `max_recipients_per_tx` if a disperse to `recipients` exceeds it
+fn chunk_size(state: &AppState, recipients: usize) -> Option<NonZeroUsize> {
+    state
+        .config()
+        .max_recipients_per_tx
+        .filter(|max| recipients > max.get())
+}
+
+async fn handle_disperse_eth_equal(
+    state: State<Arc<AppState>>,
+    options: Query<BatchOptions>,
+    tx_options: Query<TxOptions>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<DisperseEthEqualRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let req = service::equal_disperse_eth(req)?;
+
+    handle_disperse_eth(state, options, tx_options, headers, ApiJson(req)).await
+}
+
+async fn handle_disperse_erc20_equal(
+    State(state): State<Arc<AppState>>,
+    options: Query<BatchOptions>,
+    tx_options: Query<TxOptions>,
+    headers: HeaderMap,
+    ApiJson(req): ApiJson<DisperseErc20EqualRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let chain_state = state.on_chain(req.chain_id)?;
+    let req = service::equal_disperse_erc20(&chain_state, req).await?;
+
+    handle_disperse_erc20(State(state), options, tx_options, headers, ApiJson(req)).await
+}
+
+async fn handle_collect_erc20(
+    State(state): State<Arc<AppState>>,
+    Query(options): Query<BatchOptions>,
+    Query(tx_options): Query<TxOptions>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<CollectErc20Request>,
+) -> std::result::Result<Response, ApiError> {
+    let state = resolve_chain(&state, &mut req.chain_id)?;
+    ensure_max_recipients(&state, "spenders", req.spenders.len())?;
+    let request_hash = request_hash("collect-erc20", &req);
+    let idempotency_key = idempotency_key(&headers, req.idempotency_key.take());
+    let (tx_options, broadcasts) = batch_broadcast_channel(tx_options, &options);
+
+    if let Some(chunk_size) = req.chunk_size {
+        let operation = {
+            let state = state.clone();
+            async move {
+                let collect = service::collect_erc20_chunked(
+                    &state,
+                    req,
+                    chunk_size,
+                    options.verbose,
+                    options.transfers_from_events,
+                    tx_options,
+                );
+                metrics::timed("collectErc20", collect).await
+            }
+        };
+
+        return run_batch(
+            &state,
+            options,
+            idempotency_key,
+            request_hash,
+            broadcasts,
+            operation,
+        )
+        .await;
+    }
+
+    let operation = {
+        let state = state.clone();
+        async move {
+            let collect = service::collect_erc20(
+                &state,
+                req,
+                options.verbose,
+                options.transfers_from_events,
+                tx_options,
+            );
+            metrics::timed("collectErc20", collect).await
+        }
+    };
+
+    run_batch(
+        &state,
+        options,
+        idempotency_key,
+        request_hash,
+        broadcasts,
+        operation,
+    )
+    .await
+}
+
+async fn handle_collect_erc20_target(
+    State(state): State<Arc<AppState>>,
+    Query(options): Query<BatchOptions>,
+    Query(tx_options): Query<TxOptions>,
+    headers: HeaderMap,
+    ApiJson(mut req): ApiJson<CollectErc20TargetRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let state = resolve_chain(&state, &mut req.chain_id)?;
+    ensure_max_recipients(&state, "spenders", req.spenders.len())?;
+    let request_hash = request_hash("collect-erc20/target", &req);
+    let idempotency_key = idempotency_key(&headers, req.idempotency_key.take());
+    let (tx_options, broadcasts) = batch_broadcast_channel(tx_options, &options);
+    let operation = {
+        let state = state.clone();
+        async move {
+            let collect = service::collect_erc20_target(
+                &state,
+                req,
+                options.verbose,
+                options.transfers_from_events,
+                tx_options,
+            );
+            metrics::timed("collectErc20Target", collect).await
+        }
+    };
+
+    run_batch(
+        &state,
+        options,
+        idempotency_key,
+        request_hash,
+        broadcasts,
+        operation,
+    )
+    .await
+}
+
+async fn handle_transfer(
+    State(state): State<Arc<AppState>>,
+    Query(options): Query<TxOptions>,
+    ApiJson(req): ApiJson<TransferRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let state = state.on_chain(req.chain_id)?;
+    let (options, broadcasts) = broadcast_channel(options);
+    let config = state.config().clone();
+    let operation =
+        async move { metrics::timed("transfer", service::transfer(&state, req, options)).await };
+
+    respond(&config, broadcasts, operation).await
+}
+
+async fn handle_approve(
+    State(state): State<Arc<AppState>>,
+    Query(options): Query<TxOptions>,
+    ApiJson(req): ApiJson<ApproveRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let state = state.on_chain(req.chain_id)?;
+    let (options, broadcasts) = broadcast_channel(options);
+    let config = state.config().clone();
+    let operation =
+        async move { metrics::timed("approve", service::approve(&state, req, options)).await };
+
+    respond(&config, broadcasts, operation).await
+}
+
+async fn handle_ensure_approval(
+    State(state): State<Arc<AppState>>,
+    Query(options): Query<TxOptions>,
+    ApiJson(req): ApiJson<ApproveRequest>,
+) -> std::result::Result<Response, ApiError> {
+    let state = state.on_chain(req.chain_id)?;
+    let (options, broadcasts) = broadcast_channel(options);
+    let config = state.config().clone();
+    let operation = async move {
+        metrics::timed(
+            "ensureApproval",
+            service::ensure_approval(&state, req, options),
+        )
+        .await
+    };
+
+    respond(&config, broadcasts, operation).await
+}
+
+async fn handle_approve_preview(
+    State(state): State<Arc<AppState>>,
+    ApiJson(req): ApiJson<ApprovePreviewRequest>,
+) -> Result<ApprovePreviewResponse> {
+    let state = state.on_chain(req.chain_id)?;
+    service::approve_preview(&state, req)
         .await
         .map(Json)
         .map_err(Into::into)
 }
 
-async fn handle_disperse_erc20(
+async fn handle_which_signer(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<DisperseErc20Request>,
-) -> Result<DisperseErc20Response> {
-    service::disperse_erc20(state.provider(), state.contract(), req)
+    ApiJson(req): ApiJson<WhichSignerRequest>,
+) -> Result<WhichSignerResponse> {
+    let state = state.on_chain(req.chain_id)?;
+    service::which_signer(&state, req)
         .await
         .map(Json)
         .map_err(Into::into)
 }
 
-async fn handle_collect_erc20(
+async fn handle_testnet_fund(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<CollectErc20Request>,
-) -> Result<CollectErc20Response> {
-    service::collect_erc20(state.provider(), state.contract(), req)
+    ApiJson(req): ApiJson<TestnetFundRequest>,
+) -> Result<TestnetFundResponse> {
+    let state = state.on_chain(req.chain_id)?;
+    service::testnet_fund(&state, req)
         .await
         .map(Json)
         .map_err(Into::into)
 }
 
-async fn handle_transfer(
+async fn handle_stats(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StatsQuery>,
+    headers: HeaderMap,
+) -> Result<StatsResponse> {
+    let expected = state.config().stats_token.as_deref().unwrap_or_default();
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    if !token.is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes())) {
+        return Err(ApiError::Unauthorized(
+            "missing or invalid bearer token".into(),
+        ));
+    }
+
+    let window = query
+        .window_secs
+        .map(Duration::from_secs)
+        .unwrap_or(state.stats().retention());
+
+    Ok(Json(state.stats().summary(window)))
+}
+
+/// Compares without exiting early, so the response time doesn't leak how much of a token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn handle_required_balance(
+    ApiJson(req): ApiJson<RequiredBalanceRequest>,
+) -> Result<RequiredBalanceResponse> {
+    service::required_balance(req).map(Json).map_err(Into::into)
+}
+
+async fn handle_quote(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<TransferRequest>,
-) -> Result<TransactionResponse> {
-    service::transfer(state.provider(), req)
+    ApiJson(req): ApiJson<QuoteRequest>,
+) -> Result<QuoteResponse> {
+    let state = state.on_chain(req.chain_id)?;
+    service::quote(&state, req)
         .await
         .map(Json)
         .map_err(Into::into)
 }
 
-async fn handle_approve(
+async fn handle_capabilities(State(state): State<Arc<AppState>>) -> Json<CapabilitiesResponse> {
+    let endpoints = endpoints(state.config())
+        .into_iter()
+        .map(|(endpoint, _)| endpoint)
+        .collect();
+
+    Json(service::capabilities(&state, endpoints))
+}
+
+async fn handle_health() -> StatusCode {
+    StatusCode::OK
+}
+
+/// `503 Service Unavailable` if any chain fails a check
+async fn handle_ready(State(state): State<Arc<AppState>>) -> Response {
+    let chains = state
+        .chain_ids()
+        .into_iter()
+        .filter_map(|chain_id| state.on_chain(Some(chain_id)).ok());
+    let checks: Vec<_> = futures::future::join_all(
+        chains.map(|chain| async move { service::readiness_checks(&chain).await }),
+    )
+    .await
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let ready = checks.iter().all(|check| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(ReadinessResponse { ready, checks })).into_response()
+}
+
+async fn handle_token(
+    State(state): State<Arc<AppState>>,
+    Path(address): Path<Address>,
+    Query(chain): Query<ChainQuery>,
+) -> Result<TokenResponse> {
+    let state = state.on_chain(chain.chain_id)?;
+    service::token_metadata(&state, address)
+        .await
+        .map(Json)
+        .map_err(Into::into)
+}
+
+async fn handle_balance(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<BalanceQuery>,
+    Query(chain): Query<ChainQuery>,
+) -> Result<BalanceResponse> {
+    let state = state.on_chain(chain.chain_id)?;
+    service::balance(&state, query)
+        .await
+        .map(Json)
+        .map_err(Into::into)
+}
+
+async fn handle_allowance(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AllowanceQuery>,
+    Query(chain): Query<ChainQuery>,
+) -> Result<AllowanceResponse> {
+    let state = state.on_chain(chain.chain_id)?;
+    service::allowance(&state, query)
+        .await
+        .map(Json)
+        .map_err(Into::into)
+}
+
+async fn handle_transaction_status(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<ApproveRequest>,
-) -> Result<TransactionResponse> {
-    service::approve(state.provider(), req)
+    Path(tx_hash): Path<B256>,
+    Query(chain): Query<ChainQuery>,
+    Query(options): Query<TransactionStatusOptions>,
+) -> Result<TransactionStatusResponse> {
+    let state = state.on_chain(chain.chain_id)?;
+    service::get_transaction_status(&state, tx_hash, options)
         .await
         .map(Json)
         .map_err(Into::into)
 }
+
+async fn handle_caller_usage(
+    State(state): State<Arc<AppState>>,
+    Path(address): Path<Address>,
+) -> Json<CallerUsageResponse> {
+    Json(service::caller_usage(&state, address))
+}
+
+async fn handle_job(State(state): State<Arc<AppState>>, Path(job_id): Path<Uuid>) -> Result<Job> {
+    state
+        .jobs()
+        .get(&job_id)
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("job {job_id}")))
+}
+
+/// Sets up a sink for transaction hashes if the response is streamed
+fn broadcast_channel(mut options: TxOptions) -> (TxOptions, Option<UnboundedReceiver<B256>>) {
+    if !options.stream {
+        return (options, None);
+    }
+
+    let (sink, broadcasts) = mpsc::unbounded_channel();
+    options.broadcast_sink = Some(sink);
+
+    (options, Some(broadcasts))
+}
+
+/// Like [`broadcast_channel`], also set up for background jobs with a deadline, which keep the hashes sent
+/// before the deadline passed
+fn batch_broadcast_channel(
+    mut options: TxOptions,
+    batch: &BatchOptions,
+) -> (TxOptions, Option<UnboundedReceiver<B256>>) {
+    if !batch.run_async || batch.deadline.is_none() {
+        return broadcast_channel(options);
+    }
+
+    let (sink, broadcasts) = mpsc::unbounded_channel();
+    options.broadcast_sink = Some(sink);
+
+    (options, Some(broadcasts))
+}
+
+/// Responds with the result of the operation, or streams it as server-sent events:
+/// a `broadcast` event per sent transaction followed by a final `result` or `error` event
+async fn respond<T, E>(
+    config: &AppConfig,
+    broadcasts: Option<UnboundedReceiver<B256>>,
+    operation: impl Future<Output = std::result::Result<T, E>> + Send + 'static,
+) -> std::result::Result<Response, ApiError>
+where
+    T: Serialize + Send + 'static,
+    E: Send + 'static,
+    ApiError: From<E>,
+{
+    let Some(broadcasts) = broadcasts else {
+        return Ok(Json(operation.await?).into_response());
+    };
+
+    let (result_tx, result_rx) = oneshot::channel();
+
+    tokio::spawn(async move {
+        let _ = result_tx.send(operation.await);
+    });
+
+    // ends once the operation is done and its sink is dropped
+    let broadcasts = stream::unfold(broadcasts, |mut broadcasts| async move {
+        let tx_hash = broadcasts.recv().await?;
+        let event = Event::default()
+            .event("broadcast")
+            .json_data(BroadcastEvent { tx_hash });
+
+        Some((event, broadcasts))
+    });
+
+    let (redact_logs, templates) = (config.redact_logs, config.error_templates.clone());
+    let result = stream::once(async move {
+        let result = match result_rx.await {
+            Ok(result) => result.map_err(ApiError::from),
+            Err(e) => Err(DcError::unexpected(e).into()),
+        };
+
+        match result {
+            Ok(response) => Event::default().event("result").json_data(response),
+            Err(e) => {
+                error!(
+                    "Streamed request failed with error: {:?}",
+                    Redacted::new(&e, redact_logs)
+                );
+
+                Event::default()
+                    .event("error")
+                    .json_data(e.body(&templates))
+            }
+        }
+    });
+
+    Ok(Sse::new(broadcasts.chain(result)).into_response())
+}
+
+/// Runs a batch operation in place or, if requested, as a background job, and adds the request hash to
+/// the response in `X-Request-Hash`
+async fn run_batch<T>(
+    state: &AppState,
+    options: BatchOptions,
+    idempotency_key: Option<String>,
+    request_hash: B256,
+    broadcasts: Option<UnboundedReceiver<B256>>,
+    operation: impl Future<Output = std::result::Result<T, DcError>> + Send + 'static,
+) -> std::result::Result<Response, ApiError>
+where
+    T: BatchResponse + Serialize + Send + 'static,
+{
+    // without an explicit key, identical requests within the dedup window share a job
+    let key = match idempotency_key {
+        Some(key) => Some((key, None)),
+        None => state.config().implicit_dedup_secs.map(|secs| {
+            (
+                format!("request-hash:{request_hash}"),
+                Some(Duration::from_secs(secs)),
+            )
+        }),
+    };
+
+    let mut response =
+        dispatch_batch(state, options, key, request_hash, broadcasts, operation).await?;
+
+    if let Ok(value) = HeaderValue::from_str(&request_hash.to_string()) {
+        response.headers_mut().insert(REQUEST_HASH.clone(), value);
+    }
+
+    Ok(response)
+}
+
+/// `key` is the idempotency key and the window it dedups requests in, unlimited if `None`.
+/// A key reused for a request with a different `request_hash` is rejected
+async fn dispatch_batch<T>(
+    state: &AppState,
+    options: BatchOptions,
+    key: Option<(String, Option<Duration>)>,
+    request_hash: B256,
+    broadcasts: Option<UnboundedReceiver<B256>>,
+    operation: impl Future<Output = std::result::Result<T, DcError>> + Send + 'static,
+) -> std::result::Result<Response, ApiError>
+where
+    T: BatchResponse + Serialize + Send + 'static,
+{
+    let timeout = match options.deadline {
+        Some(_) if !options.run_async => {
+            return Err(ApiError::InvalidRequest("deadline requires async".into()))
+        }
+        Some(deadline) => {
+            let deadline = UNIX_EPOCH + Duration::from_secs(deadline);
+            let timeout = deadline
+                .duration_since(SystemTime::now())
+                .map_err(|_| ApiError::InvalidRequest("deadline has already passed".into()))?;
+
+            Some(timeout)
+        }
+        None => None,
+    };
+
+    let jobs = state.jobs().clone();
+    let config = state.config().clone();
+
+    // a repeated request gets the state of the original operation instead of running it again
+    let job_id = match key {
+        Some((key, window)) => match jobs.create_keyed(key.clone(), request_hash, 1, window) {
+            Ok(job_id) => Some(job_id),
+            Err(KeyConflict::Existing(job)) => return Ok(replay(job)),
+            Err(KeyConflict::Mismatch) => {
+                return Err(ApiError::Conflict(format!(
+                    "idempotency key {key} was already used for a different request"
+                )))
+            }
+        },
+        None => None,
+    };
+
+    if !options.run_async {
+        let Some(job_id) = job_id else {
+            return respond(&config, broadcasts, operation).await;
+        };
+
+        // spawned so the job is finished even if the client disconnects or the request deadline passes
+        let operation = tokio::spawn({
+            let config = config.clone();
+            async move {
+                let result = operation.await.map_err(ApiError::from);
+                let templates = &config.error_templates;
+                jobs.update(&job_id, |job| {
+                    finish_job(job, Some(result.as_ref()), templates)
+                });
+
+                result
+            }
+        });
+
+        let operation = async move {
+            match operation.await {
+                Ok(result) => result,
+                Err(e) => Err(DcError::unexpected(e).into()),
+            }
+        };
+
+        return respond(&config, broadcasts, operation).await;
+    }
+
+    let job_id = job_id.unwrap_or_else(|| jobs.create(1));
+
+    tokio::spawn(async move {
+        let operation = async move { operation.await.map_err(ApiError::from) };
+        let result = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, operation).await.ok(),
+            None => Some(operation.await),
+        };
+
+        match &result {
+            Some(Err(e)) => error!(
+                "Job {job_id} failed with error: {:?}",
+                Redacted::new(e, config.redact_logs)
+            ),
+            None => warn!("Job {job_id} abandoned, deadline passed"),
+            Some(Ok(_)) => {}
+        }
+
+        // transactions the dropped operation already broadcast, they may still be mined
+        let abandoned: Vec<_> = match (&result, broadcasts) {
+            (None, Some(mut broadcasts)) => {
+                std::iter::from_fn(|| broadcasts.try_recv().ok()).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        jobs.update(&job_id, |job| {
+            job.items
+                .extend(abandoned.into_iter().map(|tx_hash| JobItem {
+                    tx_hash: Some(tx_hash),
+                    error: None,
+                }));
+            let result = result.as_ref().map(std::result::Result::as_ref);
+            finish_job(job, result, &config.error_templates)
+        });
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(JobCreatedResponse { job_id })).into_response())
+}
+
+/// Records the outcome of the job's operation, `None` if its deadline passed
+fn finish_job<T>(
+    job: &mut Job,
+    result: Option<std::result::Result<&T, &ApiError>>,
+    templates: &ErrorTemplates,
+) where
+    T: BatchResponse + Serialize,
+{
+    job.completed = 1;
+
+    match result {
+        Some(Ok(response)) => {
+            let tx_hashes = response.tx_hashes();
+            job.total = tx_hashes.len();
+            job.completed = tx_hashes.len();
+
+            job.items
+                .extend(tx_hashes.into_iter().map(|tx_hash| JobItem {
+                    tx_hash: Some(tx_hash),
+                    error: None,
+                }));
+            job.result = serde_json::to_value(response).ok();
+            job.status = JobStatus::Completed;
+        }
+        Some(Err(e)) => {
+            job.items.push(JobItem {
+                tx_hash: None,
+                error: Some(e.message(templates).into_owned()),
+            });
+            job.status = JobStatus::Failed;
+            job.error_status = Some(e.status_code());
+        }
+        None => {
+            job.items.push(JobItem {
+                tx_hash: None,
+                error: Some("deadline passed before the operation finished".into()),
+            });
+            job.status = JobStatus::Abandoned;
+            job.error_status = Some(StatusCode::GATEWAY_TIMEOUT);
+        }
+    }
+}
+
+/// Responds to a repeated request with the job of the original one
+fn replay(job: Job) -> Response {
+    let status = match job.status {
+        JobStatus::Pending => StatusCode::ACCEPTED,
+        JobStatus::Completed => StatusCode::OK,
+        JobStatus::Failed | JobStatus::Abandoned => job
+            .error_status
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    (status, Json(job)).into_response()
+}
+
+/// State of the requested chain, filling in the default chain if none was requested so that both hash the same
+fn resolve_chain(
+    state: &Arc<AppState>,
+    chain_id: &mut Option<u64>,
+) -> std::result::Result<Arc<AppState>, DcError> {
+    let state = state.on_chain(*chain_id)?;
+    *chain_id = Some(state.chain_id());
+
+    Ok(state)
+}
+
+/// Keccak256 of the endpoint and the parsed request. Recipients are sorted and amounts parsed, so
+/// requests differing only in formatting hash the same
+fn request_hash(endpoint: &str, request: &impl Serialize) -> B256 {
+    let mut bytes = endpoint.as_bytes().to_vec();
+    bytes.extend(serde_json::to_vec(request).unwrap_or_default());
+
+    keccak256(bytes)
+}
+
+/// Value of the `Idempotency-Key` header, or the `idempotencyKey` of the request body
+fn idempotency_key(headers: &HeaderMap, body_key: Option<String>) -> Option<String> {
+    headers
+        .get(&IDEMPOTENCY_KEY)
+        .and_then(|key| key.to_str().ok())
+        .map(Into::into)
+        .or(body_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use alloy::primitives::{Address, B256, U256};
+    use axum::{
+        body::Body,
+        extract::{FromRequest, Request},
+        http::{header, StatusCode},
+        middleware::from_fn_with_state,
+        response::Response,
+        routing::get,
+        Router,
+    };
+    use serde::Serialize;
+    use serde_json::json;
+    use tokio::sync::oneshot;
+    use tower::ServiceExt;
+
+    use super::{
+        api_routes, batch_broadcast_channel, dispatch_batch, ensure_max_recipients, request_hash,
+        resolve_chain, run_batch, ApiError, ApiJson, REQUEST_HASH,
+    };
+    use crate::{
+        dto::{BatchOptions, BatchResponse, DisperseEthRequest, FractionOrAmount},
+        middleware,
+        service::DcError,
+        state::AppState,
+        testing::{self, MockNode},
+    };
+
+    #[derive(Serialize)]
+    struct Sent(B256);
+
+    impl BatchResponse for Sent {
+        fn tx_hashes(&self) -> Vec<B256> {
+            vec![self.0]
+        }
+    }
+
+    fn sync() -> BatchOptions {
+        serde_json::from_value(json!({})).unwrap()
+    }
+
+    fn key() -> Option<(String, Option<Duration>)> {
+        Some(("key".into(), None))
+    }
+
+    async fn body(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_replay_a_completed_keyed_request_without_running_it_again() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+
+        let original = async { Ok::<_, DcError>(Sent(B256::repeat_byte(1))) };
+        let response = dispatch_batch(&state, sync(), key(), B256::ZERO, None, original)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let repeated = async { panic!("a replayed request must not run") };
+        let response = dispatch_batch::<Sent>(&state, sync(), key(), B256::ZERO, None, repeated)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let job = body(response).await;
+        assert_eq!(job["status"], "completed");
+        assert_eq!(job["result"], json!(B256::repeat_byte(1)));
+    }
+
+    #[tokio::test]
+    async fn should_replay_the_status_of_a_failed_keyed_request() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+
+        let failing = async { Err::<Sent, _>(DcError::NoSpenders) };
+        let result = dispatch_batch(&state, sync(), key(), B256::ZERO, None, failing).await;
+        assert!(matches!(result, Err(ApiError::InvalidRequest(_))));
+
+        let repeated = async { Ok(Sent(B256::ZERO)) };
+        let response = dispatch_batch(&state, sync(), key(), B256::ZERO, None, repeated)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_reject_a_key_reused_for_a_different_request() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+
+        let original = async { Ok::<_, DcError>(Sent(B256::ZERO)) };
+        dispatch_batch(&state, sync(), key(), B256::ZERO, None, original)
+            .await
+            .unwrap();
+
+        let different = async { Ok::<_, DcError>(Sent(B256::ZERO)) };
+        let hash = B256::repeat_byte(1);
+        let result = dispatch_batch(&state, sync(), key(), hash, None, different).await;
+        assert!(matches!(result, Err(ApiError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn should_finish_a_keyed_job_after_the_client_disconnects() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+        let (release, released) = oneshot::channel::<()>();
+
+        let operation = async move {
+            released.await.unwrap();
+            Ok::<_, DcError>(Sent(B256::ZERO))
+        };
+        let request = dispatch_batch(&state, sync(), key(), B256::ZERO, None, operation);
+        // the handler future is dropped like on a disconnect
+        assert!(tokio::time::timeout(Duration::from_millis(10), request)
+            .await
+            .is_err());
+        release.send(()).unwrap();
+
+        for _ in 0..100 {
+            let repeated = async { Ok::<_, DcError>(Sent(B256::repeat_byte(1))) };
+            let response = dispatch_batch(&state, sync(), key(), B256::ZERO, None, repeated)
+                .await
+                .unwrap();
+
+            if response.status() == StatusCode::OK {
+                return;
+            }
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        panic!("job was never finished");
+    }
+
+    #[tokio::test]
+    async fn should_reject_a_deadline_without_async() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+        let options = serde_json::from_value(json!({ "deadline": u64::MAX })).unwrap();
+
+        let operation = async { Ok::<_, DcError>(Sent(B256::ZERO)) };
+        let result = dispatch_batch(&state, options, None, B256::ZERO, None, operation).await;
+
+        assert!(matches!(result, Err(ApiError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn should_keep_the_hashes_broadcast_before_the_deadline() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+        let deadline = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 1;
+        let options: BatchOptions =
+            serde_json::from_value(json!({ "async": true, "deadline": deadline })).unwrap();
+        let tx_options = serde_json::from_value(json!({})).unwrap();
+        let (tx_options, broadcasts) = batch_broadcast_channel(tx_options, &options);
+
+        let operation = async move {
+            let _ = tx_options
+                .broadcast_sink
+                .unwrap()
+                .send(B256::repeat_byte(1));
+            std::future::pending::<std::result::Result<Sent, DcError>>().await
+        };
+        let response = dispatch_batch(&state, options, key(), B256::ZERO, broadcasts, operation)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+
+        let repeated = async { Ok::<_, DcError>(Sent(B256::ZERO)) };
+        let response = dispatch_batch(&state, sync(), key(), B256::ZERO, None, repeated)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        let job = body(response).await;
+        assert_eq!(job["status"], "abandoned");
+        assert_eq!(job["items"][0]["txHash"], json!(B256::repeat_byte(1)));
+    }
+
+    #[tokio::test]
+    async fn should_dedup_identical_requests_without_a_key() {
+        let node = MockNode::new(|_, _| None);
+        let state = testing::state(&node, json!({ "implicit_dedup_secs": 60 })).await;
+        let hash = B256::repeat_byte(1);
+
+        let original = async { Ok::<_, DcError>(Sent(B256::ZERO)) };
+        let response = run_batch(&state, sync(), None, hash, None, original)
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[&REQUEST_HASH], hash.to_string());
+
+        let repeated = async { panic!("a deduped request must not run") };
+        let response = run_batch::<Sent>(&state, sync(), None, hash, None, repeated)
+            .await
+            .unwrap();
+        assert_eq!(body(response).await["status"], "completed");
+
+        let (run, ran) = oneshot::channel();
+        let different = async move {
+            run.send(()).unwrap();
+            Ok::<_, DcError>(Sent(B256::ZERO))
+        };
+        run_batch(&state, sync(), None, B256::ZERO, None, different)
+            .await
+            .unwrap();
+        ran.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_reject_more_recipients_than_the_limit() {
+        let state =
+            testing::state(&MockNode::new(|_, _| None), json!({ "max_recipients": 2 })).await;
+
+        assert!(ensure_max_recipients(&state, "recipients", 2).is_ok());
+        let e = ensure_max_recipients(&state, "recipients", 3).unwrap_err();
+        assert!(matches!(
+            e,
+            DcError::TooManyRecipients {
+                field: "recipients",
+                count: 3,
+                max: 2
+            }
+        ));
+        assert!(matches!(ApiError::from(e), ApiError::InvalidRequest(_)));
+    }
+
+    #[tokio::test]
+    async fn should_parse_amounts_with_the_configured_parser() {
+        let node = MockNode::new(|_, _| None);
+        let request = |content_type: &str| {
+            Request::post("/")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(r#"{ "amount": "1e6" }"#))
+                .unwrap()
+        };
+        let parse = |state: Arc<AppState>, request: Request| async move {
+            ApiJson::<FractionOrAmount>::from_request(request, &state)
+                .await
+                .map(|ApiJson(amount)| amount)
+        };
+
+        let lenient = testing::state(&node, json!({})).await;
+        let amount = parse(lenient.clone(), request("application/json")).await;
+        assert!(
+            matches!(amount, Ok(FractionOrAmount::Amount { amount }) if amount == U256::from(1_000_000))
+        );
+        let rejection = parse(lenient, request("text/plain")).await.unwrap_err();
+        assert_eq!(rejection.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let strict = testing::state(&node, json!({ "strict_amounts": true })).await;
+        let rejection = parse(strict, request("application/json; charset=utf-8"))
+            .await
+            .unwrap_err();
+        assert_eq!(rejection.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_render_failures_with_the_configured_templates() {
+        let token = Address::repeat_byte(1);
+        let config = json!({
+            "error_templates": { "token_not_found": "{address} is not a supported token" },
+        });
+        let state = testing::state(&MockNode::new(|_, _| None), config).await;
+        let app =
+            Router::new()
+                .route(
+                    "/",
+                    get(move || async move {
+                        Err::<(), ApiError>(DcError::TokenNotFound(token).into())
+                    }),
+                )
+                .layer(from_fn_with_state(state, middleware::handle_failure));
+
+        let response = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body(response).await["error"],
+            format!("{token} is not a supported token")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_route_every_reported_endpoint() {
+        let state = testing::state(
+            &MockNode::new(|_, _| None),
+            json!({ "stats_token": "secret" }),
+        )
+        .await;
+        let app = api_routes(state).fallback(|| async { StatusCode::IM_A_TEAPOT });
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/capabilities").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let endpoints: Vec<String> =
+            serde_json::from_value(body(response).await["endpoints"].clone()).unwrap();
+        assert!(endpoints.contains(&"GET /stats".to_owned()));
+        assert!(!endpoints.contains(&"POST /testnet/fund".to_owned()));
+
+        for endpoint in endpoints {
+            let (method, path) = endpoint.split_once(' ').unwrap();
+            let uri = path
+                .replace(":address", &Address::repeat_byte(1).to_string())
+                .replace(":hash", &B256::repeat_byte(1).to_string())
+                .replace(":job_id", &uuid::Uuid::new_v4().to_string());
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+
+            let status = app.clone().oneshot(request).await.unwrap().status();
+
+            assert_ne!(status, StatusCode::IM_A_TEAPOT, "{endpoint} is not routed");
+            assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{endpoint}");
+        }
+    }
+
+    #[tokio::test]
+    async fn should_hash_the_default_chain_like_an_omitted_one() {
+        let state = testing::state(&MockNode::new(|_, _| None), json!({})).await;
+        let request = |chain_id: Option<u64>| -> DisperseEthRequest {
+            serde_json::from_value(json!({
+                "caller": testing::SIGNER,
+                "recipients": { Address::repeat_byte(1).to_string(): { "amount": "1" } },
+                "chainId": chain_id,
+            }))
+            .unwrap()
+        };
+
+        let hashes = [None, Some(testing::CHAIN_ID)].map(|chain_id| {
+            let mut request = request(chain_id);
+            resolve_chain(&state, &mut request.chain_id).unwrap();
+            request_hash("disperse-eth", &request)
+        });
+
+        assert_eq!(hashes[0], hashes[1]);
+        assert!(matches!(
+            resolve_chain(&state, &mut Some(1)),
+            Err(DcError::UnknownChain(1))
+        ));
+    }
+}
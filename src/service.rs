@@ -1,31 +1,78 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    num::NonZeroUsize,
+    time::{Duration, Instant},
+};
 
 use alloy::{
+    consensus::{Transaction, TxEnvelope},
     contract,
-    network::TransactionBuilder,
-    primitives::{Address, U256},
-    providers::{Provider, WalletProvider},
-    rpc::types::TransactionRequest,
+    network::{Network, TransactionBuilder},
+    primitives::{
+        keccak256,
+        utils::{format_ether, Unit},
+        Address, B256, U256,
+    },
+    providers::{PendingTransactionBuilder, Provider, SendableTx, WalletProvider},
+    rpc::types::{BlockId, Log, TransactionRequest},
     serde::WithOtherFields,
-    transports::{RpcError, TransportErrorKind},
+    sol_types::{self, SolCall},
+    transports::{http::reqwest, RpcError, TransportErrorKind, TransportResult},
 };
 use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::try_join;
 
 use alloy::contract::Error as ContractError;
-use tracing::instrument;
+use tracing::{info, instrument, warn};
 
+#[cfg(feature = "blobs")]
+use crate::dto::BlobFields;
 use crate::{
-    contracts::{DisperseCollectContract, Erc20Contract},
+    config::{
+        AllowanceRecheck, ContractRecipients, ErrorTemplates, NewContractPolicy, PendingRecovery,
+        PermitFailure, TestnetFaucet, ZeroAmounts,
+    },
+    contracts::{Erc20Contract, Erc20PermitContract, IDisperseOutcomes, IMulticall3, IERC20},
+    decode::decode_call,
     dto::{
-        ApproveRequest, CollectErc20Request, CollectErc20Response, DisperseCollectResponse,
-        DisperseErc20Request, DisperseErc20Response, DisperseEthRequest, DisperseEthResponse,
-        FractionOrAmount, FractionalAmount, TransactionResponse, TransferRequest,
+        AllowanceQuery, AllowanceResponse, ApprovePreviewRequest, ApprovePreviewResponse,
+        ApproveRequest, BalanceChange, BalanceDiff, BalanceQuery, BalanceResponse,
+        CallerUsageResponse, CapabilitiesResponse, ChunkResponse, CollectChunksResponse,
+        CollectErc20Request, CollectErc20Response, CollectErc20TargetRequest, CollectOrder,
+        CollectSpender, DecimalAmount, DisperseBreakdown, DisperseChunksResponse,
+        DisperseCollectResponse, DisperseErc20EqualRequest, DisperseErc20Request,
+        DisperseErc20Response, DisperseEthEqualRequest, DisperseEthRequest, DisperseEthResponse,
+        EnsureApprovalResponse, FailedTransfer, FractionOrAmount, FractionalAmount, GasParams,
+        Limits, QuoteRequest, QuoteResponse, QuoteStep, ReadinessCheck, RecipientBreakdown,
+        RequiredBalanceRequest, RequiredBalanceResponse, SkipReason, SkippedEntry,
+        SpenderBreakdown, StepQuote, TestnetFundRequest, TestnetFundResponse, TokenResponse,
+        TopUpResponse, TransactionResponse, TransactionStatus, TransactionStatusOptions,
+        TransactionStatusResponse, TransferRequest, TxCost, TxOptions, WhichSignerRequest,
+        WhichSignerResponse,
     },
-    state::DefaultProvider,
+    metrics,
+    nonce::NonceError,
+    notify::TransactionEvent,
+    recipient::Recipients,
+    state::{AppNetwork, AppState, DecimalsCache},
+    stats::{self, TransactionRecord},
+    templates,
+    transport::RpcTimeout,
 };
 
+/// Details of [`DcError::InsufficientAllowance`], boxed to keep the error small
+#[derive(Debug, Clone)]
+pub struct AllowanceShortfall {
+    pub owner: Address,
+    pub spender: Address,
+    pub token: Address,
+    pub required: U256,
+    pub allowance: U256,
+    pub chain_id: u64,
+}
+
 #[derive(Debug, Error)]
 pub enum DcError {
     #[error(
@@ -36,6 +83,35 @@ pub enum DcError {
         available: U256,
         address: Address,
     },
+    #[error(
+        "insufficient allowance for address {}, required: {}, allowed: {}, approve {} to spend the token",
+        .0.owner, .0.required, .0.allowance, .0.spender
+    )]
+    InsufficientAllowance(Box<AllowanceShortfall>),
+    #[error(
+        "spender {owner} allowed the contract only {allowance} of the required {required}, \
+         the spender has to approve the contract itself for {caller} to disperse its tokens"
+    )]
+    RelayedInsufficientAllowance {
+        owner: Address,
+        caller: Address,
+        required: U256,
+        allowance: U256,
+    },
+    #[error("caller {caller} can't disperse tokens of {spender}, relayed disperses are disabled")]
+    RelayedDisperse { caller: Address, spender: Address },
+    #[error(
+        "allowance of {address} changed before the transaction was sent, required: {required}, allowed: {allowance}"
+    )]
+    AllowanceChanged {
+        address: Address,
+        required: U256,
+        allowance: U256,
+    },
+    #[error("cannot collect {target}, only {available} available from the given spenders")]
+    TargetNotReached { target: U256, available: U256 },
+    #[error("invalid permit of {owner}: {reason}")]
+    InvalidPermit { owner: Address, reason: String },
     #[error(transparent)]
     InvalidFractionalAmount(#[from] InvalidFractionalAmountError),
     #[error("erc20 not found at address: {0}")]
@@ -46,17 +122,210 @@ pub enum DcError {
     Unexpected(#[source] anyhow::Error),
     #[error("no signer found for {0}")]
     SignerNotFound(Address),
+    #[error(
+        "decimal amount {value} has more fractional digits than the token's {decimals} decimals"
+    )]
+    InvalidDecimalAmount { value: DecimalAmount, decimals: u8 },
+    #[error("decimal amount {0} needs decimals, there's no token to read them from")]
+    MissingDecimals(DecimalAmount),
+    #[error("basis points of the recipients sum to {0}, more than 10000 (100%)")]
+    BasisPointsExceeded(u64),
+    #[error("weights of the recipients sum to zero")]
+    ZeroTotalWeight,
+    #[error("weights are only supported for disperse recipients")]
+    UnexpectedWeight,
+    #[error(
+        "no signer found for spenders: {}",
+        .0.iter().map(Address::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    SpenderSignersNotFound(Vec<Address>),
+    #[error(
+        "caller {0} is a contract account, only externally owned accounts can sign transactions"
+    )]
+    CallerIsContract(Address),
+    #[error("caller {caller} exceeded its gas budget, spent: {spent}, budget: {budget}")]
+    GasBudgetExceeded {
+        caller: Address,
+        spent: U256,
+        budget: U256,
+    },
+    #[error("recipient {0} is a contract account, only externally owned accounts are allowed")]
+    ContractRecipient(Address),
+    #[error("chain {0} is not configured")]
+    UnknownChain(u64),
+    #[error("recipient can't be the zero address")]
+    ZeroAddressRecipient,
+    #[error("recipient {0} is not a checksummed address")]
+    UnchecksummedRecipient(String),
+    #[error("recipients must not be empty")]
+    NoRecipients,
+    #[error("spenders must not be empty")]
+    NoSpenders,
+    #[error("{count} {field} submitted, at most {max} are allowed per request")]
+    TooManyRecipients {
+        field: &'static str,
+        count: usize,
+        max: usize,
+    },
+    #[error("recipient {0} is denylisted")]
+    DeniedRecipient(Address),
+    #[error("{address} is a contract deployed within the last {window} blocks")]
+    NewContract { address: Address, window: u64 },
+    #[error("transaction simulation reverted: {0}")]
+    SimulationReverted(String),
+    #[error("token {token} is not verified, code hash: {code_hash}")]
+    UnverifiedToken { token: Address, code_hash: B256 },
+    #[error("amount {amount} exceeds the total supply of token {token}: {total_supply}, check the token units")]
+    ExceedsTotalSupply {
+        token: Address,
+        amount: U256,
+        total_supply: U256,
+    },
+    #[error(
+        "no balance covers the requested amounts, fractions add up to the whole balance or more"
+    )]
+    BalanceUnreachable,
+    #[error("requested amounts add up to more than the maximum uint256 value")]
+    AmountOverflow,
+    #[error("requested amount for {0} is zero")]
+    ZeroAmount(Address),
+    #[error("remainderTo needs the whole balance to be split, the rest of a partial split stays with the sender")]
+    PartialSplitRemainder,
+    #[error("invalid fee override: {0}")]
+    InvalidFeeOverride(&'static str),
+    #[error("invalid blob fields: {0}")]
+    InvalidBlobFields(&'static str),
+    #[error(
+        "transaction {tx_hash} was mined but reverted{}",
+        .reason.as_deref().map(|reason| format!(": {reason}")).unwrap_or_default()
+    )]
+    TransactionReverted {
+        tx_hash: B256,
+        reason: Option<String>,
+    },
+    #[error("transaction {tx_hash} was sent but not confirmed in time, it may still be mined")]
+    ConfirmationTimeout { tx_hash: B256 },
+    /// Recipients were topped up with ETH before the operation failed
+    #[error("{source}")]
+    ToppedUp {
+        source: Box<DcError>,
+        top_up: Box<TopUpResponse>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    DisperseEth,
+    DisperseErc20,
+    CollectErc20,
+    TransferEth,
+    TransferErc20,
+    Approve,
+    Permit,
+}
+
+impl Operation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DisperseEth => "disperseEth",
+            Self::DisperseErc20 => "disperseErc20",
+            Self::CollectErc20 => "collectErc20",
+            Self::TransferEth => "transferEth",
+            Self::TransferErc20 => "transferErc20",
+            Self::Approve => "approve",
+            Self::Permit => "permit",
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 #[error("fraction {0} results in invalid or zero amount for corresponding balance")]
-pub struct InvalidFractionalAmountError(FractionalAmount);
+pub struct InvalidFractionalAmountError(pub FractionalAmount);
 
 impl DcError {
     pub fn unexpected(e: impl Into<anyhow::Error>) -> Self {
         Self::Unexpected(e.into())
     }
 
+    /// Whether the error is transient, e.g. the node was unreachable
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::ToppedUp { source, .. } => source.is_retryable(),
+            e => matches!(e, Self::Transport(_)),
+        }
+    }
+
+    /// Name of the variant, used as a metrics label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InsufficientFunds { .. } => "insufficient_funds",
+            Self::InsufficientAllowance(_) => "insufficient_allowance",
+            Self::RelayedInsufficientAllowance { .. } => "relayed_insufficient_allowance",
+            Self::RelayedDisperse { .. } => "relayed_disperse",
+            Self::AllowanceChanged { .. } => "allowance_changed",
+            Self::TargetNotReached { .. } => "target_not_reached",
+            Self::InvalidPermit { .. } => "invalid_permit",
+            Self::InvalidFractionalAmount(_) => "invalid_fractional_amount",
+            Self::TokenNotFound(_) => "token_not_found",
+            Self::Transport(_) => "transport",
+            Self::Unexpected(_) => "unexpected",
+            Self::SignerNotFound(_) => "signer_not_found",
+            Self::InvalidDecimalAmount { .. } => "invalid_decimal_amount",
+            Self::MissingDecimals(_) => "missing_decimals",
+            Self::BasisPointsExceeded(_) => "basis_points_exceeded",
+            Self::ZeroTotalWeight => "zero_total_weight",
+            Self::UnexpectedWeight => "unexpected_weight",
+            Self::SpenderSignersNotFound(_) => "spender_signers_not_found",
+            Self::CallerIsContract(_) => "caller_is_contract",
+            Self::GasBudgetExceeded { .. } => "gas_budget_exceeded",
+            Self::ContractRecipient(_) => "contract_recipient",
+            Self::UnknownChain(_) => "unknown_chain",
+            Self::ZeroAddressRecipient => "zero_address_recipient",
+            Self::UnchecksummedRecipient(_) => "unchecksummed_recipient",
+            Self::NoRecipients => "no_recipients",
+            Self::NoSpenders => "no_spenders",
+            Self::TooManyRecipients { .. } => "too_many_recipients",
+            Self::DeniedRecipient(_) => "denied_recipient",
+            Self::NewContract { .. } => "new_contract",
+            Self::SimulationReverted(_) => "simulation_reverted",
+            Self::UnverifiedToken { .. } => "unverified_token",
+            Self::ExceedsTotalSupply { .. } => "exceeds_total_supply",
+            Self::BalanceUnreachable => "balance_unreachable",
+            Self::AmountOverflow => "amount_overflow",
+            Self::ZeroAmount(_) => "zero_amount",
+            Self::PartialSplitRemainder => "partial_split_remainder",
+            Self::InvalidFeeOverride(_) => "invalid_fee_override",
+            Self::InvalidBlobFields(_) => "invalid_blob_fields",
+            Self::TransactionReverted { .. } => "transaction_reverted",
+            Self::ConfirmationTimeout { .. } => "confirmation_timeout",
+            Self::ToppedUp { source, .. } => source.kind(),
+        }
+    }
+
+    /// Message safe to return to clients, rendered from `templates` if one applies. Internal errors
+    /// are not exposed
+    pub fn client_message(&self, templates: &ErrorTemplates) -> String {
+        match self {
+            Self::Transport(_) | Self::Unexpected(_) | Self::SignerNotFound(_) => {
+                "internal server error".into()
+            }
+            Self::ToppedUp { source, .. } => source.client_message(templates),
+            e => templates::message(templates, e),
+        }
+    }
+
+    /// Attaches the top-up sent before the error to it
+    pub fn with_top_up(self, top_up: &Option<TopUpResponse>) -> Self {
+        match top_up {
+            Some(top_up) => Self::ToppedUp {
+                source: Box::new(self),
+                top_up: Box::new(top_up.clone()),
+            },
+            None => self,
+        }
+    }
+
     pub fn from_erc20_err(e: contract::Error, token_address: Address) -> Self {
         match e {
             ContractError::UnknownFunction(_) | ContractError::UnknownSelector(_) => {
@@ -77,302 +346,4989 @@ impl From<RpcError<TransportErrorKind>> for DcError {
     }
 }
 
+/// With `verbose`, balances are read at a pinned block and the response includes a [`DisperseBreakdown`]
 pub async fn disperse_eth(
-    provider: &DefaultProvider,
-    contract: &DisperseCollectContract,
-    request: DisperseEthRequest,
+    state: &AppState,
+    mut request: DisperseEthRequest,
+    verbose: bool,
+    options: TxOptions,
 ) -> Result<DisperseEthResponse, DcError> {
-    let available_balance = provider.get_balance(request.caller).await?;
+    let addresses: Vec<_> = request
+        .recipients
+        .keys()
+        .copied()
+        .chain(request.remainder_to)
+        .collect();
+    if request.recipients.is_empty() {
+        return Err(DcError::NoRecipients);
+    }
+    validate_checksums(state, &request.recipients)?;
+    validate_recipients(state, &addresses)?;
+    resolve_decimals(state, None, request.recipients.values_mut()).await?;
+    let (provider, contract) = (state.provider(), state.contract());
+    let mut warnings = check_new_contracts(state, addresses.iter().copied()).await?;
+    let contracts = get_contract_recipients(state, addresses).await?;
+    let remainder_to = request.remainder_to.filter(|a| !contracts.contains(a));
+    let block_number = get_pinned_block(state, verbose).await?;
+
+    let balance = provider
+        .get_balance(request.caller)
+        .block_id(block_id(block_number))
+        .await?;
+
+    let requested = || {
+        request
+            .recipients
+            .iter()
+            .filter(|(a, _)| !contracts.contains(a))
+            .map(|(a, v)| (*a, *v))
+    };
+
+    // gas is estimated with the amounts of the whole balance, the disperse then spends what's left
+    let zero_amounts = state.config().zero_amounts;
+    let estimate = construct_disperse_recipients(
+        request.caller,
+        balance,
+        requested(),
+        remainder_to,
+        zero_amounts,
+    )?;
+    let estimate_tx = contract
+        .disperseEth(estimate.addresses.clone(), estimate.amounts)
+        .value(estimate.total)
+        .into_transaction_request();
+    let reservation = reserve_gas(
+        state,
+        &estimate_tx,
+        request.caller,
+        estimate.addresses.len(),
+        &options,
+    )
+    .await?;
+    let available_balance = balance.saturating_sub(reservation.cost());
 
-    let (addresses, amounts) = construct_disperse_recipients(
+    let mut recipients = construct_disperse_recipients(
         request.caller,
         available_balance,
-        request.recipients.into_iter(),
+        requested(),
+        remainder_to,
+        zero_amounts,
     )?;
+    recipients.skip_all(contracts, SkipReason::IsContract);
 
-    let tx = contract
-        .disperseEth(addresses.clone(), amounts.clone())
-        .value(amounts.iter().sum())
+    let mut tx = contract
+        .disperseEth(recipients.addresses.clone(), recipients.amounts.clone())
+        .value(recipients.total)
         .into_transaction_request();
+    reservation.apply(&mut tx);
+
+    let include_balance = options.include_balance;
+    let mut tx_response = send_transaction(
+        state,
+        tx,
+        request.caller,
+        Operation::DisperseEth,
+        recipients.addresses.len(),
+        options,
+    )
+    .await?;
 
-    let tx_response = send_transaction(provider, tx, request.caller).await?;
+    let diff = if tx_response.simulated {
+        let transfers = recipients.transfers_from(request.caller);
+        Some(predict_balance_diff(state, None, &transfers).await?)
+    } else {
+        None
+    };
+
+    let low_balance_wei = state.config().low_balance_warning_wei.map(U256::from);
+    if (include_balance || low_balance_wei.is_some()) && !tx_response.simulated {
+        let balance = provider.get_balance(request.caller).await?;
+
+        if let Some(threshold) = low_balance_wei.filter(|threshold| balance < *threshold) {
+            warnings.push(format!(
+                "caller balance after the disperse is {balance} wei, below {threshold} wei, \
+                 further transactions may not be able to pay for gas"
+            ));
+        }
+        if include_balance {
+            tx_response.balance_after = Some(balance);
+        }
+    }
+
+    let breakdown = block_number.map(|block_number| {
+        recipients.breakdown(available_balance, block_number, &request.recipients)
+    });
+    let skipped = recipients.skipped.clone();
+    let mut transfers = recipients.into_transfers();
+    let failed = take_failed_transfers(state, &tx_response.logs, Address::ZERO, &mut transfers);
 
     Ok(DisperseEthResponse(DisperseCollectResponse {
-        transfers: BTreeMap::from_iter(addresses.into_iter().zip(amounts)),
+        breakdown,
+        spenders: None,
+        skipped,
+        transfers,
+        failed,
         tx: tx_response,
+        top_up: None,
+        diff,
+        warnings,
     }))
 }
 
-pub async fn disperse_erc20(
-    provider: &DefaultProvider,
-    contract: &DisperseCollectContract,
-    request: DisperseErc20Request,
-) -> Result<DisperseErc20Response, DcError> {
-    let token = Erc20Contract::new(request.token, provider.clone());
-
-    let (balance, allowance) = try_join!(
-        async {
-            token
-                .allowance(request.spender, *contract.address())
-                .call()
-                .await
-        },
-        async { token.balanceOf(request.spender).call().await }
-    )
-    .map(|(a, b)| (a._0, b._0))
-    .map_err(|e: alloy::contract::Error| DcError::from_erc20_err(e, request.token))?;
+/// Same as [`disperse_eth`], split into `disperseEth` transactions of at most `chunk_size` recipients.
+///
+/// Gas is reserved for every chunk and amounts are computed once up front from what's left of the balance.
+/// Chunks are sent one by one, once one fails the remaining ones are not sent.
+pub async fn disperse_eth_chunked(
+    state: &AppState,
+    mut request: DisperseEthRequest,
+    chunk_size: NonZeroUsize,
+    options: TxOptions,
+) -> Result<DisperseChunksResponse, DcError> {
+    let addresses: Vec<_> = request
+        .recipients
+        .keys()
+        .copied()
+        .chain(request.remainder_to)
+        .collect();
+    if request.recipients.is_empty() {
+        return Err(DcError::NoRecipients);
+    }
+    validate_checksums(state, &request.recipients)?;
+    validate_recipients(state, &addresses)?;
+    resolve_decimals(state, None, request.recipients.values_mut()).await?;
+    let (provider, contract) = (state.provider(), state.contract());
+    let warnings = check_new_contracts(state, addresses.iter().copied()).await?;
+    let contracts = get_contract_recipients(state, addresses).await?;
+    let remainder_to = request.remainder_to.filter(|a| !contracts.contains(a));
+    let balance = provider.get_balance(request.caller).await?;
 
-    let available_balance = balance.min(allowance);
+    let requested = || {
+        request
+            .recipients
+            .iter()
+            .filter(|(a, _)| !contracts.contains(a))
+            .map(|(a, v)| (*a, *v))
+    };
 
-    let (addresses, amounts) = construct_disperse_recipients(
-        request.spender,
-        available_balance,
-        request.recipients.into_iter(),
+    let zero_amounts = state.config().zero_amounts;
+    let estimate = construct_disperse_recipients(
+        request.caller,
+        balance,
+        requested(),
+        remainder_to,
+        zero_amounts,
     )?;
+    let build_tx = |addresses: &[Address], amounts: &[U256]| {
+        let total = amounts.iter().fold(U256::ZERO, |acc, amount| acc + amount);
 
-    let tx = contract
-        .disperseERC20(
-            request.spender,
-            request.token,
-            addresses.clone(),
-            amounts.clone(),
-        )
-        .into_transaction_request();
+        contract
+            .disperseEth(addresses.to_vec(), amounts.to_vec())
+            .value(total)
+            .into_transaction_request()
+    };
 
-    let tx_response = send_transaction(provider, tx, request.caller).await?;
+    // chunks may shift once the amounts are recomputed with less balance, so every chunk gets the
+    // largest estimate
+    let mut reservation: Option<GasReservation> = None;
+    let estimate_chunks = estimate
+        .addresses
+        .chunks(chunk_size.get())
+        .zip(estimate.amounts.chunks(chunk_size.get()));
 
-    Ok(DisperseErc20Response(DisperseCollectResponse {
-        tx: tx_response,
-        transfers: BTreeMap::from_iter(addresses.into_iter().zip(amounts)),
-    }))
-}
+    for (addresses, amounts) in estimate_chunks {
+        let tx = build_tx(addresses, amounts);
+        let chunk = reserve_gas(state, &tx, request.caller, addresses.len(), &options).await?;
 
-#[instrument(skip(provider, contract), target = "collect_erc20")]
-pub async fn collect_erc20(
-    provider: &DefaultProvider,
-    contract: &DisperseCollectContract,
-    request: CollectErc20Request,
-) -> Result<CollectErc20Response, DcError> {
-    let token = Erc20Contract::new(request.token, provider.clone());
+        reservation = Some(match reservation {
+            Some(reservation) if reservation.gas_limit >= chunk.gas_limit => reservation,
+            _ => chunk,
+        });
+    }
 
-    let balances = try_join_all(request.spenders.keys().cloned().map(|owner| {
-        let token = token.clone();
-        async move {
-            try_join!(
-                // nested async blocks because part before .call() is borrowed
-                async { token.allowance(owner, *contract.address()).call().await },
-                async { token.balanceOf(owner).call().await }
-            )
+    let chunk_count = estimate.addresses.len().div_ceil(chunk_size.get());
+    let gas_cost = reservation
+        .as_ref()
+        .map(|reservation| reservation.cost() * U256::from(chunk_count))
+        .unwrap_or_default();
+
+    let mut recipients = construct_disperse_recipients(
+        request.caller,
+        balance.saturating_sub(gas_cost),
+        requested(),
+        remainder_to,
+        zero_amounts,
+    )?;
+    recipients.skip_all(contracts, SkipReason::IsContract);
+
+    let build_tx = |addresses: &[Address], amounts: &[U256]| {
+        let mut tx = build_tx(addresses, amounts);
+        if let Some(reservation) = &reservation {
+            reservation.apply(&mut tx);
         }
-    }))
-    .await
-    .map_err(|e| DcError::from_erc20_err(e, request.token))?
-    .into_iter()
-    .map(|(a, b)| (a._0, b._0));
+        tx
+    };
 
-    let mut addresses = Vec::with_capacity(request.spenders.len());
-    let mut amounts = Vec::with_capacity(request.spenders.len());
+    let mut chunks = submit_disperse_chunks(
+        state,
+        request.caller,
+        &recipients,
+        chunk_size,
+        Operation::DisperseEth,
+        build_tx,
+        options,
+    )
+    .await?;
 
-    for ((allowance, balance), (address, amount)) in balances.zip(request.spenders.into_iter()) {
-        let actual_amount = normalize_amount(amount, balance)?;
+    let (transfers, failed) = settle_chunks(state, &mut chunks, Address::ZERO);
 
-        let available = allowance.min(balance);
+    Ok(DisperseChunksResponse {
+        chunks,
+        transfers,
+        skipped: recipients.skipped,
+        failed,
+        top_up: None,
+        warnings,
+    })
+}
 
-        if actual_amount > available {
-            return Err(DcError::InsufficientFunds {
-                required: actual_amount,
-                available,
-                address,
+/// Sends `recipients` in transactions of at most `chunk_size` recipients built by `build_tx`, one by one.
+///
+/// Fails if the first chunk fails, as nothing was sent then. Once a later chunk fails, the remaining
+/// ones are not sent and reported with an error.
+async fn submit_disperse_chunks(
+    state: &AppState,
+    caller: Address,
+    recipients: &DisperseRecipients,
+    chunk_size: NonZeroUsize,
+    operation: Operation,
+    build_tx: impl Fn(&[Address], &[U256]) -> WithOtherFields<TransactionRequest>,
+    options: TxOptions,
+) -> Result<Vec<ChunkResponse>, DcError> {
+    let chunks = recipients
+        .addresses
+        .chunks(chunk_size.get())
+        .zip(recipients.amounts.chunks(chunk_size.get()));
+
+    let mut responses = Vec::with_capacity(recipients.addresses.len().div_ceil(chunk_size.get()));
+    let mut failed = false;
+
+    for (addresses, amounts) in chunks {
+        let requested = BTreeMap::from_iter(addresses.iter().cloned().zip(amounts.iter().cloned()));
+
+        if failed {
+            responses.push(ChunkResponse {
+                tx: None,
+                transfers: requested,
+                error: Some("not sent, a previous chunk failed".into()),
             });
+            continue;
         }
 
-        addresses.push(address);
-        amounts.push(actual_amount);
+        let tx = build_tx(addresses, amounts);
+        let result = send_transaction(
+            state,
+            tx,
+            caller,
+            operation,
+            addresses.len(),
+            options.clone(),
+        )
+        .await;
+
+        match result {
+            Ok(tx) => responses.push(ChunkResponse {
+                tx: Some(tx),
+                transfers: requested,
+                error: None,
+            }),
+            // nothing was sent yet
+            Err(e) if responses.is_empty() => return Err(e),
+            Err(e) => {
+                warn!("Disperse chunk failed, remaining chunks are not sent: {e}");
+                failed = true;
+
+                responses.push(ChunkResponse {
+                    tx: None,
+                    transfers: requested,
+                    error: Some(e.client_message(&state.config().error_templates)),
+                });
+            }
+        }
+    }
+
+    Ok(responses)
+}
+
+/// Moves transfers the contract reported as failed out of the sent chunks and merges the remaining ones
+fn settle_chunks(
+    state: &AppState,
+    chunks: &mut [ChunkResponse],
+    token: Address,
+) -> (BTreeMap<Address, U256>, Vec<FailedTransfer>) {
+    let mut transfers = BTreeMap::new();
+    let mut failed = Vec::new();
+
+    for chunk in chunks {
+        let Some(tx) = &chunk.tx else {
+            continue;
+        };
+
+        failed.extend(take_failed_transfers(
+            state,
+            &tx.logs,
+            token,
+            &mut chunk.transfers,
+        ));
+        transfers.extend(
+            chunk
+                .transfers
+                .iter()
+                .map(|(address, amount)| (*address, *amount)),
+        );
     }
 
+    (transfers, failed)
+}
+
+/// See [`disperse_eth`] for `verbose`
+pub async fn disperse_erc20(
+    state: &AppState,
+    mut request: DisperseErc20Request,
+    verbose: bool,
+    from_events: bool,
+    options: TxOptions,
+) -> Result<DisperseErc20Response, DcError> {
+    let contract = state.contract();
+    let Erc20DispersePlan {
+        token,
+        quote,
+        recipients,
+        warnings,
+    } = plan_disperse_erc20(state, &mut request, verbose).await?;
+
     let tx = contract
-        .collectERC20(
+        .disperseERC20(
+            request.spender,
             request.token,
-            request.recipient,
-            addresses.clone(),
-            amounts.clone(),
+            recipients.addresses.clone(),
+            recipients.amounts.clone(),
         )
         .into_transaction_request();
 
-    let tx_response = send_transaction(provider, tx, request.caller).await?;
+    let top_up =
+        top_up_recipients(state, request.caller, &recipients.addresses, &tx, &options).await?;
 
-    Ok(CollectErc20Response(DisperseCollectResponse {
+    let tx_response = send_transaction(
+        state,
+        tx,
+        request.caller,
+        Operation::DisperseErc20,
+        recipients.addresses.len(),
+        options,
+    )
+    .await
+    .map_err(|e| e.with_top_up(&top_up))?;
+
+    let breakdown = quote.block_number.map(|block_number| {
+        recipients.breakdown(quote.available(), block_number, &request.recipients)
+    });
+    let skipped = recipients.skipped.clone();
+
+    let diff = if tx_response.simulated {
+        let transfers = recipients.transfers_from(request.spender);
+        Some(predict_balance_diff(state, Some(&token), &transfers).await?)
+    } else {
+        None
+    };
+
+    let mut transfers = if from_events && !tx_response.simulated {
+        transfers_from_logs(&tx_response.logs, request.token, |t| {
+            (t.from == request.spender).then_some(t.to)
+        })
+    } else {
+        recipients.into_transfers()
+    };
+    let failed = take_failed_transfers(state, &tx_response.logs, request.token, &mut transfers);
+
+    Ok(DisperseErc20Response(DisperseCollectResponse {
         tx: tx_response,
-        transfers: BTreeMap::from_iter(addresses.into_iter().zip(amounts)),
+        breakdown,
+        spenders: None,
+        skipped,
+        transfers,
+        failed,
+        top_up,
+        diff,
+        warnings,
     }))
 }
 
-pub async fn transfer(
-    provider: &DefaultProvider,
-    request: TransferRequest,
-) -> Result<TransactionResponse, DcError> {
-    match request.token {
-        Some(addr) => {
-            transfer_erc20(
-                provider,
-                request.caller,
-                request.recipient,
-                addr,
-                request.value,
+/// Same as [`disperse_erc20`], split into `disperseERC20` transactions of at most `chunk_size` recipients.
+///
+/// Amounts are computed once up front. Chunks are sent one by one, once one fails the remaining ones
+/// are not sent.
+pub async fn disperse_erc20_chunked(
+    state: &AppState,
+    mut request: DisperseErc20Request,
+    chunk_size: NonZeroUsize,
+    from_events: bool,
+    options: TxOptions,
+) -> Result<DisperseChunksResponse, DcError> {
+    let contract = state.contract();
+    let Erc20DispersePlan {
+        recipients,
+        warnings,
+        ..
+    } = plan_disperse_erc20(state, &mut request, false).await?;
+
+    let build_tx = |addresses: &[Address], amounts: &[U256]| {
+        contract
+            .disperseERC20(
+                request.spender,
+                request.token,
+                addresses.to_vec(),
+                amounts.to_vec(),
             )
-            .await
+            .into_transaction_request()
+    };
+
+    let disperse = build_tx(&recipients.addresses, &recipients.amounts);
+    let top_up = top_up_recipients(
+        state,
+        request.caller,
+        &recipients.addresses,
+        &disperse,
+        &options,
+    )
+    .await?;
+
+    let mut chunks = submit_disperse_chunks(
+        state,
+        request.caller,
+        &recipients,
+        chunk_size,
+        Operation::DisperseErc20,
+        build_tx,
+        options,
+    )
+    .await
+    .map_err(|e| e.with_top_up(&top_up))?;
+
+    if from_events {
+        for chunk in &mut chunks {
+            if let Some(tx) = chunk.tx.as_ref().filter(|tx| !tx.simulated) {
+                chunk.transfers = transfers_from_logs(&tx.logs, request.token, |t| {
+                    (t.from == request.spender).then_some(t.to)
+                });
+            }
         }
-        None => transfer_eth(provider, request.caller, request.recipient, request.value).await,
     }
-}
 
-pub async fn transfer_eth(
-    provider: &DefaultProvider,
-    caller: Address,
-    recipient: Address,
-    amount: FractionOrAmount,
-) -> Result<TransactionResponse, DcError> {
-    let available_balance = provider.get_balance(caller).await?;
+    let (transfers, failed) = settle_chunks(state, &mut chunks, request.token);
 
-    let actual_amount = normalize_amount(amount, available_balance)?;
+    Ok(DisperseChunksResponse {
+        chunks,
+        transfers,
+        skipped: recipients.skipped,
+        failed,
+        top_up,
+        warnings,
+    })
+}
 
-    if actual_amount > available_balance {
-        return Err(DcError::InsufficientFunds {
-            required: actual_amount,
-            available: available_balance,
-            address: caller,
-        });
+/// Turns an equal split into a regular ETH disperse, see [`split_equally`]
+pub fn equal_disperse_eth(request: DisperseEthEqualRequest) -> Result<DisperseEthRequest, DcError> {
+    let mut total = request.total;
+    if let FractionOrAmount::Decimal { decimals, .. } = &mut total {
+        decimals.get_or_insert(Unit::ETHER.get());
     }
+    let (split, remainder_to) =
+        split_equally(request.recipients.to_vec(), total, request.remainder_to)?;
 
-    let tx = TransactionRequest::default()
-        .value(actual_amount)
-        .to(recipient);
+    Ok(DisperseEthRequest {
+        recipients: request.recipients.replace(split),
+        remainder_to,
+        caller: request.caller,
+        chain_id: request.chain_id,
+        idempotency_key: request.idempotency_key,
+        #[cfg(feature = "blobs")]
+        blob: None,
+    })
+}
 
-    let tx_response = send_transaction(provider, WithOtherFields::new(tx), caller).await?;
+/// Turns an equal split into a regular ERC20 disperse, see [`split_equally`]
+pub async fn equal_disperse_erc20(
+    state: &AppState,
+    mut request: DisperseErc20EqualRequest,
+) -> Result<DisperseErc20Request, DcError> {
+    resolve_decimals(state, Some(request.token), [&mut request.total]).await?;
+    let (split, remainder_to) = split_equally(
+        request.recipients.to_vec(),
+        request.total,
+        request.remainder_to,
+    )?;
 
-    Ok(tx_response)
+    Ok(DisperseErc20Request {
+        recipients: request.recipients.replace(split),
+        remainder_to,
+        token: request.token,
+        spender: request.spender,
+        caller: request.caller,
+        chain_id: request.chain_id,
+        idempotency_key: request.idempotency_key,
+        #[cfg(feature = "blobs")]
+        blob: None,
+    })
 }
 
-pub async fn transfer_erc20(
-    provider: &DefaultProvider,
-    caller: Address,
-    recipient: Address,
-    token_address: Address,
-    amount: FractionOrAmount,
-) -> Result<TransactionResponse, DcError> {
-    let token = Erc20Contract::new(token_address, provider.clone());
-    let balance = get_erc20_balance(&token, caller).await?;
+/// Divides `total` by the number of distinct recipients.
+///
+/// An absolute total is split right away, the remainder of the division going to `remainder_to` or the
+/// first recipient. A fraction of the balance becomes an equal fraction per recipient. If it's the whole
+/// balance, the rounding dust is passed on as the disperse's `remainder_to`, otherwise it stays with the sender
+/// and an explicit `remainder_to` is rejected.
+fn split_equally(
+    mut recipients: Vec<Address>,
+    total: FractionOrAmount,
+    remainder_to: Option<Address>,
+) -> Result<(BTreeMap<Address, FractionOrAmount>, Option<Address>), DcError> {
+    let mut seen = BTreeSet::new();
+    recipients.retain(|recipient| seen.insert(*recipient));
 
-    let actual_amount = normalize_amount(amount, balance)?;
+    let first = *recipients.first().ok_or(DcError::NoRecipients)?;
 
-    if actual_amount > balance {
-        return Err(DcError::InsufficientFunds {
-            required: actual_amount,
-            available: balance,
-            address: caller,
-        });
-    }
+    let total = match total {
+        FractionOrAmount::Amount { amount } => amount,
+        FractionOrAmount::Decimal { value, decimals } => decimal_to_base_units(value, decimals)?,
+        FractionOrAmount::Weighted { .. } => return Err(DcError::UnexpectedWeight),
+        FractionOrAmount::Fraction(f) => return split_fraction(recipients, f, remainder_to),
+        FractionOrAmount::BasisPoints { bps } => {
+            let f = FractionalAmount::from_basis_points(bps);
+            return split_fraction(recipients, f, remainder_to);
+        }
+    };
+    let (share, remainder) = total.div_rem(U256::from(recipients.len()));
 
-    let tx = token
-        .transfer(recipient, actual_amount)
-        .into_transaction_request();
+    let mut split: BTreeMap<_, _> = recipients
+        .into_iter()
+        .map(|recipient| (recipient, share))
+        .collect();
+    if !remainder.is_zero() {
+        *split.entry(remainder_to.unwrap_or(first)).or_default() += remainder;
+    }
 
-    let tx_response = send_transaction(provider, tx, caller).await?;
+    let recipients = split
+        .into_iter()
+        .map(|(recipient, amount)| (recipient, FractionOrAmount::Amount { amount }))
+        .collect();
 
-    Ok(tx_response)
+    Ok((recipients, None))
 }
 
-pub async fn approve(
-    provider: &DefaultProvider,
-    request: ApproveRequest,
-) -> Result<TransactionResponse, DcError> {
-    let token = Erc20Contract::new(request.token, provider.clone());
-
-    let balance = get_erc20_balance(&token, request.caller).await?;
-    let actual_amount = normalize_amount(request.amount, balance)?;
-
-    let tx = token
-        .approve(request.spender, actual_amount)
-        .into_transaction_request();
+fn split_fraction(
+    recipients: Vec<Address>,
+    total: FractionalAmount,
+    remainder_to: Option<Address>,
+) -> Result<(BTreeMap<Address, FractionOrAmount>, Option<Address>), DcError> {
+    let whole_balance = total.fraction >= total.units;
+    if !whole_balance && remainder_to.is_some() {
+        return Err(DcError::PartialSplitRemainder);
+    }
 
-    let tx_response = send_transaction(provider, tx, request.caller).await?;
+    let count = U256::from(recipients.len());
+    let share = FractionOrAmount::Fraction(FractionalAmount {
+        fraction: total.fraction,
+        units: total
+            .units
+            .checked_mul(count)
+            .ok_or(DcError::AmountOverflow)?,
+    });
+    let remainder_to = remainder_to.or(recipients.first().copied());
 
-    Ok(tx_response)
+    Ok((
+        recipients.into_iter().map(|r| (r, share)).collect(),
+        remainder_to.filter(|_| whole_balance),
+    ))
 }
 
-async fn get_erc20_balance(token: &Erc20Contract, address: Address) -> Result<U256, DcError> {
-    token
-        .balanceOf(address)
-        .call()
-        .await
-        .map(|b| b._0)
-        .map_err(|e| DcError::from_erc20_err(e, *token.address()))
+/// Everything [`disperse_erc20`] and [`disperse_erc20_chunked`] need before sending
+struct Erc20DispersePlan {
+    token: Erc20Contract,
+    quote: Erc20Quote,
+    recipients: DisperseRecipients,
+    warnings: Vec<String>,
 }
 
-fn normalize_amount(
-    amount: FractionOrAmount,
-    available_balance: U256,
-) -> Result<U256, InvalidFractionalAmountError> {
-    let actual_amount = match amount {
-        FractionOrAmount::Amount { amount } => amount,
-        FractionOrAmount::Fraction(f) => f
-            .to_absolute(available_balance)
-            .filter(|a| *a != U256::ZERO)
-            .ok_or(InvalidFractionalAmountError(f))?,
+/// Validates an ERC20 disperse and computes its amounts
+async fn plan_disperse_erc20(
+    state: &AppState,
+    request: &mut DisperseErc20Request,
+    verbose: bool,
+) -> Result<Erc20DispersePlan, DcError> {
+    let recipient_addresses: Vec<_> = request
+        .recipients
+        .keys()
+        .copied()
+        .chain(request.remainder_to)
+        .collect();
+    if request.recipients.is_empty() {
+        return Err(DcError::NoRecipients);
+    }
+    validate_checksums(state, &request.recipients)?;
+    validate_recipients(state, &recipient_addresses)?;
+    let relayed = ensure_relay_allowed(state, request.caller, request.spender)?;
+    let (provider, contract) = (state.provider(), state.contract());
+    ensure_verified_token(state, request.token).await?;
+    resolve_decimals(state, Some(request.token), request.recipients.values_mut()).await?;
+    let token = Erc20Contract::new(request.token, provider.clone());
+    ensure_within_supply(state, &token, absolute_amounts(request.recipients.values())).await?;
+    let addresses = std::iter::once(request.token).chain(recipient_addresses.iter().copied());
+    let warnings = check_new_contracts(state, addresses).await?;
+    let contracts = get_contract_recipients(state, recipient_addresses).await?;
+    let remainder_to = request.remainder_to.filter(|a| !contracts.contains(a));
+
+    let build = |quote: &Erc20Quote| {
+        let mut recipients = construct_disperse_recipients(
+            request.spender,
+            quote.available(),
+            request
+                .recipients
+                .iter()
+                .filter(|(a, _)| !contracts.contains(a))
+                .map(|(a, v)| (*a, *v)),
+            remainder_to,
+            state.config().zero_amounts,
+        )
+        .map_err(|e| match e {
+            DcError::InsufficientFunds { required, .. } if required <= quote.balance && relayed => {
+                DcError::RelayedInsufficientAllowance {
+                    owner: request.spender,
+                    caller: request.caller,
+                    required,
+                    allowance: quote.allowance,
+                }
+            }
+            DcError::InsufficientFunds { required, .. } if required <= quote.balance => {
+                DcError::InsufficientAllowance(Box::new(AllowanceShortfall {
+                    owner: request.spender,
+                    spender: *contract.address(),
+                    token: request.token,
+                    required,
+                    allowance: quote.allowance,
+                    chain_id: state.chain_id(),
+                }))
+            }
+            e => e,
+        })?;
+        recipients.skip_all(contracts.clone(), SkipReason::IsContract);
+
+        Ok::<_, DcError>(recipients)
     };
 
-    Ok(actual_amount)
-}
+    let mut quote = get_erc20_quote(state, &token, request.spender, verbose).await?;
+    let mut recipients = build(&quote)?;
 
-async fn send_transaction(
-    provider: &DefaultProvider,
-    mut tx: WithOtherFields<TransactionRequest>,
-    signer: Address,
-) -> Result<TransactionResponse, DcError> {
-    if !provider.has_signer_for(&signer) {
-        return Err(DcError::SignerNotFound(signer));
+    if quote.is_stale(state) {
+        warn!(
+            "Balance of {} is stale, reading it again",
+            state.redacted(request.spender)
+        );
+
+        quote = get_erc20_quote(state, &token, request.spender, verbose).await?;
+        recipients = build(&quote)?;
     }
 
-    tx.set_from(signer);
+    Ok(Erc20DispersePlan {
+        token,
+        quote,
+        recipients,
+        warnings,
+    })
+}
+
+/// With `verbose`, the response includes the balance and allowance of every spender
+#[instrument(skip_all, fields(request = ?state.redacted(&request)), target = "collect_erc20")]
+pub async fn collect_erc20(
+    state: &AppState,
+    request: CollectErc20Request,
+    verbose: bool,
+    from_events: bool,
+    options: TxOptions,
+) -> Result<CollectErc20Response, DcError> {
+    if request.spenders.is_empty() {
+        return Err(DcError::NoSpenders);
+    }
+    validate_checksums(state, &request.recipient)?;
+    validate_recipients(state, [&*request.recipient])?;
+    ensure_verified_token(state, request.token).await?;
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+    let amounts = absolute_amounts(request.spenders.values().map(|spender| &spender.amount));
+    ensure_within_supply(state, &token, amounts).await?;
+    let warnings = check_new_contracts(state, [request.token, *request.recipient]).await?;
+
+    let mut plan = plan_collect(
+        state,
+        &token,
+        request.caller,
+        request.spenders,
+        verbose,
+        &options,
+    )
+    .await?;
+    plan.warnings = warnings;
+
+    submit_collect(
+        state,
+        &token,
+        request.caller,
+        *request.recipient,
+        plan,
+        from_events,
+        options,
+    )
+    .await
+}
+
+/// Same as [`collect_erc20`], split into `collectERC20` transactions of at most `chunk_size` spenders.
+///
+/// Balances and allowances are read once up front. Chunks are sent one by one, once one fails
+/// the remaining ones are not sent.
+#[instrument(skip_all, fields(request = ?state.redacted(&request)), target = "collect_erc20")]
+pub async fn collect_erc20_chunked(
+    state: &AppState,
+    request: CollectErc20Request,
+    chunk_size: NonZeroUsize,
+    verbose: bool,
+    from_events: bool,
+    options: TxOptions,
+) -> Result<CollectChunksResponse, DcError> {
+    if request.spenders.is_empty() {
+        return Err(DcError::NoSpenders);
+    }
+    validate_checksums(state, &request.recipient)?;
+    validate_recipients(state, [&*request.recipient])?;
+    ensure_verified_token(state, request.token).await?;
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+    let amounts = absolute_amounts(request.spenders.values().map(|spender| &spender.amount));
+    ensure_within_supply(state, &token, amounts).await?;
+    let warnings = check_new_contracts(state, [request.token, *request.recipient]).await?;
+
+    let mut plan = plan_collect(
+        state,
+        &token,
+        request.caller,
+        request.spenders,
+        verbose,
+        &options,
+    )
+    .await?;
+    plan.warnings = warnings;
+    let mut spenders = plan.spenders;
 
-    let access_list = provider.create_access_list(&tx).await?.access_list;
+    let chunks = plan
+        .addresses
+        .chunks(chunk_size.get())
+        .zip(plan.amounts.chunks(chunk_size.get()));
 
-    tx.set_access_list(access_list);
+    let mut responses = Vec::with_capacity(plan.addresses.len().div_ceil(chunk_size.get()));
+    let mut failed = false;
 
-    let receipt = provider.send_transaction(tx).await?.get_receipt().await?;
+    for (addresses, amounts) in chunks {
+        let requested = BTreeMap::from_iter(addresses.iter().cloned().zip(amounts.iter().cloned()));
 
-    Ok(TransactionResponse {
-        tx_hash: receipt.transaction_hash,
+        if failed {
+            responses.push(ChunkResponse {
+                tx: None,
+                transfers: requested,
+                error: Some("not sent, a previous chunk failed".into()),
+            });
+            continue;
+        }
+
+        let chunk = CollectPlan {
+            addresses: addresses.to_vec(),
+            amounts: amounts.to_vec(),
+            skipped: Vec::new(),
+            spenders: None,
+            warnings: Vec::new(),
+            assumed_allowances: plan.assumed_allowances.clone(),
+        };
+
+        let result = submit_collect(
+            state,
+            &token,
+            request.caller,
+            *request.recipient,
+            chunk,
+            from_events,
+            options.clone(),
+        )
+        .await;
+
+        match result {
+            Ok(CollectErc20Response(response)) => {
+                if let Some(spenders) = &mut spenders {
+                    add_collected(spenders, &response.transfers);
+                }
+
+                responses.push(ChunkResponse {
+                    tx: Some(response.tx),
+                    transfers: response.transfers,
+                    error: None,
+                });
+            }
+            // nothing was sent yet
+            Err(e) if responses.is_empty() => return Err(e),
+            Err(e) => {
+                warn!("Collect chunk failed, remaining chunks are not sent: {e}");
+                failed = true;
+
+                responses.push(ChunkResponse {
+                    tx: None,
+                    transfers: requested,
+                    error: Some(e.client_message(&state.config().error_templates)),
+                });
+            }
+        }
+    }
+
+    Ok(CollectChunksResponse {
+        chunks: responses,
+        skipped: plan.skipped,
+        spenders,
+        warnings: plan.warnings,
     })
 }
 
-fn construct_disperse_recipients(
-    sender: Address,
-    total_balance: U256,
-    recipients: impl Iterator<Item = (Address, FractionOrAmount)>,
-) -> Result<(Vec<Address>, Vec<U256>), DcError> {
-    let (l, u) = recipients.size_hint();
-    let iter_len = u.unwrap_or(l);
+/// Sends the spenders' permits and computes the amounts to collect
+async fn plan_collect(
+    state: &AppState,
+    token: &Erc20Contract,
+    caller: Address,
+    mut spenders: BTreeMap<Address, CollectSpender>,
+    verbose: bool,
+    options: &TxOptions,
+) -> Result<CollectPlan, DcError> {
+    let mut plan = CollectPlan::with_capacity(spenders.len(), verbose);
+    let custodial = state.config().custodial_spenders;
+    let amounts: Vec<_> = spenders
+        .values_mut()
+        .map(|spender| &mut spender.amount)
+        .collect();
+    resolve_decimals(state, Some(*token.address()), amounts).await?;
 
-    let mut addresses = Vec::with_capacity(iter_len);
-    let mut amounts = Vec::with_capacity(iter_len);
-    let mut sum = U256::ZERO;
+    if custodial {
+        ensure_spender_signers(state, caller, spenders.keys()).await?;
+    }
 
-    for (address, amount) in recipients {
-        let actual_amount = normalize_amount(amount, total_balance)?;
-        sum += actual_amount;
+    submit_permits(
+        state,
+        *token.address(),
+        caller,
+        &mut spenders,
+        &mut plan,
+        options,
+    )
+    .await?;
 
-        addresses.push(address);
-        amounts.push(actual_amount);
+    let owners: Vec<_> = spenders.keys().cloned().collect();
+    let balances = get_collectable(state, token, &owners, *state.contract().address()).await?;
+    let mut approvals = Vec::new();
+
+    for ((allowance, balance), (address, spender)) in balances.into_iter().zip(spenders) {
+        let allowance = plan.allowance(address, allowance);
+        plan.quote(address, balance, allowance);
+
+        let zero_amounts = state.config().zero_amounts;
+        let Some(actual_amount) = batch_amount(address, spender.amount, balance, zero_amounts)?
+        else {
+            plan.skip(address, SkipReason::ZeroAmount);
+            continue;
+        };
+
+        let available = if custodial {
+            balance
+        } else {
+            allowance.min(balance)
+        };
+
+        if actual_amount > available {
+            return Err(DcError::InsufficientFunds {
+                required: actual_amount,
+                available,
+                address,
+            });
+        }
+
+        if actual_amount > allowance {
+            approvals.push((address, actual_amount));
+        }
+        plan.push(address, actual_amount);
     }
 
-    if sum > total_balance {
-        return Err(DcError::InsufficientFunds {
-            required: sum,
-            available: total_balance,
-            address: sender,
+    if is_simulating(state, options) {
+        plan.assumed_allowances.extend(approvals.iter().cloned());
+    }
+    approve_spenders(state, token, approvals, options).await?;
+
+    Ok(plan)
+}
+
+/// Fails with every spender the service holds no key for, checked before anything is sent
+async fn ensure_spender_signers<'a>(
+    state: &AppState,
+    caller: Address,
+    spenders: impl IntoIterator<Item = &'a Address>,
+) -> Result<(), DcError> {
+    resolve_signer(state, caller).await?;
+
+    let provider = state.provider();
+    let missing: Vec<_> = spenders
+        .into_iter()
+        .filter(|spender| !provider.has_signer_for(spender))
+        .cloned()
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(DcError::SpenderSignersNotFound(missing));
+    }
+
+    Ok(())
+}
+
+/// Approves the contract for the amount from each custodial spender, signed by the spender itself
+async fn approve_spenders(
+    state: &AppState,
+    token: &Erc20Contract,
+    approvals: Vec<(Address, U256)>,
+    options: &TxOptions,
+) -> Result<(), DcError> {
+    for (spender, amount) in approvals {
+        let tx = token
+            .approve(*state.contract().address(), amount)
+            .into_transaction_request();
+
+        send_transaction(state, tx, spender, Operation::Approve, 0, options.clone()).await?;
+    }
+
+    Ok(())
+}
+
+/// Greedily collects `target_total` from the spenders, taking a partial amount from the last one.
+///
+/// See [`collect_erc20`] for `verbose`
+#[instrument(skip_all, fields(request = ?state.redacted(&request)), target = "collect_erc20")]
+pub async fn collect_erc20_target(
+    state: &AppState,
+    request: CollectErc20TargetRequest,
+    verbose: bool,
+    from_events: bool,
+    options: TxOptions,
+) -> Result<CollectErc20Response, DcError> {
+    let contract = state.contract();
+    if request.spenders.is_empty() {
+        return Err(DcError::NoSpenders);
+    }
+    validate_checksums(state, &request.recipient)?;
+    validate_recipients(state, [&*request.recipient])?;
+    ensure_verified_token(state, request.token).await?;
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+    ensure_within_supply(state, &token, [request.target_total]).await?;
+    let warnings = check_new_contracts(state, [request.token, *request.recipient]).await?;
+
+    let mut owners = Vec::with_capacity(request.spenders.len());
+    for spender in request.spenders {
+        if !owners.contains(&spender) {
+            owners.push(spender);
+        }
+    }
+
+    let custodial = state.config().custodial_spenders;
+    if custodial {
+        ensure_spender_signers(state, request.caller, &owners).await?;
+    }
+
+    let balances = get_collectable(state, &token, &owners, *contract.address()).await?;
+    let mut plan = CollectPlan::with_capacity(owners.len(), verbose);
+    plan.warnings = warnings;
+    let mut available: Vec<_> = owners
+        .into_iter()
+        .zip(balances)
+        .map(|(address, (allowance, balance))| {
+            plan.quote(address, balance, allowance);
+            let available = if custodial {
+                balance
+            } else {
+                allowance.min(balance)
+            };
+            (address, available, allowance)
+        })
+        .collect();
+
+    let total_available = available.iter().fold(U256::ZERO, |acc, (_, amount, _)| {
+        acc.saturating_add(*amount)
+    });
+
+    if total_available < request.target_total {
+        return Err(DcError::TargetNotReached {
+            target: request.target_total,
+            available: total_available,
         });
     }
 
-    Ok((addresses, amounts))
+    if request.order == CollectOrder::Available {
+        // stable sort keeps input order between equal amounts
+        available.sort_by(|(_, a, _), (_, b, _)| b.cmp(a));
+    }
+
+    let mut remaining = request.target_total;
+    let mut approvals = Vec::new();
+
+    for (address, amount, allowance) in available {
+        if remaining.is_zero() {
+            plan.skip(address, SkipReason::TargetReached);
+        } else if amount.is_zero() {
+            plan.skip(address, SkipReason::ZeroAmount);
+        } else {
+            let amount = amount.min(remaining);
+            remaining -= amount;
+            if amount > allowance {
+                approvals.push((address, amount));
+            }
+            plan.push(address, amount);
+        }
+    }
+
+    if is_simulating(state, &options) {
+        plan.assumed_allowances.extend(approvals.iter().cloned());
+    }
+    approve_spenders(state, &token, approvals, &options).await?;
+
+    submit_collect(
+        state,
+        &token,
+        request.caller,
+        *request.recipient,
+        plan,
+        from_events,
+        options,
+    )
+    .await
+}
+
+/// Balance and allowance of an ERC20 disperse spender
+struct Erc20Quote {
+    allowance: U256,
+    balance: U256,
+    /// Pinned block the quote was read at
+    block_number: Option<u64>,
+    read_at: Instant,
+}
+
+impl Erc20Quote {
+    fn available(&self) -> U256 {
+        self.balance.min(self.allowance)
+    }
+
+    /// Older than `max_quote_age_secs`
+    fn is_stale(&self, state: &AppState) -> bool {
+        state
+            .config()
+            .max_quote_age_secs
+            .is_some_and(|age| self.read_at.elapsed() > Duration::from_secs(age))
+    }
+}
+
+async fn get_erc20_quote(
+    state: &AppState,
+    token: &Erc20Contract,
+    spender: Address,
+    verbose: bool,
+) -> Result<Erc20Quote, DcError> {
+    let contract = state.contract();
+    let block_number = get_pinned_block(state, verbose).await?;
+
+    let (allowance, balance) = try_join!(
+        async {
+            token
+                .allowance(spender, *contract.address())
+                .block(block_id(block_number))
+                .call()
+                .await
+        },
+        async {
+            token
+                .balanceOf(spender)
+                .block(block_id(block_number))
+                .call()
+                .await
+        }
+    )
+    .map(|(a, b)| (a._0, b._0))
+    .map_err(|e: alloy::contract::Error| DcError::from_erc20_err(e, *token.address()))?;
+
+    Ok(Erc20Quote {
+        allowance,
+        balance,
+        block_number,
+        read_at: Instant::now(),
+    })
+}
+
+/// Whether an ERC20 disperse is relayed, i.e. `caller` sends it on behalf of another `spender`.
+///
+/// A self-disperse moves the caller's own tokens. A relayed one moves the spender's tokens, which only
+/// works if the spender approved the contract, and fails if `relayed_disperse` is disabled.
+fn ensure_relay_allowed(
+    state: &AppState,
+    caller: Address,
+    spender: Address,
+) -> Result<bool, DcError> {
+    let relayed = caller != spender;
+
+    if relayed && !state.config().relayed_disperse {
+        return Err(DcError::RelayedDisperse { caller, spender });
+    }
+
+    Ok(relayed)
+}
+
+/// Rejects the zero address and denylisted recipients, unless the recipient policy is `off`
+fn validate_recipients<'a>(
+    state: &AppState,
+    recipients: impl IntoIterator<Item = &'a Address>,
+) -> Result<(), DcError> {
+    let config = state.config();
+
+    if !config.recipient_policy.rejects_invalid() {
+        return Ok(());
+    }
+
+    for recipient in recipients {
+        if recipient.is_zero() {
+            return Err(DcError::ZeroAddressRecipient);
+        }
+        if config.recipient_denylist.contains(recipient) {
+            return Err(DcError::DeniedRecipient(*recipient));
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects recipients sent without an EIP-55 checksum if the recipient policy is `strict`
+fn validate_checksums<T>(state: &AppState, recipients: &Recipients<T>) -> Result<(), DcError> {
+    match recipients.unchecksummed() {
+        Some(address) if state.config().recipient_policy.requires_checksum() => {
+            Err(DcError::UnchecksummedRecipient(address.into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Recipients with code that are left out of a disperse, see [`ContractRecipients`]
+async fn get_contract_recipients(
+    state: &AppState,
+    recipients: impl IntoIterator<Item = Address>,
+) -> Result<Vec<Address>, DcError> {
+    let policy = state.config().contract_recipients();
+
+    if policy == ContractRecipients::Allow {
+        return Ok(Vec::new());
+    }
+
+    let recipients: Vec<_> = recipients.into_iter().collect();
+    let provider = state.provider();
+    let codes = try_join_all(
+        recipients
+            .iter()
+            .map(|recipient| async move { provider.get_code_at(*recipient).await }),
+    )
+    .await?;
+
+    let contracts: Vec<_> = recipients
+        .into_iter()
+        .zip(codes)
+        .filter(|(_, code)| !code.is_empty())
+        .map(|(address, _)| address)
+        .collect();
+
+    match (policy, contracts.first()) {
+        (ContractRecipients::Reject, Some(address)) => Err(DcError::ContractRecipient(*address)),
+        _ => Ok(contracts),
+    }
+}
+
+/// Sends `recipient_topup_wei` of ETH to every recipient holding less, so they can pay for gas.
+/// The `disperse` the recipients are topped up for is simulated first, so a failing one sends nothing
+async fn top_up_recipients(
+    state: &AppState,
+    caller: Address,
+    recipients: &[Address],
+    disperse: &WithOtherFields<TransactionRequest>,
+    options: &TxOptions,
+) -> Result<Option<TopUpResponse>, DcError> {
+    let Some(amount) = state.config().recipient_topup_wei.map(U256::from) else {
+        return Ok(None);
+    };
+
+    let provider = state.provider();
+    let balances = try_join_all(
+        recipients
+            .iter()
+            .map(|recipient| async move { provider.get_balance(*recipient).await }),
+    )
+    .await?;
+
+    let addresses: Vec<_> = recipients
+        .iter()
+        .zip(balances)
+        .filter(|(_, balance)| *balance < amount)
+        .map(|(address, _)| *address)
+        .collect();
+
+    if addresses.is_empty() {
+        return Ok(None);
+    }
+
+    // a simulated disperse is checked right after its simulated top-up anyway
+    if !is_simulating(state, options) {
+        let signer = resolve_signer(state, caller).await?;
+        let disperse = disperse.clone().with_from(signer);
+
+        provider.call(&disperse).await.map_err(|e| match e {
+            RpcError::ErrorResp(payload) => DcError::SimulationReverted(payload.message),
+            e => e.into(),
+        })?;
+    }
+
+    let total = amount
+        .checked_mul(U256::from(addresses.len()))
+        .ok_or_else(|| DcError::unexpected(anyhow::anyhow!("top-up total overflows")))?;
+
+    let tx = state
+        .contract()
+        .disperseEth(addresses.clone(), vec![amount; addresses.len()])
+        .value(total)
+        .into_transaction_request();
+
+    let tx_response = send_transaction(
+        state,
+        tx,
+        caller,
+        Operation::DisperseEth,
+        addresses.len(),
+        options.clone(),
+    )
+    .await?;
+
+    Ok(Some(TopUpResponse {
+        tx: tx_response,
+        transfers: addresses.into_iter().map(|a| (a, amount)).collect(),
+    }))
+}
+
+/// Sends the permits attached to `spenders` one by one. Spenders with an expired or invalid permit
+/// are removed and skipped, or fail the request, depending on [`PermitFailure`].
+async fn submit_permits(
+    state: &AppState,
+    token: Address,
+    caller: Address,
+    spenders: &mut BTreeMap<Address, CollectSpender>,
+    plan: &mut CollectPlan,
+    options: &TxOptions,
+) -> Result<(), DcError> {
+    let contract = Erc20PermitContract::new(token, state.provider().clone());
+    let mut invalid = Vec::new();
+
+    for (owner, spender) in spenders.iter() {
+        let Some(permit) = spender.permit else {
+            continue;
+        };
+
+        let call = contract.permit(
+            *owner,
+            *state.contract().address(),
+            permit.value,
+            permit.deadline,
+            permit.v,
+            permit.r,
+            permit.s,
+        );
+
+        // a reverting permit would otherwise only surface as a failed transaction
+        if let Err(e) = call.clone().from(caller).call().await {
+            let reason = match e {
+                ContractError::TransportError(RpcError::ErrorResp(payload)) => payload.message,
+                e => return Err(DcError::from_erc20_err(e, token)),
+            };
+
+            if state.config().collect_permit_failure == PermitFailure::Fail {
+                return Err(DcError::InvalidPermit {
+                    owner: *owner,
+                    reason,
+                });
+            }
+
+            warn!(
+                "Skipping {} with invalid permit: {reason}",
+                state.redacted(owner)
+            );
+            invalid.push(*owner);
+            continue;
+        }
+
+        send_transaction(
+            state,
+            call.into_transaction_request(),
+            caller,
+            Operation::Permit,
+            0,
+            options.clone(),
+        )
+        .await?;
+
+        if is_simulating(state, options) {
+            plan.assumed_allowances.insert(*owner, permit.value);
+        }
+    }
+
+    for owner in invalid {
+        spenders.remove(&owner);
+        plan.skip(owner, SkipReason::InvalidPermit);
+    }
+
+    Ok(())
+}
+
+/// Spenders and amounts of a single `collectERC20` call
+struct CollectPlan {
+    addresses: Vec<Address>,
+    amounts: Vec<U256>,
+    skipped: Vec<SkippedEntry>,
+    /// Balances and allowances the amounts were computed from, only recorded if verbose
+    spenders: Option<BTreeMap<Address, SpenderBreakdown>>,
+    warnings: Vec<String>,
+    /// Allowances set by simulated permits and approvals, the node still reports the old ones
+    assumed_allowances: BTreeMap<Address, U256>,
+}
+
+impl CollectPlan {
+    fn with_capacity(capacity: usize, verbose: bool) -> Self {
+        Self {
+            addresses: Vec::with_capacity(capacity),
+            amounts: Vec::with_capacity(capacity),
+            skipped: Vec::new(),
+            spenders: verbose.then(BTreeMap::new),
+            warnings: Vec::new(),
+            assumed_allowances: BTreeMap::new(),
+        }
+    }
+
+    /// Allowance of `address`, as set by a simulated permit if there was one
+    fn allowance(&self, address: Address, read: U256) -> U256 {
+        self.assumed_allowances
+            .get(&address)
+            .copied()
+            .unwrap_or(read)
+    }
+
+    fn quote(&mut self, address: Address, balance: U256, allowance: U256) {
+        if let Some(spenders) = &mut self.spenders {
+            spenders.insert(
+                address,
+                SpenderBreakdown {
+                    balance,
+                    allowance,
+                    available: balance.min(allowance),
+                    collected: U256::ZERO,
+                },
+            );
+        }
+    }
+
+    fn push(&mut self, address: Address, amount: U256) {
+        self.addresses.push(address);
+        self.amounts.push(amount);
+    }
+
+    fn skip(&mut self, address: Address, reason: SkipReason) {
+        self.skipped.push(SkippedEntry { address, reason });
+    }
+}
+
+fn add_collected(
+    spenders: &mut BTreeMap<Address, SpenderBreakdown>,
+    transfers: &BTreeMap<Address, U256>,
+) {
+    for (address, amount) in transfers {
+        if let Some(spender) = spenders.get_mut(address) {
+            spender.collected += amount;
+        }
+    }
+}
+
+async fn submit_collect(
+    state: &AppState,
+    token: &Erc20Contract,
+    caller: Address,
+    recipient: Address,
+    plan: CollectPlan,
+    from_events: bool,
+    mut options: TxOptions,
+) -> Result<CollectErc20Response, DcError> {
+    let contract = state.contract();
+    let CollectPlan {
+        addresses,
+        mut amounts,
+        skipped,
+        mut spenders,
+        warnings,
+        assumed_allowances,
+    } = plan;
+
+    // the node would revert the collect against the allowances from before the simulated approvals
+    let assumed = !assumed_allowances.is_empty();
+    options.skip_simulation |= assumed;
+
+    let recheck = state.config().collect_allowance_recheck;
+
+    if recheck != AllowanceRecheck::Off && !assumed {
+        let allowances = get_erc20_allowances(token, &addresses, *contract.address()).await?;
+
+        for ((address, amount), allowance) in addresses.iter().zip(&mut amounts).zip(allowances) {
+            if *amount <= allowance {
+                continue;
+            }
+
+            if recheck == AllowanceRecheck::Fail {
+                return Err(DcError::AllowanceChanged {
+                    address: *address,
+                    required: *amount,
+                    allowance,
+                });
+            }
+
+            warn!(
+                "Allowance of {} dropped to {}, clamping collected amount",
+                state.redacted(address),
+                state.redacted(allowance)
+            );
+            *amount = allowance;
+        }
+    }
+
+    let tx = contract
+        .collectERC20(
+            *token.address(),
+            recipient,
+            addresses.clone(),
+            amounts.clone(),
+        )
+        .into_transaction_request();
+
+    let tx_response = match send_transaction(
+        state,
+        tx,
+        caller,
+        Operation::CollectErc20,
+        addresses.len(),
+        options,
+    )
+    .await
+    {
+        Ok(tx_response) => tx_response,
+        Err(e) => {
+            // a reverting collect is most likely caused by an owner revoking the allowance
+            let allowances = get_erc20_allowances(token, &addresses, *contract.address()).await?;
+
+            let changed = addresses
+                .iter()
+                .zip(&amounts)
+                .zip(allowances)
+                .find(|((_, amount), allowance)| allowance < *amount);
+
+            return Err(match changed {
+                Some(((address, amount), allowance)) => DcError::AllowanceChanged {
+                    address: *address,
+                    required: *amount,
+                    allowance,
+                },
+                None => e,
+            });
+        }
+    };
+
+    let diff = if tx_response.simulated {
+        let transfers: Vec<_> = addresses
+            .iter()
+            .zip(&amounts)
+            .map(|(address, amount)| (*address, recipient, *amount))
+            .collect();
+
+        Some(predict_balance_diff(state, Some(token), &transfers).await?)
+    } else {
+        None
+    };
+
+    let mut transfers = if from_events && !tx_response.simulated {
+        transfers_from_logs(&tx_response.logs, *token.address(), |t| {
+            (t.to == recipient).then_some(t.from)
+        })
+    } else {
+        BTreeMap::from_iter(addresses.into_iter().zip(amounts))
+    };
+    let failed = take_failed_transfers(state, &tx_response.logs, *token.address(), &mut transfers);
+
+    if let Some(spenders) = &mut spenders {
+        add_collected(spenders, &transfers);
+    }
+
+    Ok(CollectErc20Response(DisperseCollectResponse {
+        tx: tx_response,
+        transfers,
+        failed,
+        skipped,
+        breakdown: None,
+        spenders,
+        top_up: None,
+        diff,
+        warnings,
+    }))
+}
+
+pub async fn transfer(
+    state: &AppState,
+    request: TransferRequest,
+    options: TxOptions,
+) -> Result<TransactionResponse, DcError> {
+    validate_checksums(state, &request.recipient)?;
+    validate_recipients(state, [&*request.recipient])?;
+    let include_balance = options.include_balance;
+
+    let mut tx_response = match request.token {
+        Some(addr) => {
+            transfer_erc20(
+                state,
+                request.caller,
+                *request.recipient,
+                addr,
+                request.value,
+                options,
+            )
+            .await
+        }
+        None => {
+            transfer_eth(
+                state,
+                request.caller,
+                *request.recipient,
+                request.value,
+                options,
+            )
+            .await
+        }
+    }?;
+
+    if include_balance && !tx_response.simulated {
+        let balance = match request.token {
+            Some(addr) => {
+                let token = Erc20Contract::new(addr, state.provider().clone());
+                get_erc20_balance(&token, request.caller).await?
+            }
+            None => state.provider().get_balance(request.caller).await?,
+        };
+
+        tx_response.balance_after = Some(balance);
+    }
+
+    Ok(tx_response)
+}
+
+pub async fn transfer_eth(
+    state: &AppState,
+    caller: Address,
+    recipient: Address,
+    mut amount: FractionOrAmount,
+    options: TxOptions,
+) -> Result<TransactionResponse, DcError> {
+    resolve_decimals(state, None, [&mut amount]).await?;
+    let provider = state.provider();
+    let balance = provider.get_balance(caller).await?;
+
+    let ensure_covered = |required: U256, available: U256| {
+        if required > available {
+            return Err(DcError::InsufficientFunds {
+                required,
+                available,
+                address: caller,
+            });
+        }
+
+        Ok(())
+    };
+
+    // gas is estimated with the amount of the whole balance, the transfer then spends what's left
+    let estimate = normalize_amount(amount, balance)?;
+    ensure_covered(estimate, balance)?;
+
+    let estimate_tx = TransactionRequest::default().value(estimate).to(recipient);
+    let estimate_tx = WithOtherFields::new(estimate_tx);
+    let reservation = reserve_gas(state, &estimate_tx, caller, 1, &options).await?;
+    let available_balance = balance.saturating_sub(reservation.cost());
+
+    let actual_amount = normalize_amount(amount, available_balance)?;
+    ensure_covered(actual_amount, available_balance)?;
+
+    let mut tx = WithOtherFields::new(
+        TransactionRequest::default()
+            .value(actual_amount)
+            .to(recipient),
+    );
+    reservation.apply(&mut tx);
+
+    let tx_response =
+        send_transaction(state, tx, caller, Operation::TransferEth, 1, options).await?;
+
+    Ok(tx_response)
+}
+
+pub async fn transfer_erc20(
+    state: &AppState,
+    caller: Address,
+    recipient: Address,
+    token_address: Address,
+    mut amount: FractionOrAmount,
+    options: TxOptions,
+) -> Result<TransactionResponse, DcError> {
+    ensure_verified_token(state, token_address).await?;
+    resolve_decimals(state, Some(token_address), [&mut amount]).await?;
+    let token = Erc20Contract::new(token_address, state.provider().clone());
+    ensure_within_supply(state, &token, absolute_amounts([&amount])).await?;
+    let balance = get_erc20_balance(&token, caller).await?;
+
+    let actual_amount = normalize_amount(amount, balance)?;
+
+    if actual_amount > balance {
+        return Err(DcError::InsufficientFunds {
+            required: actual_amount,
+            available: balance,
+            address: caller,
+        });
+    }
+
+    let tx = token
+        .transfer(recipient, actual_amount)
+        .into_transaction_request();
+
+    let tx_response =
+        send_transaction(state, tx, caller, Operation::TransferErc20, 1, options).await?;
+
+    Ok(tx_response)
+}
+
+pub async fn approve(
+    state: &AppState,
+    mut request: ApproveRequest,
+    options: TxOptions,
+) -> Result<TransactionResponse, DcError> {
+    ensure_verified_token(state, request.token).await?;
+    resolve_decimals(state, Some(request.token), [&mut request.amount]).await?;
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+
+    let balance = get_erc20_balance(&token, request.caller).await?;
+    let actual_amount = normalize_amount(request.amount, balance)?;
+
+    let tx = token
+        .approve(request.spender, actual_amount)
+        .into_transaction_request();
+
+    let tx_response =
+        send_transaction(state, tx, request.caller, Operation::Approve, 0, options).await?;
+
+    Ok(tx_response)
+}
+
+/// Approves `request.amount` only if the current allowance is lower
+pub async fn ensure_approval(
+    state: &AppState,
+    mut request: ApproveRequest,
+    options: TxOptions,
+) -> Result<EnsureApprovalResponse, DcError> {
+    ensure_verified_token(state, request.token).await?;
+    resolve_decimals(state, Some(request.token), [&mut request.amount]).await?;
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+
+    let (balance, allowance) = try_join!(get_erc20_balance(&token, request.caller), async {
+        token
+            .allowance(request.caller, request.spender)
+            .call()
+            .await
+            .map(|a| a._0)
+            .map_err(|e| DcError::from_erc20_err(e, request.token))
+    })?;
+    let actual_amount = normalize_amount(request.amount, balance)?;
+
+    if allowance >= actual_amount {
+        return Ok(EnsureApprovalResponse {
+            allowance,
+            tx: None,
+        });
+    }
+
+    let tx = token
+        .approve(request.spender, actual_amount)
+        .into_transaction_request();
+
+    let tx_response =
+        send_transaction(state, tx, request.caller, Operation::Approve, 0, options).await?;
+
+    Ok(EnsureApprovalResponse {
+        allowance,
+        tx: Some(tx_response),
+    })
+}
+
+/// Reads the allowance of `request.spender` and, with recipients, how much more the disperse needs.
+/// Nothing is sent
+pub async fn approve_preview(
+    state: &AppState,
+    mut request: ApprovePreviewRequest,
+) -> Result<ApprovePreviewResponse, DcError> {
+    ensure_verified_token(state, request.token).await?;
+    if let Some(recipients) = &mut request.recipients {
+        resolve_decimals(state, Some(request.token), recipients.values_mut()).await?;
+    }
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+    let spender = request.spender.unwrap_or(*state.contract().address());
+
+    let (balance, allowance) = try_join!(get_erc20_balance(&token, request.caller), async {
+        token
+            .allowance(request.caller, spender)
+            .call()
+            .await
+            .map(|a| a._0)
+            .map_err(|e| DcError::from_erc20_err(e, request.token))
+    })?;
+
+    let required = request
+        .recipients
+        .map(|recipients| {
+            construct_disperse_recipients(
+                request.caller,
+                balance,
+                recipients.into_iter(),
+                None,
+                state.config().zero_amounts,
+            )
+            .map(|recipients| recipients.total)
+        })
+        .transpose()?;
+
+    Ok(ApprovePreviewResponse {
+        spender,
+        allowance,
+        required,
+        additional_amount: required.map(|required| required.saturating_sub(allowance)),
+    })
+}
+
+pub async fn which_signer(
+    state: &AppState,
+    request: WhichSignerRequest,
+) -> Result<WhichSignerResponse, DcError> {
+    Ok(WhichSignerResponse {
+        signer: resolve_signer(state, request.caller).await?,
+    })
+}
+
+/// Symbol, name and decimals of a token, cached per chain after the first lookup
+pub async fn token_metadata(
+    state: &AppState,
+    token_address: Address,
+) -> Result<TokenResponse, DcError> {
+    if let Some(metadata) = state.token_metadata().get(&token_address) {
+        return Ok(metadata);
+    }
+
+    let token = Erc20Contract::new(token_address, state.provider().clone());
+    // reverts and empty return data mean there's no ERC20 at the address
+    let not_found = |e| match e {
+        ContractError::TransportError(RpcError::ErrorResp(_)) | ContractError::AbiError(_) => {
+            DcError::TokenNotFound(token_address)
+        }
+        e => DcError::from_erc20_err(e, token_address),
+    };
+
+    let symbol = token.symbol().call().await.map_err(not_found)?._0;
+    let name = token.name().call().await.map_err(not_found)?._0;
+    let decimals = get_erc20_decimals(&token, state.decimals()).await?;
+
+    let metadata = TokenResponse {
+        symbol,
+        name,
+        decimals,
+    };
+    state
+        .token_metadata()
+        .insert(token_address, metadata.clone());
+
+    Ok(metadata)
+}
+
+/// ERC20 balance of `request.address`, or its ETH balance without a token
+pub async fn balance(state: &AppState, request: BalanceQuery) -> Result<BalanceResponse, DcError> {
+    let balance = match request.token {
+        Some(token) => {
+            let token = Erc20Contract::new(token, state.provider().clone());
+            get_erc20_balance(&token, request.address).await?
+        }
+        None => state.provider().get_balance(request.address).await?,
+    };
+
+    Ok(BalanceResponse { balance })
+}
+
+pub async fn allowance(
+    state: &AppState,
+    request: AllowanceQuery,
+) -> Result<AllowanceResponse, DcError> {
+    let token = Erc20Contract::new(request.token, state.provider().clone());
+
+    let allowance = token
+        .allowance(request.owner, request.spender)
+        .call()
+        .await
+        .map(|a| a._0)
+        .map_err(|e| DcError::from_erc20_err(e, request.token))?;
+
+    Ok(AllowanceResponse { allowance })
+}
+
+pub async fn get_transaction_status(
+    state: &AppState,
+    tx_hash: B256,
+    options: TransactionStatusOptions,
+) -> Result<TransactionStatusResponse, DcError> {
+    let provider = state.provider();
+
+    // missing receipts are reported as pending so clients can keep polling
+    let receipt = provider.get_transaction_receipt(tx_hash).await?;
+    let Some((receipt, block_number)) =
+        receipt.and_then(|r| r.block_number.map(|block_number| (r, block_number)))
+    else {
+        return Ok(TransactionStatusResponse {
+            status: TransactionStatus::Pending,
+            block_number: None,
+            gas_used: None,
+            confirmations: None,
+            transaction_index: None,
+        });
+    };
+
+    let latest = provider.get_block_number().await?;
+    let gas_used = u64::try_from(receipt.gas_used).map_err(DcError::unexpected)?;
+
+    Ok(TransactionStatusResponse {
+        status: if receipt.inner.inner.status() {
+            TransactionStatus::Success
+        } else {
+            TransactionStatus::Reverted
+        },
+        block_number: Some(block_number),
+        gas_used: Some(gas_used),
+        confirmations: Some(latest.saturating_sub(block_number)),
+        transaction_index: receipt.transaction_index.filter(|_| options.include_index),
+    })
+}
+
+async fn ensure_verified_token(state: &AppState, token: Address) -> Result<(), DcError> {
+    let Some(verified) = state.config().verified_token_codehashes.as_ref() else {
+        return Ok(());
+    };
+
+    let code_hash = keccak256(state.provider().get_code_at(token).await?);
+
+    if !verified.contains(&code_hash) {
+        return Err(DcError::UnverifiedToken { token, code_hash });
+    }
+
+    Ok(())
+}
+
+/// Checks whether any of `addresses` is a contract deployed within `new_contract_window_blocks`,
+/// by comparing its current code with its code at the start of the window.
+///
+/// Returns a warning per new contract, or fails on the first one if `new_contract_policy` is `reject`.
+/// Nodes without the state of the window's first block (non-archive nodes prune it after ~128 blocks)
+/// can't tell, such addresses are logged and let through.
+async fn check_new_contracts(
+    state: &AppState,
+    addresses: impl IntoIterator<Item = Address>,
+) -> Result<Vec<String>, DcError> {
+    let Some(window) = state.config().new_contract_window_blocks else {
+        return Ok(Vec::new());
+    };
+
+    let provider = state.provider();
+    let latest = provider.get_block_number().await?;
+    let window_start = BlockId::number(latest.saturating_sub(window));
+    let addresses: BTreeSet<_> = addresses.into_iter().collect();
+
+    let codes = try_join_all(addresses.iter().map(|address| async move {
+        let code = provider
+            .get_code_at(*address)
+            .block_id(BlockId::number(latest))
+            .await?;
+
+        if code.is_empty() {
+            return Ok::<_, DcError>(None);
+        }
+
+        let code_before = provider.get_code_at(*address).block_id(window_start).await;
+
+        Ok(Some(code_before))
+    }))
+    .await?;
+
+    let mut warnings = Vec::new();
+
+    for (address, code_before) in addresses.into_iter().zip(codes) {
+        match code_before {
+            Some(Ok(code)) if code.is_empty() => {
+                if state.config().new_contract_policy == NewContractPolicy::Reject {
+                    return Err(DcError::NewContract { address, window });
+                }
+
+                warnings.push(DcError::NewContract { address, window }.to_string());
+            }
+            Some(Err(e)) => warn!(
+                "Can't tell whether {} is a new contract, the node may not keep the state of block {}: {e}",
+                state.redacted(address),
+                latest.saturating_sub(window)
+            ),
+            _ => {}
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Fails if any of `amounts` is above the token's total supply, if `check_total_supply` is enabled
+async fn ensure_within_supply(
+    state: &AppState,
+    token: &Erc20Contract,
+    amounts: impl IntoIterator<Item = U256>,
+) -> Result<(), DcError> {
+    if !state.config().check_total_supply {
+        return Ok(());
+    }
+
+    let Some(amount) = amounts.into_iter().max() else {
+        return Ok(());
+    };
+
+    let cache = state.total_supply();
+
+    if cache
+        .get(token.address())
+        .is_some_and(|supply| amount <= supply)
+    {
+        return Ok(());
+    }
+
+    // the supply may have grown since it was cached
+    let total_supply = token
+        .totalSupply()
+        .call()
+        .await
+        .map(|s| s._0)
+        .map_err(|e| DcError::from_erc20_err(e, *token.address()))?;
+
+    cache.insert(*token.address(), total_supply);
+
+    if amount > total_supply {
+        return Err(DcError::ExceedsTotalSupply {
+            token: *token.address(),
+            amount,
+            total_supply,
+        });
+    }
+
+    Ok(())
+}
+
+/// Absolute amounts of `amounts`, fractions and invalid decimal amounts are skipped
+fn absolute_amounts<'a>(amounts: impl IntoIterator<Item = &'a FractionOrAmount>) -> Vec<U256> {
+    amounts
+        .into_iter()
+        .filter_map(|amount| match amount {
+            FractionOrAmount::Amount { amount } => Some(*amount),
+            FractionOrAmount::Fraction(_)
+            | FractionOrAmount::BasisPoints { .. }
+            | FractionOrAmount::Weighted { .. } => None,
+            FractionOrAmount::Decimal { value, decimals } => {
+                decimal_to_base_units(*value, *decimals).ok()
+            }
+        })
+        .collect()
+}
+
+async fn get_erc20_decimals(token: &Erc20Contract, cache: &DecimalsCache) -> Result<u8, DcError> {
+    if let Some(decimals) = cache.get(token.address()) {
+        return Ok(decimals);
+    }
+
+    let decimals = token
+        .decimals()
+        .call()
+        .await
+        .map(|d| d._0)
+        .map_err(|e| DcError::from_erc20_err(e, *token.address()))?;
+
+    cache.insert(*token.address(), decimals);
+
+    Ok(decimals)
+}
+
+async fn get_erc20_allowances(
+    token: &Erc20Contract,
+    owners: &[Address],
+    spender: Address,
+) -> Result<Vec<U256>, DcError> {
+    try_join_all(
+        owners.iter().map(|owner| async move {
+            token.allowance(*owner, spender).call().await.map(|a| a._0)
+        }),
+    )
+    .await
+    .map_err(|e| DcError::from_erc20_err(e, *token.address()))
+}
+
+/// Sums `Transfer` events of `token` per address returned by `key`, events it returns `None` for are ignored
+fn transfers_from_logs(
+    logs: &[Log],
+    token: Address,
+    key: impl Fn(&IERC20::Transfer) -> Option<Address>,
+) -> BTreeMap<Address, U256> {
+    let mut transfers = BTreeMap::new();
+
+    let events = logs
+        .iter()
+        .filter(|log| log.address() == token)
+        .filter_map(|log| log.log_decode::<IERC20::Transfer>().ok());
+
+    for event in events {
+        let transfer = &event.inner.data;
+
+        if let Some(address) = key(transfer) {
+            let amount: &mut U256 = transfers.entry(address).or_default();
+            *amount = amount.saturating_add(transfer.value);
+        }
+    }
+
+    transfers
+}
+
+/// Removes the transfers the contract reported with [`IDisperseOutcomes::TransferFailed`] from `transfers`
+/// and returns them. `token` is the zero address for ETH
+fn take_failed_transfers(
+    state: &AppState,
+    logs: &[Log],
+    token: Address,
+    transfers: &mut BTreeMap<Address, U256>,
+) -> Vec<FailedTransfer> {
+    logs.iter()
+        .filter(|log| log.address() == *state.contract().address())
+        .filter_map(|log| log.log_decode::<IDisperseOutcomes::TransferFailed>().ok())
+        .map(|event| event.inner.data)
+        .filter(|failure| failure.token == token)
+        .map(|failure| {
+            transfers.remove(&failure.account);
+
+            FailedTransfer {
+                address: failure.account,
+                amount: failure.amount,
+                reason: failure.reason,
+            }
+        })
+        .collect()
+}
+
+/// Reads the current balances of everyone in `transfers` and applies the `(from, to, amount)` transfers to them,
+/// ETH balances if `token` is not set
+async fn predict_balance_diff(
+    state: &AppState,
+    token: Option<&Erc20Contract>,
+    transfers: &[(Address, Address, U256)],
+) -> Result<BalanceDiff, DcError> {
+    let mut addresses: Vec<_> = transfers
+        .iter()
+        .flat_map(|(from, to, _)| [*from, *to])
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+
+    let before = try_join_all(addresses.iter().map(|address| async move {
+        match token {
+            Some(token) => get_erc20_balance(token, *address).await,
+            None => Ok(state.provider().get_balance(*address).await?),
+        }
+    }))
+    .await?;
+
+    let mut balances: BTreeMap<_, _> = addresses
+        .into_iter()
+        .zip(before)
+        .map(|(address, before)| {
+            let change = BalanceChange {
+                before,
+                after: before,
+            };
+            (address, change)
+        })
+        .collect();
+
+    for (from, to, amount) in transfers {
+        if let Some(change) = balances.get_mut(from) {
+            change.after = change.after.saturating_sub(*amount);
+        }
+        if let Some(change) = balances.get_mut(to) {
+            change.after = change.after.saturating_add(*amount);
+        }
+    }
+
+    let note = match token {
+        Some(_) => {
+            "estimated from the quoted amounts, fee-on-transfer tokens deliver less than shown"
+        }
+        None => "estimated from the quoted amounts, gas paid by the caller is not included",
+    };
+
+    Ok(BalanceDiff {
+        token: token.map(|token| *token.address()),
+        balances,
+        note,
+    })
+}
+
+/// Allowance and balance of every owner, read in a single `eth_call` through Multicall3.
+///
+/// Falls back to two calls per owner if the multicall fails, e.g. because Multicall3 isn't deployed
+/// or the token doesn't implement ERC20, the single calls then report the actual error.
+async fn get_collectable(
+    state: &AppState,
+    token: &Erc20Contract,
+    owners: &[Address],
+    spender: Address,
+) -> Result<Vec<(U256, U256)>, DcError> {
+    match multicall_collectable(state, *token.address(), owners, spender).await {
+        Ok(collectable) => return Ok(collectable),
+        Err(e @ DcError::Transport(_)) => return Err(e),
+        Err(e) => {
+            warn!("Multicall of balances and allowances failed, reading them one by one: {e}")
+        }
+    }
+
+    try_join_all(owners.iter().map(|owner| async move {
+        try_join!(
+            // nested async blocks because part before .call() is borrowed
+            async { token.allowance(*owner, spender).call().await },
+            async { token.balanceOf(*owner).call().await }
+        )
+        .map(|(a, b)| (a._0, b._0))
+    }))
+    .await
+    .map_err(|e| DcError::from_erc20_err(e, *token.address()))
+}
+
+async fn multicall_collectable(
+    state: &AppState,
+    token: Address,
+    owners: &[Address],
+    spender: Address,
+) -> Result<Vec<(U256, U256)>, DcError> {
+    let calls = owners
+        .iter()
+        .flat_map(|owner| {
+            [
+                IERC20::allowanceCall {
+                    owner: *owner,
+                    spender,
+                }
+                .abi_encode(),
+                IERC20::balanceOfCall { account: *owner }.abi_encode(),
+            ]
+        })
+        .map(|call_data| IMulticall3::Call3 {
+            target: token,
+            allowFailure: false,
+            callData: call_data.into(),
+        })
+        .collect();
+
+    let results = state
+        .multicall()
+        .aggregate3(calls)
+        .call()
+        .await
+        .map_err(|e| match e {
+            ContractError::TransportError(e) => e.into(),
+            e => DcError::unexpected(e),
+        })?
+        .returnData;
+
+    if results.len() != owners.len() * 2 {
+        return Err(DcError::unexpected(anyhow::anyhow!(
+            "multicall returned {} results for {} calls",
+            results.len(),
+            owners.len() * 2
+        )));
+    }
+
+    results
+        .chunks_exact(2)
+        .map(|pair| {
+            let allowance = IERC20::allowanceCall::abi_decode_returns(&pair[0].returnData, true)?;
+            let balance = IERC20::balanceOfCall::abi_decode_returns(&pair[1].returnData, true)?;
+            Ok((allowance._0, balance._0))
+        })
+        .collect::<Result<_, sol_types::Error>>()
+        .map_err(DcError::unexpected)
+}
+
+async fn get_erc20_balance(token: &Erc20Contract, address: Address) -> Result<U256, DcError> {
+    token
+        .balanceOf(address)
+        .call()
+        .await
+        .map(|b| b._0)
+        .map_err(|e| DcError::from_erc20_err(e, *token.address()))
+}
+
+fn normalize_amount(amount: FractionOrAmount, available_balance: U256) -> Result<U256, DcError> {
+    let fraction = |f: FractionalAmount| {
+        f.to_absolute(available_balance)
+            .filter(|a| *a != U256::ZERO)
+            .ok_or(InvalidFractionalAmountError(f))
+    };
+
+    let actual_amount = match amount {
+        FractionOrAmount::Amount { amount } => amount,
+        FractionOrAmount::Fraction(f) => fraction(f)?,
+        FractionOrAmount::BasisPoints { bps } => {
+            fraction(FractionalAmount::from_basis_points(bps))?
+        }
+        FractionOrAmount::Decimal { value, decimals } => decimal_to_base_units(value, decimals)?,
+        FractionOrAmount::Weighted { .. } => return Err(DcError::UnexpectedWeight),
+    };
+
+    Ok(actual_amount)
+}
+
+/// Fills in the decimals of decimal amounts that don't set them, those of `token` or 18 for ETH
+async fn resolve_decimals<'a>(
+    state: &AppState,
+    token: Option<Address>,
+    amounts: impl IntoIterator<Item = &'a mut FractionOrAmount>,
+) -> Result<(), DcError> {
+    for amount in amounts {
+        let FractionOrAmount::Decimal { decimals, .. } = amount else {
+            continue;
+        };
+        if decimals.is_some() {
+            continue;
+        }
+
+        *decimals = Some(match token {
+            Some(token) => {
+                let token = Erc20Contract::new(token, state.provider().clone());
+                get_erc20_decimals(&token, state.decimals()).await?
+            }
+            None => Unit::ETHER.get(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Rejects amounts with more fractional digits than `decimals` instead of truncating them
+fn decimal_to_base_units(value: DecimalAmount, decimals: Option<u8>) -> Result<U256, DcError> {
+    let decimals = decimals.ok_or(DcError::MissingDecimals(value))?;
+
+    if value.exceeds_precision(decimals) {
+        return Err(DcError::InvalidDecimalAmount { value, decimals });
+    }
+
+    value.to_base_units(decimals).ok_or(DcError::AmountOverflow)
+}
+
+/// Amount of a disperse recipient or collect spender, `None` if it is zero and `zero_amounts` skips it
+fn batch_amount(
+    address: Address,
+    amount: FractionOrAmount,
+    available_balance: U256,
+    zero_amounts: ZeroAmounts,
+) -> Result<Option<U256>, DcError> {
+    let fraction = |f: FractionalAmount| {
+        f.to_absolute(available_balance)
+            .ok_or(InvalidFractionalAmountError(f))
+    };
+
+    let actual_amount = match amount {
+        FractionOrAmount::Amount { amount } => amount,
+        FractionOrAmount::Fraction(f) => fraction(f)?,
+        FractionOrAmount::BasisPoints { bps } => {
+            fraction(FractionalAmount::from_basis_points(bps))?
+        }
+        FractionOrAmount::Decimal { value, decimals } => decimal_to_base_units(value, decimals)?,
+        // disperses turn weights into fractions first, see `weights_to_fractions`
+        FractionOrAmount::Weighted { .. } => return Err(DcError::UnexpectedWeight),
+    };
+
+    if !actual_amount.is_zero() {
+        return Ok(Some(actual_amount));
+    }
+
+    match (zero_amounts, amount.fraction()) {
+        (ZeroAmounts::Allow, _) => Ok(Some(actual_amount)),
+        (ZeroAmounts::Skip, _) => Ok(None),
+        (ZeroAmounts::Error, Some(f)) => Err(InvalidFractionalAmountError(f).into()),
+        (ZeroAmounts::Error, None) => Err(DcError::ZeroAmount(address)),
+    }
+}
+
+/// `recipients` is the number of transfers in the transaction, used for the fallback gas limit
+async fn send_transaction(
+    state: &AppState,
+    tx: WithOtherFields<TransactionRequest>,
+    signer: Address,
+    operation: Operation,
+    recipients: usize,
+    options: TxOptions,
+) -> Result<TransactionResponse, DcError> {
+    let moved = stats::moved_value(operation, &tx);
+    let started = Instant::now();
+    let result = submit_transaction(state, tx, signer, recipients, options).await;
+
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(e) => e.kind(),
+    };
+    metrics::TRANSACTIONS.increment(&[("operation", operation.name()), ("result", outcome)]);
+
+    let record = match &result {
+        Ok(response) if response.simulated => None,
+        Ok(response) => Some(TransactionRecord {
+            operation,
+            success: true,
+            gas_used: response.gas_used,
+            fee: response.fee,
+            duration: started.elapsed(),
+            moved,
+        }),
+        Err(_) => Some(TransactionRecord {
+            operation,
+            success: false,
+            gas_used: 0,
+            fee: U256::ZERO,
+            duration: started.elapsed(),
+            moved: None,
+        }),
+    };
+    if let Some(record) = record {
+        state.stats().record(record);
+    }
+
+    if let Some(notifier) = state.notifier() {
+        notifier.publish(TransactionEvent::new(operation, signer, &result));
+    }
+
+    result
+}
+
+fn gas_budget(state: &AppState) -> Option<U256> {
+    state
+        .config()
+        .caller_gas_budget_gwei
+        .map(|gwei| U256::from(gwei) * U256::from(1_000_000_000u64))
+}
+
+fn ensure_gas_budget(state: &AppState, caller: Address) -> Result<(), DcError> {
+    let (Some(budget), Some(usage)) = (gas_budget(state), state.usage().get(&caller)) else {
+        return Ok(());
+    };
+
+    if usage.spent >= budget {
+        return Err(DcError::GasBudgetExceeded {
+            caller,
+            spent: usage.spent,
+            budget,
+        });
+    }
+
+    Ok(())
+}
+
+pub fn caller_usage(state: &AppState, caller: Address) -> CallerUsageResponse {
+    let usage = state.usage().get(&caller);
+    let spent = usage.map(|u| u.spent).unwrap_or_default();
+    let budget = gas_budget(state);
+
+    CallerUsageResponse {
+        caller,
+        spent_wei: spent,
+        transactions: usage.map(|u| u.transactions).unwrap_or_default(),
+        budget_wei: budget,
+        remaining_wei: budget.map(|budget| budget.saturating_sub(spent)),
+    }
+}
+
+/// Connects to the node and primes caches before the first request, failures are only logged
+pub async fn warm_up(state: &AppState) {
+    let provider = state.provider();
+    let started = Instant::now();
+
+    match provider.get_chain_id().await {
+        Ok(chain_id) => info!("Warm-up: connected to chain {chain_id}"),
+        Err(e) => warn!("Warm-up: failed to fetch the chain id: {e}"),
+    }
+
+    match provider.estimate_eip1559_fees(None).await {
+        Ok(fees) => info!(
+            "Warm-up: max fee per gas {} wei, priority fee {} wei",
+            fees.max_fee_per_gas, fees.max_priority_fee_per_gas
+        ),
+        Err(e) => warn!("Warm-up: failed to estimate fees: {e}"),
+    }
+
+    for address in &state.config().warm_up_tokens {
+        let token = Erc20Contract::new(*address, provider.clone());
+
+        match get_erc20_decimals(&token, state.decimals()).await {
+            Ok(decimals) => info!("Warm-up: token {address} has {decimals} decimals"),
+            Err(e) => warn!("Warm-up: failed to fetch decimals of {address}: {e}"),
+        }
+    }
+
+    info!("Warm-up finished in {:?}", started.elapsed());
+}
+
+/// Looks for transactions of the local signers that a previous run left pending, i.e. a gap between
+/// the latest and the pending nonce, and handles them per `pending_recovery`. Failures are only logged,
+/// and recovery gives up after `recovery_timeout_secs` so a stuck chain doesn't hold up startup
+pub async fn recover_pending(state: &AppState) {
+    let secs = state.config().recovery_timeout_secs;
+
+    if tokio::time::timeout(Duration::from_secs(secs), recover_signers(state))
+        .await
+        .is_err()
+    {
+        warn!(
+            "Recovery: gave up after {secs}s, transactions of previous runs may still be pending"
+        );
+    }
+}
+
+async fn recover_signers(state: &AppState) {
+    let provider = state.provider();
+
+    for signer in provider.signer_addresses() {
+        let gap = try_join!(
+            provider.get_transaction_count(signer),
+            provider
+                .get_transaction_count(signer)
+                .block_id(BlockId::pending()),
+        );
+        let (latest, pending) = match gap {
+            Ok((latest, pending)) if pending > latest => (latest, pending),
+            Ok(_) => continue,
+            Err(e) => {
+                warn!("Recovery: failed to read the nonces of {signer}: {e}");
+                continue;
+            }
+        };
+
+        warn!(
+            "Recovery: {signer} has {} pending transactions from before the restart (nonces {latest}..{pending})",
+            pending - latest
+        );
+
+        let result = match state.config().pending_recovery {
+            PendingRecovery::Alert => Ok(()),
+            PendingRecovery::Wait => wait_for_nonce(state, signer, pending).await,
+            PendingRecovery::Replace => replace_pending(state, signer, latest..pending).await,
+        };
+
+        if let Err(e) = result {
+            warn!("Recovery: pending transactions of {signer} are still pending: {e}");
+        }
+    }
+}
+
+/// Waits until the transactions of `signer` up to `nonce` are mined
+async fn wait_for_nonce(state: &AppState, signer: Address, nonce: u64) -> Result<(), DcError> {
+    let provider = state.provider();
+
+    let wait = async {
+        while provider.get_transaction_count(signer).await? < nonce {
+            tokio::time::sleep(provider.client().poll_interval()).await;
+        }
+
+        info!("Recovery: pending transactions of {signer} were mined");
+        Ok(())
+    };
+
+    match state.config().confirmation_timeout_secs {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), wait)
+            .await
+            .map_err(|_| DcError::unexpected(anyhow::anyhow!("timed out after {secs}s")))?,
+        None => wait.await,
+    }
+}
+
+/// Sends a zero-value self-transfer for each of `nonces`, so it replaces whatever is stuck in the
+/// pool at that nonce. Replacements pay at least 12.5% more than the pending transaction, found
+/// through `txpool_contentFrom`, or twice the estimated fees if the node doesn't expose its pool.
+/// The replacements are not waited for
+async fn replace_pending(
+    state: &AppState,
+    signer: Address,
+    nonces: std::ops::Range<u64>,
+) -> Result<(), DcError> {
+    let provider = state.provider();
+    let estimate = provider.estimate_eip1559_fees(None).await?;
+    let pool = pending_pool(state, signer).await;
+
+    for nonce in nonces {
+        let pending = match pool.get(&nonce.to_string()) {
+            Some(tx) => provider.get_transaction_by_hash(tx.hash).await?,
+            None => None,
+        };
+        let (max_fee, priority_fee) = match pending {
+            Some(tx) => {
+                let max_fee = tx.max_fee_per_gas.or(tx.gas_price).unwrap_or_default();
+                let priority_fee = tx.max_priority_fee_per_gas.unwrap_or(max_fee);
+
+                (
+                    bump_fee(max_fee).max(estimate.max_fee_per_gas),
+                    bump_fee(priority_fee).max(estimate.max_priority_fee_per_gas),
+                )
+            }
+            None => {
+                warn!("Recovery: pending transaction of nonce {nonce} of {signer} not found, replacing it at twice the estimated fees");
+                (
+                    estimate.max_fee_per_gas * 2,
+                    estimate.max_priority_fee_per_gas * 2,
+                )
+            }
+        };
+
+        let tx = TransactionRequest::default()
+            .with_from(signer)
+            .with_to(signer)
+            .with_value(U256::ZERO)
+            .with_nonce(nonce)
+            .with_gas_limit(21_000)
+            .with_max_fee_per_gas(max_fee)
+            .with_max_priority_fee_per_gas(priority_fee);
+
+        match provider.send_transaction(WithOtherFields::new(tx)).await {
+            Ok(pending) => info!(
+                "Recovery: replaced nonce {nonce} of {signer} with {}",
+                pending.tx_hash()
+            ),
+            Err(e) if is_underpriced(&e) => {
+                return Err(DcError::unexpected(anyhow::anyhow!(
+                    "replacement of nonce {nonce} at {max_fee} wei per gas is underpriced, the pending transaction pays more"
+                )))
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Transaction of the pool, only its hash is read as the fees are fetched with `eth_getTransactionByHash`
+#[derive(Debug, Deserialize)]
+struct PoolTransaction {
+    hash: B256,
+}
+
+/// Pending transactions of `signer` by nonce, empty if the node doesn't expose its pool
+async fn pending_pool(state: &AppState, signer: Address) -> HashMap<String, PoolTransaction> {
+    #[derive(Debug, Deserialize)]
+    struct PoolContent {
+        #[serde(default)]
+        pending: HashMap<String, PoolTransaction>,
+    }
+
+    let content: TransportResult<PoolContent> = state
+        .provider()
+        .client()
+        .request("txpool_contentFrom", (signer,))
+        .await;
+
+    match content {
+        Ok(content) => content.pending,
+        Err(e) => {
+            warn!("Recovery: failed to read the pool of {signer}: {e}");
+            HashMap::new()
+        }
+    }
+}
+
+/// `fee` raised by the 12.5% nodes require to replace a pending transaction, rounded up
+fn bump_fee(fee: u128) -> u128 {
+    fee + fee.div_ceil(8)
+}
+
+/// Whether the node rejected a replacement for not paying enough over the pending transaction
+fn is_underpriced(e: &RpcError<TransportErrorKind>) -> bool {
+    e.as_error_resp()
+        .is_some_and(|payload| payload.message.to_lowercase().contains("underpriced"))
+}
+
+/// Checks that the node of the chain is reachable and serves the same chain, and that the
+/// contract is deployed
+pub async fn readiness_checks(state: &AppState) -> Vec<ReadinessCheck> {
+    let provider = state.provider();
+    let chain_id = state.chain_id();
+
+    let rpc = match provider.get_chain_id().await {
+        Ok(id) if id == chain_id => Ok(()),
+        Ok(id) => Err(format!("node serves chain {id}")),
+        Err(e) => Err(e.to_string()),
+    };
+
+    let contract = match rpc {
+        Ok(()) => match provider.get_code_at(*state.contract().address()).await {
+            Ok(code) if code.is_empty() => Err(format!(
+                "no contract deployed at {}",
+                state.contract().address()
+            )),
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(_) => Err("node is unreachable".to_owned()),
+    };
+
+    [("rpc", rpc), ("contract", contract)]
+        .into_iter()
+        .map(|(check, result)| ReadinessCheck {
+            check,
+            chain_id,
+            ok: result.is_ok(),
+            error: result.err(),
+        })
+        .collect()
+}
+
+/// `endpoints` are the routes served by this deployment
+pub fn capabilities(state: &AppState, endpoints: Vec<&'static str>) -> CapabilitiesResponse {
+    let config = state.config();
+
+    CapabilitiesResponse {
+        endpoints,
+        chains: state.chain_ids(),
+        default_chain: state.chain_id(),
+        limits: Limits {
+            caller_gas_budget_wei: gas_budget(state),
+            caller_budget_window_secs: config.caller_budget_window_secs,
+            max_recipients: config.max_recipients,
+            max_body_bytes: config.max_body_bytes,
+        },
+        simulate_only: config.simulate_only,
+        verified_tokens_only: config.verified_token_codehashes.is_some(),
+        contract_recipients: config.contract_recipients(),
+    }
+}
+
+/// Tops up the signer of `request.caller` from the configured testnet faucet
+pub async fn testnet_fund(
+    state: &AppState,
+    request: TestnetFundRequest,
+) -> Result<TestnetFundResponse, DcError> {
+    let faucet = state
+        .config()
+        .testnet_faucet()
+        .ok_or_else(|| DcError::unexpected(anyhow::anyhow!("testnet faucet is not configured")))?;
+
+    let signer = resolve_signer(state, request.caller).await?;
+    let amount = U256::from(state.config().testnet_fund_wei);
+
+    let balance = match faucet {
+        TestnetFaucet::Anvil => {
+            let provider = state.provider();
+            let balance = provider.get_balance(signer).await?.saturating_add(amount);
+
+            provider
+                .raw_request::<_, ()>("anvil_setBalance".into(), (signer, balance))
+                .await?;
+
+            Some(balance)
+        }
+        TestnetFaucet::Http(url) => {
+            reqwest::Client::new()
+                .post(url.clone())
+                .json(&serde_json::json!({ "address": signer, "amount": amount }))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(DcError::unexpected)?;
+
+            None
+        }
+    };
+
+    Ok(TestnetFundResponse {
+        signer,
+        amount,
+        balance,
+    })
+}
+
+/// Finds the smallest spender balance `request` can be dispersed from, nothing is read or sent.
+///
+/// Fractions are floored against the balance, so the search checks candidate balances with the
+/// same summation as the disperse itself instead of solving for it.
+pub fn required_balance(
+    request: RequiredBalanceRequest,
+) -> Result<RequiredBalanceResponse, DcError> {
+    // there's no token to read decimals from, decimal amounts have to set them
+    for amount in request.recipients.values() {
+        if let FractionOrAmount::Decimal { value, decimals } = amount {
+            decimal_to_base_units(*value, *decimals)?;
+        }
+    }
+    ensure_basis_points(request.recipients.values())?;
+    let mut recipients: Vec<_> = request.recipients.into_iter().collect();
+    weights_to_fractions(&mut recipients)?;
+    let amounts = || recipients.iter().map(|(_, amount)| amount);
+
+    let covers = |balance: U256| {
+        let recipients = recipients.iter().copied();
+        // a fraction flooring to zero doesn't count as covered, whatever the zero amount policy
+        construct_disperse_recipients(Address::ZERO, balance, recipients, None, ZeroAmounts::Error)
+            .is_ok()
+    };
+
+    let fixed_total = absolute_amounts(amounts())
+        .into_iter()
+        .try_fold(U256::ZERO, U256::checked_add)
+        .ok_or(DcError::BalanceUnreachable)?;
+
+    let scales_with_balance = amounts().any(|amount| amount.fraction().is_some());
+
+    // with flooring, fractions adding up to more than 1 may still fit a tiny balance
+    let scale = U256::from(10).pow(U256::from(36));
+    let fractions_total = amounts()
+        .filter_map(FractionOrAmount::fraction)
+        .try_fold(U256::ZERO, |total, f| {
+            total.checked_add(f.to_absolute(scale)?)
+        });
+
+    if fractions_total.is_none_or(|total| total > scale) {
+        return Err(DcError::BalanceUnreachable);
+    }
+
+    // double until covered, then bisect between the last two candidates
+    let mut low = fixed_total;
+    let mut high = fixed_total.max(U256::from(1));
+
+    while !covers(high) {
+        low = high;
+        high = high
+            .checked_mul(U256::from(2))
+            .ok_or(DcError::BalanceUnreachable)?;
+    }
+
+    if covers(low) {
+        high = low;
+    }
+
+    while high - low > U256::from(1) {
+        let mid = low + (high - low) / U256::from(2);
+
+        if covers(mid) {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+
+    Ok(RequiredBalanceResponse {
+        required: high,
+        fixed_total,
+        scales_with_balance,
+        note: scales_with_balance.then_some(
+            "fractional amounts are taken from the balance at send time, a larger balance disperses more",
+        ),
+    })
+}
+
+/// Allowances granted by earlier quote steps, by `(token, owner, spender)`, with the approving step
+type AssumedApprovals = HashMap<(Address, Address, Address), (U256, usize)>;
+
+/// Quotes `request.steps` in order, nothing is sent.
+///
+/// Steps are simulated with `estimate_gas` against the current chain state. Approvals of earlier steps
+/// are the only assumed post-state: an ERC20 disperse that relies on one would revert without it, so it
+/// isn't simulated and uses the gas fallback, if configured.
+pub async fn quote(state: &AppState, request: QuoteRequest) -> Result<QuoteResponse, DcError> {
+    let mut approvals = AssumedApprovals::new();
+    let mut steps = Vec::with_capacity(request.steps.len());
+
+    for (index, step) in request.steps.into_iter().enumerate() {
+        let quote = match quote_step(state, step, index, &mut approvals).await {
+            Ok(quote) => quote,
+            Err(e) if e.is_retryable() => return Err(e),
+            Err(e) => StepQuote {
+                error: Some(e.client_message(&state.config().error_templates)),
+                ..Default::default()
+            },
+        };
+
+        steps.push(quote);
+    }
+
+    let total_gas = steps.iter().filter_map(|step| step.gas).sum::<u128>();
+    let fees = state.provider().estimate_eip1559_fees(None).await?;
+    let max_cost = U256::from(total_gas) * U256::from(fees.max_fee_per_gas);
+
+    Ok(QuoteResponse {
+        steps,
+        total_gas,
+        max_cost: TxCost {
+            wei: max_cost,
+            formatted: format_ether(max_cost),
+        },
+    })
+}
+
+async fn quote_step(
+    state: &AppState,
+    mut step: QuoteStep,
+    index: usize,
+    approvals: &mut AssumedApprovals,
+) -> Result<StepQuote, DcError> {
+    let (provider, contract) = (state.provider(), state.contract());
+
+    match &mut step {
+        QuoteStep::Approve(request) => {
+            resolve_decimals(state, Some(request.token), [&mut request.amount]).await?
+        }
+        QuoteStep::Transfer(request) => {
+            resolve_decimals(state, request.token, [&mut request.value]).await?
+        }
+        QuoteStep::DisperseEth(request) => {
+            resolve_decimals(state, None, request.recipients.values_mut()).await?
+        }
+        QuoteStep::DisperseErc20(request) => {
+            let amounts = request.recipients.values_mut();
+            resolve_decimals(state, Some(request.token), amounts).await?
+        }
+    }
+
+    let ensure_covered = |address: Address, required: U256, available: U256| {
+        if required > available {
+            return Err(DcError::InsufficientFunds {
+                required,
+                available,
+                address,
+            });
+        }
+
+        Ok(())
+    };
+
+    let mut approval = None;
+
+    let (mut tx, sender, mut quote) = match step {
+        QuoteStep::Approve(request) => {
+            ensure_verified_token(state, request.token).await?;
+            let token = Erc20Contract::new(request.token, provider.clone());
+            let balance = get_erc20_balance(&token, request.caller).await?;
+            let amount = normalize_amount(request.amount, balance)?;
+            approval = Some(((request.token, request.caller, request.spender), amount));
+
+            let tx = token
+                .approve(request.spender, amount)
+                .into_transaction_request();
+            (tx, request.caller, StepQuote::default())
+        }
+        QuoteStep::Transfer(request) => {
+            validate_checksums(state, &request.recipient)?;
+            validate_recipients(state, [&*request.recipient])?;
+
+            let (tx, amount) = match request.token {
+                Some(address) => {
+                    ensure_verified_token(state, address).await?;
+                    let token = Erc20Contract::new(address, provider.clone());
+                    let balance = get_erc20_balance(&token, request.caller).await?;
+                    let amount = normalize_amount(request.value, balance)?;
+                    ensure_covered(request.caller, amount, balance)?;
+
+                    let tx = token
+                        .transfer(*request.recipient, amount)
+                        .into_transaction_request();
+                    (tx, amount)
+                }
+                None => {
+                    let balance = provider.get_balance(request.caller).await?;
+                    let amount = normalize_amount(request.value, balance)?;
+                    ensure_covered(request.caller, amount, balance)?;
+
+                    let tx = TransactionRequest::default()
+                        .value(amount)
+                        .to(*request.recipient);
+                    (WithOtherFields::new(tx), amount)
+                }
+            };
+
+            let quote = StepQuote {
+                required_balance: Some(amount),
+                ..Default::default()
+            };
+            (tx, request.caller, quote)
+        }
+        QuoteStep::DisperseEth(request) => {
+            validate_checksums(state, &request.recipients)?;
+            validate_recipients(state, request.recipients.keys())?;
+            let balance = provider.get_balance(request.caller).await?;
+            let recipients = construct_disperse_recipients(
+                request.caller,
+                balance,
+                request.recipients.into_iter(),
+                request.remainder_to,
+                state.config().zero_amounts,
+            )?;
+
+            let quote = StepQuote {
+                required_balance: Some(recipients.total),
+                ..Default::default()
+            };
+            let tx = contract
+                .disperseEth(recipients.addresses, recipients.amounts)
+                .value(recipients.total)
+                .into_transaction_request();
+            (tx, request.caller, quote)
+        }
+        QuoteStep::DisperseErc20(request) => {
+            validate_checksums(state, &request.recipients)?;
+            validate_recipients(state, request.recipients.keys())?;
+            ensure_relay_allowed(state, request.caller, request.spender)?;
+            ensure_verified_token(state, request.token).await?;
+            let token = Erc20Contract::new(request.token, provider.clone());
+            let erc20_quote = get_erc20_quote(state, &token, request.spender, false).await?;
+            let recipients = construct_disperse_recipients(
+                request.spender,
+                erc20_quote.balance,
+                request.recipients.into_iter(),
+                request.remainder_to,
+                state.config().zero_amounts,
+            )?;
+
+            let quote = StepQuote {
+                required_balance: Some(recipients.total),
+                required_allowance: Some(recipients.total),
+                ..Default::default()
+            };
+
+            let approving = approvals
+                .get(&(request.token, request.spender, *contract.address()))
+                .filter(|(allowance, _)| *allowance >= recipients.total)
+                .map(|(_, step)| *step);
+
+            if let Some(approving) = approving.filter(|_| erc20_quote.allowance < recipients.total)
+            {
+                let gas_fallback = state.config().gas_fallback;
+
+                return Ok(StepQuote {
+                    success: true,
+                    gas: gas_fallback.map(|f| f.gas_limit(recipients.addresses.len())),
+                    depends_on: Some(approving),
+                    ..quote
+                });
+            }
+
+            let tx = contract
+                .disperseERC20(
+                    request.spender,
+                    request.token,
+                    recipients.addresses,
+                    recipients.amounts,
+                )
+                .into_transaction_request();
+            (tx, request.caller, quote)
+        }
+    };
+
+    tx.set_from(sender);
+
+    match provider.estimate_gas(&tx).await {
+        Ok(gas) => {
+            quote.success = true;
+            quote.gas = Some(gas);
+
+            if let Some((key, amount)) = approval {
+                approvals.insert(key, (amount, index));
+            }
+        }
+        Err(RpcError::ErrorResp(payload)) => {
+            quote.error = Some(
+                DcError::SimulationReverted(payload.message)
+                    .client_message(&state.config().error_templates),
+            );
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(quote)
+}
+
+/// Signer that sends transactions on behalf of `caller`
+async fn resolve_signer(state: &AppState, caller: Address) -> Result<Address, DcError> {
+    let provider = state.provider();
+
+    if provider.has_signer_for(&caller) {
+        return Ok(caller);
+    }
+
+    let code = provider.get_code_at(caller).await?;
+
+    Err(if code.is_empty() {
+        DcError::SignerNotFound(caller)
+    } else {
+        DcError::CallerIsContract(caller)
+    })
+}
+
+/// Signs and sends `tx` with the next local nonce of `signer`.
+///
+/// A transaction the node already knows is tracked by its hash. On any other failure the nonce is
+/// resynced with the node, and if the nonce was too low or too high the send is retried once.
+/// Returns the hash and the gas settings of the signed transaction.
+async fn send_with_nonce(
+    state: &AppState,
+    tx: WithOtherFields<TransactionRequest>,
+    signer: Address,
+) -> Result<(B256, GasParams), DcError> {
+    let (provider, nonces) = (state.provider(), state.nonces());
+    let mut retried = false;
+
+    loop {
+        let mut tx = tx.clone();
+        tx.set_nonce(nonces.next(provider, signer).await?);
+
+        let envelope = match fill_transaction(state, tx).await {
+            Ok(SendableTx::Envelope(envelope)) => envelope,
+            Ok(SendableTx::Builder(_)) => {
+                nonces.resync(signer).await;
+                return Err(DcError::SignerNotFound(signer));
+            }
+            Err(e) => {
+                nonces.resync(signer).await;
+                return Err(e.into());
+            }
+        };
+        let tx_hash = *envelope.tx_hash();
+        let gas_params = gas_params(&envelope);
+
+        let e = match provider.send_tx_envelope(envelope).await {
+            Ok(pending) => return Ok((*pending.tx_hash(), gas_params)),
+            Err(e) => e,
+        };
+
+        match NonceError::from_rpc_error(&e) {
+            Some(NonceError::AlreadyKnown) => return Ok((tx_hash, gas_params)),
+            Some(NonceError::TooLow) => {
+                if provider.get_transaction_by_hash(tx_hash).await?.is_some() {
+                    return Ok((tx_hash, gas_params));
+                }
+            }
+            Some(NonceError::TooHigh) => {}
+            None => {
+                nonces.resync(signer).await;
+                return Err(e.into());
+            }
+        }
+
+        nonces.resync(signer).await;
+
+        if retried {
+            return Err(e.into());
+        }
+
+        warn!(
+            "Nonce of {} is out of sync, retrying",
+            state.redacted(signer)
+        );
+        retried = true;
+    }
+}
+
+fn gas_params(envelope: &TxEnvelope) -> GasParams {
+    let (max_fee_per_gas, max_priority_fee_per_gas) = match envelope {
+        TxEnvelope::Eip1559(signed) => (
+            Some(signed.tx().max_fee_per_gas),
+            Some(signed.tx().max_priority_fee_per_gas),
+        ),
+        TxEnvelope::Eip4844(signed) => {
+            let tx = signed.tx().tx();
+            (Some(tx.max_fee_per_gas), Some(tx.max_priority_fee_per_gas))
+        }
+        _ => (None, None),
+    };
+
+    GasParams {
+        gas_limit: envelope.gas_limit(),
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+        gas_price: envelope.gas_price(),
+    }
+}
+
+/// Fills gas, chain id and the signature. If the fillers fail, e.g. on a node hiccup, gas and chain id
+/// are fetched with separate calls and the fill is retried without them
+async fn fill_transaction(
+    state: &AppState,
+    mut tx: WithOtherFields<TransactionRequest>,
+) -> TransportResult<SendableTx<AppNetwork>> {
+    let provider = state.provider();
+
+    let e = match provider.fill(tx.clone()).await {
+        Ok(sendable) => return Ok(sendable),
+        Err(e) => e,
+    };
+
+    warn!("Filling the transaction failed, setting gas and chain id manually: {e}");
+
+    if tx.chain_id.is_none() {
+        tx.set_chain_id(provider.get_chain_id().await?);
+    }
+
+    if tx.gas.is_none() {
+        tx.set_gas_limit(provider.estimate_gas(&tx).await?);
+    }
+
+    if tx.gas_price.is_none() && tx.max_fee_per_gas.is_none() {
+        let fees = provider.estimate_eip1559_fees(None).await?;
+        tx.set_max_fee_per_gas(fees.max_fee_per_gas);
+        tx.set_max_priority_fee_per_gas(fees.max_priority_fee_per_gas);
+    }
+
+    provider.fill(tx).await
+}
+
+/// Transactions are simulated instead of broadcast, for the whole service or this request
+fn is_simulating(state: &AppState, options: &TxOptions) -> bool {
+    state.config().simulate_only || options.dry_run
+}
+
+async fn submit_transaction(
+    state: &AppState,
+    mut tx: WithOtherFields<TransactionRequest>,
+    signer: Address,
+    recipients: usize,
+    options: TxOptions,
+) -> Result<TransactionResponse, DcError> {
+    let provider = state.provider();
+    let fee_override = fee_override(&options)?;
+
+    let signer = resolve_signer(state, signer).await?;
+    ensure_gas_budget(state, signer)?;
+    tx.set_from(signer);
+
+    // overrides the fee filler, ETH spends already carry the override from their reservation
+    if let Some((max_fee_per_gas, max_priority_fee_per_gas)) = fee_override {
+        tx.set_max_fee_per_gas(max_fee_per_gas);
+        tx.set_max_priority_fee_per_gas(max_priority_fee_per_gas);
+    }
+
+    #[cfg(feature = "blobs")]
+    if let Some(blob) = &options.blob {
+        set_blob_fields(&mut tx, blob, is_simulating(state, &options))?;
+    }
+
+    // the gas filler prices a transaction carrying an access list as EIP-2930, which can't hold blobs
+    if state.config().use_access_lists && tx.sidecar.is_none() {
+        match provider.create_access_list(&tx).await {
+            Ok(result) => tx.set_access_list(result.access_list),
+            Err(e) if is_method_not_found(&e) => {
+                warn!("Node doesn't support eth_createAccessList, sending without an access list: {e}");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let decoded = match tx.input.input() {
+        Some(input) if options.decode => decode_call(input),
+        _ => None,
+    };
+
+    if is_simulating(state, &options) {
+        if !options.skip_simulation {
+            provider.call(&tx).await.map_err(|e| match e {
+                RpcError::ErrorResp(payload) => DcError::SimulationReverted(payload.message),
+                e => e.into(),
+            })?;
+        }
+
+        return Ok(TransactionResponse {
+            tx_hash: B256::ZERO,
+            gas_used: 0,
+            effective_gas_price: U256::ZERO,
+            fee: U256::ZERO,
+            simulated: true,
+            tx_cost: None,
+            decoded,
+            explorer_url: None,
+            balance_after: None,
+            transaction_index: None,
+            gas_params: None,
+            logs: Vec::new(),
+        });
+    }
+
+    // ETH spends reserve their gas up front
+    let fallback = state.config().gas_fallback.filter(|_| tx.gas.is_none());
+
+    if let Some(fallback) = fallback {
+        let gas_limit = match provider.estimate_gas(&tx).await {
+            Ok(gas_limit) => gas_limit,
+            Err(e) => {
+                let gas_limit = fallback.gas_limit(recipients);
+                warn!("Gas estimation failed, using fallback gas limit {gas_limit}: {e}");
+                gas_limit
+            }
+        };
+
+        tx.set_gas_limit(gas_limit);
+    }
+
+    // the gas filler prices a transaction carrying an access list as EIP-2930 and would
+    // drop the fee caps, settling the gas limit here keeps the override
+    if tx.gas.is_none() && fee_override.is_some() {
+        tx.set_gas_limit(provider.estimate_gas(&tx).await?);
+    }
+
+    let replay = tx.clone();
+    let (tx_hash, gas_params) = send_with_nonce(state, tx, signer).await?;
+
+    if let Some(sink) = &options.broadcast_sink {
+        // the client may have disconnected, the transaction is tracked regardless
+        let _ = sink.send(tx_hash);
+    }
+
+    let confirmations = options
+        .confirmations
+        .unwrap_or(state.config().confirmations);
+    let waiting = AbandonedReceipt(Some(tx_hash));
+    let receipt = wait_for_receipt(state, tx_hash, confirmations).await?;
+    waiting.received();
+
+    metrics::TRANSACTION_GAS_USED.record(&[], receipt.gas_used as f64);
+    let cost = U256::from(receipt.gas_used) * U256::from(receipt.effective_gas_price);
+    state.usage().record(signer, cost);
+
+    if !receipt.inner.inner.status() {
+        let reason = revert_reason(state, &replay, receipt.block_number).await;
+        return Err(DcError::TransactionReverted { tx_hash, reason });
+    }
+
+    let gas_used = u64::try_from(receipt.gas_used).map_err(DcError::unexpected)?;
+    let tx_cost = options.include_cost.then(|| TxCost {
+        wei: cost,
+        formatted: format_ether(cost),
+    });
+
+    Ok(TransactionResponse {
+        tx_hash: receipt.transaction_hash,
+        gas_used,
+        effective_gas_price: U256::from(receipt.effective_gas_price),
+        fee: cost,
+        simulated: false,
+        tx_cost,
+        decoded,
+        explorer_url: state
+            .explorer_url()
+            .as_ref()
+            .and_then(|url| url.join(&receipt.transaction_hash.to_string()).ok()),
+        balance_after: None,
+        transaction_index: receipt.transaction_index.filter(|_| options.include_index),
+        gas_params: options.include_gas_params.then_some(gas_params),
+        logs: receipt.inner.inner.logs().to_vec(),
+    })
+}
+
+/// Replays a reverted transaction with `eth_call` at its block to recover the revert reason.
+/// The replay sees the state after the whole block, so the reason may differ or be missing
+async fn revert_reason(
+    state: &AppState,
+    tx: &WithOtherFields<TransactionRequest>,
+    block_number: Option<u64>,
+) -> Option<String> {
+    let call = state.provider().call(tx);
+    let call = match block_number {
+        Some(number) => call.block(BlockId::number(number)),
+        None => call,
+    };
+
+    match call.await {
+        Err(RpcError::ErrorResp(payload)) => Some(payload.message),
+        _ => None,
+    }
+}
+
+/// Whether the node doesn't implement the called method, the message is checked as well as not all
+/// clients use the standard `-32601` code
+fn is_method_not_found(e: &RpcError<TransportErrorKind>) -> bool {
+    e.as_error_resp().is_some_and(|payload| {
+        let message = payload.message.to_lowercase();
+
+        payload.code == -32601
+            || message.contains("method not found")
+            || message.contains("does not exist")
+            || message.contains("not supported")
+    })
+}
+
+/// Logs the hash of a broadcast transaction if waiting for its receipt stops early,
+/// e.g. when the request is dropped on shutdown
+struct AbandonedReceipt(Option<B256>);
+
+impl AbandonedReceipt {
+    fn received(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for AbandonedReceipt {
+    fn drop(&mut self) {
+        if let Some(tx_hash) = self.0 {
+            warn!(
+                "Stopped waiting for the receipt of transaction {tx_hash}, it may still be mined"
+            );
+        }
+    }
+}
+
+/// Waits until the transaction's block has `confirmations - 1` blocks on top of it,
+/// for at most `confirmation_timeout_secs`
+async fn wait_for_receipt(
+    state: &AppState,
+    tx_hash: B256,
+    confirmations: u64,
+) -> Result<<AppNetwork as Network>::ReceiptResponse, DcError> {
+    let provider = state.provider();
+
+    let wait = async {
+        let receipt = PendingTransactionBuilder::new(provider.root(), tx_hash)
+            .get_receipt()
+            .await?;
+
+        // alloy returns the receipt as soon as it's available, regardless of required confirmations
+        if let Some(block_number) = receipt.block_number {
+            let target = block_number.saturating_add(confirmations.saturating_sub(1));
+
+            while provider.get_block_number().await? < target {
+                tokio::time::sleep(provider.client().poll_interval()).await;
+            }
+        }
+
+        Ok(receipt)
+    };
+
+    let result = match state.config().confirmation_timeout_secs {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), wait)
+            .await
+            .map_err(|_| DcError::ConfirmationTimeout { tx_hash })?,
+        None => wait.await,
+    };
+
+    // the transaction is out, a stalled node mustn't hide its hash from the client
+    result.map_err(|e| match e {
+        DcError::Transport(kind) if RpcTimeout::is_timeout(&kind) => {
+            DcError::ConfirmationTimeout { tx_hash }
+        }
+        e => e,
+    })
+}
+
+/// Gas cost set aside when a transaction spends from the sender's ETH balance
+struct GasReservation {
+    gas_limit: u128,
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+}
+
+impl GasReservation {
+    /// Most the transaction may pay for gas
+    fn cost(&self) -> U256 {
+        U256::from(self.gas_limit) * U256::from(self.max_fee_per_gas)
+    }
+
+    /// Caps the transaction's gas at the reserved amount, so the reservation covers it when fees rise
+    fn apply(&self, tx: &mut WithOtherFields<TransactionRequest>) {
+        tx.set_gas_limit(self.gas_limit);
+        tx.set_max_fee_per_gas(self.max_fee_per_gas);
+        tx.set_max_priority_fee_per_gas(self.max_priority_fee_per_gas);
+    }
+}
+
+/// Estimates the gas of `tx` sent by `sender`, falling back to `gas_fallback` if configured.
+/// Fees are estimated too unless the request overrides them
+async fn reserve_gas(
+    state: &AppState,
+    tx: &WithOtherFields<TransactionRequest>,
+    sender: Address,
+    recipients: usize,
+    options: &TxOptions,
+) -> Result<GasReservation, DcError> {
+    let provider = state.provider();
+    let fee_override = fee_override(options)?;
+    let tx = tx.clone().with_from(sender);
+
+    let gas_limit = match (
+        provider.estimate_gas(&tx).await,
+        state.config().gas_fallback,
+    ) {
+        (Ok(gas_limit), _) => gas_limit,
+        (Err(e), Some(fallback)) => {
+            let gas_limit = fallback.gas_limit(recipients);
+            warn!("Gas estimation failed, reserving fallback gas limit {gas_limit}: {e}");
+            gas_limit
+        }
+        (Err(e), None) => return Err(e.into()),
+    };
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) = match fee_override {
+        Some(fees) => fees,
+        None => {
+            let fees = provider.estimate_eip1559_fees(None).await?;
+            (fees.max_fee_per_gas, fees.max_priority_fee_per_gas)
+        }
+    };
+
+    Ok(GasReservation {
+        gas_limit,
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+    })
+}
+
+/// `maxFeePerGas` and `maxPriorityFeePerGas` of `options`, they must be set together
+fn fee_override(options: &TxOptions) -> Result<Option<(u128, u128)>, DcError> {
+    match (options.max_fee_per_gas, options.max_priority_fee_per_gas) {
+        (Some(max_fee), Some(priority_fee)) if priority_fee > max_fee => Err(
+            DcError::InvalidFeeOverride("maxPriorityFeePerGas can't exceed maxFeePerGas"),
+        ),
+        (Some(max_fee), Some(priority_fee)) => Ok(Some((max_fee.into(), priority_fee.into()))),
+        (None, None) => Ok(None),
+        _ => Err(DcError::InvalidFeeOverride(
+            "maxFeePerGas and maxPriorityFeePerGas must be set together",
+        )),
+    }
+}
+
+/// Sets the blob fields of `tx`, only a simulation can do without the sidecar
+#[cfg(feature = "blobs")]
+fn set_blob_fields(
+    tx: &mut WithOtherFields<TransactionRequest>,
+    blob: &BlobFields,
+    simulating: bool,
+) -> Result<(), DcError> {
+    let hashes = match &blob.sidecar {
+        Some(sidecar) => {
+            let hashes: Vec<_> = sidecar.versioned_hashes().collect();
+            if !blob.blob_versioned_hashes.is_empty() && blob.blob_versioned_hashes != hashes {
+                return Err(DcError::InvalidBlobFields(
+                    "blobVersionedHashes don't match the sidecar",
+                ));
+            }
+            tx.sidecar = Some(sidecar.clone());
+            hashes
+        }
+        None if !simulating => {
+            return Err(DcError::InvalidBlobFields(
+                "a sidecar is required to broadcast a blob transaction",
+            ))
+        }
+        None => blob.blob_versioned_hashes.clone(),
+    };
+
+    if hashes.is_empty() {
+        return Err(DcError::InvalidBlobFields("no blobs to attach"));
+    }
+    tx.blob_versioned_hashes = Some(hashes);
+    tx.max_fee_per_blob_gas = blob.max_fee_per_blob_gas.map(Into::into);
+
+    Ok(())
+}
+
+struct DisperseRecipients {
+    addresses: Vec<Address>,
+    amounts: Vec<U256>,
+    total: U256,
+    skipped: Vec<SkippedEntry>,
+}
+
+impl DisperseRecipients {
+    fn skip_all(&mut self, addresses: Vec<Address>, reason: SkipReason) {
+        self.skipped.extend(
+            addresses
+                .into_iter()
+                .map(|address| SkippedEntry { address, reason }),
+        );
+    }
+
+    fn into_transfers(self) -> BTreeMap<Address, U256> {
+        BTreeMap::from_iter(self.addresses.into_iter().zip(self.amounts))
+    }
+
+    /// `(from, to, amount)` of every transfer
+    fn transfers_from(&self, from: Address) -> Vec<(Address, Address, U256)> {
+        self.addresses
+            .iter()
+            .zip(&self.amounts)
+            .map(|(to, amount)| (from, *to, *amount))
+            .collect()
+    }
+
+    /// `inputs` are the requested amounts these recipients were constructed from
+    fn breakdown(
+        &self,
+        balance: U256,
+        block_number: u64,
+        inputs: &BTreeMap<Address, FractionOrAmount>,
+    ) -> DisperseBreakdown {
+        let recipients = self
+            .addresses
+            .iter()
+            .zip(&self.amounts)
+            .map(|(address, amount)| {
+                let (fraction, units) = match inputs.get(address).and_then(|a| a.fraction()) {
+                    Some(f) => (Some(f.fraction), Some(f.units)),
+                    None => (None, None),
+                };
+
+                let breakdown = RecipientBreakdown {
+                    fraction,
+                    units,
+                    amount: *amount,
+                };
+
+                (*address, breakdown)
+            })
+            .collect();
+
+        DisperseBreakdown {
+            balance,
+            block_number,
+            total: self.total,
+            recipients,
+        }
+    }
+}
+
+fn construct_disperse_recipients(
+    sender: Address,
+    total_balance: U256,
+    recipients: impl Iterator<Item = (Address, FractionOrAmount)>,
+    remainder_to: Option<Address>,
+    zero_amounts: ZeroAmounts,
+) -> Result<DisperseRecipients, DcError> {
+    let mut recipients: Vec<_> = recipients.collect();
+    ensure_basis_points(recipients.iter().map(|(_, amount)| amount))?;
+    let remainder_to = weights_to_fractions(&mut recipients)?.or(remainder_to);
+    let iter_len = recipients.len();
+
+    let mut addresses = Vec::with_capacity(iter_len);
+    let mut amounts = Vec::with_capacity(iter_len);
+    let mut skipped = Vec::new();
+    let mut sum = U256::ZERO;
+
+    for (address, amount) in recipients {
+        let Some(actual_amount) = batch_amount(address, amount, total_balance, zero_amounts)?
+        else {
+            skipped.push(SkippedEntry {
+                address,
+                reason: SkipReason::ZeroAmount,
+            });
+            continue;
+        };
+
+        sum = sum
+            .checked_add(actual_amount)
+            .ok_or(DcError::AmountOverflow)?;
+
+        addresses.push(address);
+        amounts.push(actual_amount);
+    }
+
+    if sum > total_balance {
+        return Err(DcError::InsufficientFunds {
+            required: sum,
+            available: total_balance,
+            address: sender,
+        });
+    }
+
+    // the whole balance is distributed, including what integer division left over
+    let remainder = total_balance - sum;
+    if let Some(remainder_to) = remainder_to.filter(|_| !remainder.is_zero()) {
+        match addresses
+            .iter()
+            .position(|address| *address == remainder_to)
+        {
+            Some(i) => amounts[i] += remainder,
+            None => {
+                skipped.retain(|entry| entry.address != remainder_to);
+                addresses.push(remainder_to);
+                amounts.push(remainder);
+            }
+        }
+        sum = total_balance;
+    }
+
+    Ok(DisperseRecipients {
+        addresses,
+        amounts,
+        total: sum,
+        skipped,
+    })
+}
+
+/// Replaces weights with their fraction of the total weight, so weighted recipients share the whole
+/// balance. Returns the recipient with the largest weight, the first of them on a tie, which gets
+/// the rounding remainder.
+fn weights_to_fractions(
+    recipients: &mut [(Address, FractionOrAmount)],
+) -> Result<Option<Address>, DcError> {
+    let mut total_weight = U256::ZERO;
+    let mut largest: Option<(Address, U256)> = None;
+
+    for (address, amount) in recipients.iter() {
+        let FractionOrAmount::Weighted { weight } = amount else {
+            continue;
+        };
+
+        total_weight = total_weight
+            .checked_add(*weight)
+            .ok_or(DcError::AmountOverflow)?;
+        if largest.is_none_or(|(_, largest)| *weight > largest) {
+            largest = Some((*address, *weight));
+        }
+    }
+
+    let Some((largest, _)) = largest else {
+        return Ok(None);
+    };
+    if total_weight.is_zero() {
+        return Err(DcError::ZeroTotalWeight);
+    }
+
+    for (_, amount) in recipients.iter_mut() {
+        if let FractionOrAmount::Weighted { weight } = *amount {
+            *amount = FractionOrAmount::Fraction(FractionalAmount {
+                fraction: weight,
+                units: total_weight,
+            });
+        }
+    }
+
+    Ok(Some(largest))
+}
+
+/// Basis points of a disperse can't add up to more than the whole balance
+fn ensure_basis_points<'a>(
+    amounts: impl IntoIterator<Item = &'a FractionOrAmount>,
+) -> Result<(), DcError> {
+    let sum = amounts
+        .into_iter()
+        .filter_map(|amount| match amount {
+            FractionOrAmount::BasisPoints { bps } => Some(u64::from(*bps)),
+            _ => None,
+        })
+        .sum();
+
+    if sum > u64::from(FractionalAmount::BASIS_POINTS) {
+        return Err(DcError::BasisPointsExceeded(sum));
+    }
+
+    Ok(())
+}
+
+/// Fetches the latest block number to pin balance reads to, if `pin` is set
+async fn get_pinned_block(state: &AppState, pin: bool) -> Result<Option<u64>, DcError> {
+    if !pin {
+        return Ok(None);
+    }
+
+    Ok(Some(state.provider().get_block_number().await?))
+}
+
+fn block_id(block_number: Option<u64>) -> BlockId {
+    block_number.map_or(BlockId::latest(), BlockId::number)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use alloy::{
+        consensus::TxEnvelope,
+        eips::eip2718::Decodable2718,
+        primitives::{keccak256, Address, Bytes, B256, U256},
+        providers::Provider,
+        rpc::types::TransactionRequest,
+        serde::WithOtherFields,
+        sol_types::{SolCall, SolEvent},
+    };
+    use serde_json::{json, Value};
+
+    use super::{
+        collect_erc20, collect_erc20_chunked, construct_disperse_recipients, disperse_erc20,
+        disperse_eth, disperse_eth_chunked, gas_params, get_contract_recipients,
+        get_transaction_status, recover_pending, replace_pending, send_with_nonce, split_equally,
+        token_metadata, validate_checksums, validate_recipients, wait_for_receipt, DcError,
+    };
+    use crate::{
+        config::ZeroAmounts,
+        contracts::{DisperseCollect, IDisperseOutcomes, IERC20},
+        dto::{
+            DisperseErc20Request, DisperseEthRequest, FractionOrAmount, FractionalAmount,
+            SkipReason, TransactionStatus, TxOptions,
+        },
+        testing::{self, call_selector, fields, MockNode, Reply, CONTRACT, SIGNER, SPENDER, TOKEN},
+    };
+
+    /// Token holding `balance` for every owner, approvals are applied once broadcast
+    fn token_node(balance: U256, allowance: U256) -> MockNode {
+        MockNode::new(token(balance, allowance))
+    }
+
+    fn token(
+        balance: U256,
+        allowance: U256,
+    ) -> impl Fn(&str, &Value) -> Option<Reply> + Send + Sync + 'static {
+        let allowance = Arc::new(Mutex::new(allowance));
+
+        move |method, params| match method {
+            "eth_call" => match call_selector(params) {
+                (TOKEN, IERC20::allowanceCall::SELECTOR) => Some(Reply::data(
+                    IERC20::allowanceCall::abi_encode_returns(&(*allowance.lock().unwrap(),)),
+                )),
+                (TOKEN, IERC20::balanceOfCall::SELECTOR) => Some(Reply::data(
+                    IERC20::balanceOfCall::abi_encode_returns(&(balance,)),
+                )),
+                (TOKEN, IERC20::approveCall::SELECTOR) => Some(Reply::data(
+                    IERC20::approveCall::abi_encode_returns(&(true,)),
+                )),
+                (TOKEN, IERC20::decimalsCall::SELECTOR) => Some(Reply::data(
+                    IERC20::decimalsCall::abi_encode_returns(&(18,)),
+                )),
+                (CONTRACT, _) => Some(Reply::data(Bytes::new())),
+                _ => None,
+            },
+            "eth_sendRawTransaction" => {
+                let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+                let envelope = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+                if let Ok(call) = IERC20::approveCall::abi_decode(fields(&envelope).input(), true) {
+                    *allowance.lock().unwrap() = call.amount;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the broadcast transaction calls the function with `selector`
+    fn sends(params: &Value, selector: [u8; 4]) -> bool {
+        let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+        let envelope = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+
+        fields(&envelope).input().starts_with(&selector)
+    }
+
+    #[tokio::test]
+    async fn should_cache_the_token_metadata() {
+        let node = MockNode::new(|method, params| match method {
+            "eth_call" => match call_selector(params) {
+                (TOKEN, IERC20::decimalsCall::SELECTOR) => {
+                    Some(Reply::data(IERC20::decimalsCall::abi_encode_returns(&(6,))))
+                }
+                (TOKEN, IERC20::symbolCall::SELECTOR) => Some(Reply::data(
+                    IERC20::symbolCall::abi_encode_returns(&("USDC".to_string(),)),
+                )),
+                (TOKEN, IERC20::nameCall::SELECTOR) => Some(Reply::data(
+                    IERC20::nameCall::abi_encode_returns(&("USD Coin".to_string(),)),
+                )),
+                _ => None,
+            },
+            _ => None,
+        });
+
+        let state = testing::state(&node, json!({})).await;
+        for _ in 0..2 {
+            let response = token_metadata(&state, TOKEN).await.unwrap();
+            assert_eq!(response.symbol, "USDC");
+            assert_eq!(response.name, "USD Coin");
+            assert_eq!(response.decimals, 6);
+        }
+        assert_eq!(node.calls().len(), 3);
+
+        let state =
+            testing::state(&node, json!({ "token_decimals": { TOKEN.to_string(): 8 } })).await;
+        let response = token_metadata(&state, TOKEN).await.unwrap();
+        assert_eq!(response.decimals, 8);
+        assert_eq!(node.calls().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn should_reserve_the_fallback_gas_when_estimation_fails() {
+        let node = MockNode::new(|method, _| match method {
+            "eth_getBalance" => Some(Reply::Ok(json!(U256::from(10).pow(U256::from(20))))),
+            "eth_estimateGas" => Some(Reply::Rpc(-32000, "gas required exceeds allowance".into())),
+            _ => None,
+        });
+        let config = json!({ "gas_fallback": { "base": 50000, "per_recipient": 10000 } });
+        let state = testing::state(&node, config).await;
+        let request = serde_json::from_value(json!({
+            "caller": SIGNER,
+            "recipients": {
+                Address::repeat_byte(1).to_string(): { "amount": "1" },
+                Address::repeat_byte(2).to_string(): { "amount": "1" },
+            },
+        }))
+        .unwrap();
+
+        disperse_eth(&state, request, false, TxOptions::default())
+            .await
+            .unwrap();
+
+        let sent = node.sent();
+        assert_eq!(fields(&sent[0].1).gas_limit(), 70000);
+    }
+
+    fn collect_request(amount: u64) -> Value {
+        json!({
+            "caller": SIGNER,
+            "recipient": Address::repeat_byte(9),
+            "token": TOKEN,
+            "spenders": { SPENDER.to_string(): { "amount": amount.to_string() } },
+        })
+    }
+
+    #[tokio::test]
+    async fn should_only_collect_tokens_with_verified_code() {
+        let node = token_node(U256::from(1000), U256::from(1000));
+        let token_code_hash = keccak256([0x60, 0x80]);
+        let state = |verified: B256| {
+            testing::state(&node, json!({ "verified_token_codehashes": [verified] }))
+        };
+
+        let unverified = state(B256::repeat_byte(1)).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+        let result = collect_erc20(&unverified, request, false, false, TxOptions::default()).await;
+        assert!(matches!(
+            result,
+            Err(DcError::UnverifiedToken { token, code_hash })
+                if token == TOKEN && code_hash == token_code_hash
+        ));
+        assert!(node.sent().is_empty());
+
+        let verified = state(token_code_hash).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+        collect_erc20(&verified, request, false, false, TxOptions::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_recheck_allowances_revoked_before_the_collect() {
+        // the allowance drops to 300 after it was read for the plan
+        let node = || {
+            let base = token(U256::from(1000), U256::from(1000));
+            let reads = AtomicUsize::new(0);
+            MockNode::new(move |method, params| match method {
+                "eth_call"
+                    if call_selector(params) == (TOKEN, IERC20::allowanceCall::SELECTOR)
+                        && reads.fetch_add(1, Ordering::SeqCst) > 0 =>
+                {
+                    Some(Reply::data(IERC20::allowanceCall::abi_encode_returns(&(
+                        U256::from(300),
+                    ))))
+                }
+                _ => base(method, params),
+            })
+        };
+
+        let failing = node();
+        let state = testing::state(&failing, json!({ "collect_allowance_recheck": "fail" })).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+        let result = collect_erc20(&state, request, false, false, TxOptions::default()).await;
+        assert!(matches!(
+            result,
+            Err(DcError::AllowanceChanged { address, required, allowance })
+                if address == SPENDER
+                    && required == U256::from(500)
+                    && allowance == U256::from(300)
+        ));
+        assert!(failing.sent().is_empty());
+
+        let clamping = node();
+        let state =
+            testing::state(&clamping, json!({ "collect_allowance_recheck": "clamp" })).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+        let response = collect_erc20(&state, request, false, false, TxOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response.0.transfers[&SPENDER], U256::from(300));
+    }
+
+    fn disperse_request() -> DisperseErc20Request {
+        serde_json::from_value(json!({
+            "caller": SIGNER,
+            "spender": SIGNER,
+            "token": TOKEN,
+            "recipients": { Address::repeat_byte(9).to_string(): { "amount": "100" } },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_leave_contract_recipients_out_of_the_disperse() {
+        let node = token_node(U256::from(1000), U256::from(1000));
+        let state = testing::state(&node, json!({ "disperse_contract_recipients": "skip" })).await;
+        let mut request = disperse_request();
+        request.recipients.insert(
+            CONTRACT,
+            serde_json::from_value(json!({ "amount": "100" })).unwrap(),
+        );
+
+        let response = disperse_erc20(&state, request, false, false, TxOptions::default())
+            .await
+            .unwrap()
+            .0;
+
+        assert_eq!(
+            response.transfers.keys().collect::<Vec<_>>(),
+            [&Address::repeat_byte(9)]
+        );
+        let [skipped] = &response.skipped[..] else {
+            panic!("expected one skipped recipient, got {:?}", response.skipped);
+        };
+        assert_eq!(
+            (skipped.address, skipped.reason),
+            (CONTRACT, SkipReason::IsContract)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_stop_sending_once_the_gas_budget_is_spent() {
+        let base = token(U256::from(1000), U256::from(1000));
+        let node = MockNode::new(move |method, params| match method {
+            "eth_getTransactionReceipt" => {
+                let tx_hash = serde_json::from_value(params[0].clone()).unwrap();
+                let mut receipt = testing::receipt(tx_hash, true);
+                // 21000 gas at 1 gwei
+                receipt["effectiveGasPrice"] = json!("0x3b9aca00");
+                Some(Reply::Ok(receipt))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({ "caller_gas_budget_gwei": 21000 })).await;
+
+        disperse_erc20(
+            &state,
+            disperse_request(),
+            false,
+            false,
+            TxOptions::default(),
+        )
+        .await
+        .unwrap();
+        let result = disperse_erc20(
+            &state,
+            disperse_request(),
+            false,
+            false,
+            TxOptions::default(),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(DcError::GasBudgetExceeded { caller, .. }) if caller == SIGNER
+        ));
+        assert_eq!(node.sent().len(), 1);
+    }
+
+    fn transfer() -> WithOtherFields<TransactionRequest> {
+        WithOtherFields::new(
+            TransactionRequest::default()
+                .from(SIGNER)
+                .to(Address::repeat_byte(9))
+                .value(U256::from(1)),
+        )
+    }
+
+    #[tokio::test]
+    async fn should_resend_with_the_node_nonce_after_a_nonce_too_low() {
+        let rejected = Arc::new(Mutex::new(false));
+        let node = MockNode::new(move |method, _| match method {
+            "eth_getTransactionCount" => Some(Reply::Ok(json!("0x5"))),
+            "eth_sendRawTransaction"
+                if !std::mem::replace(&mut *rejected.lock().unwrap(), true) =>
+            {
+                Some(Reply::Rpc(-32000, "nonce too low".into()))
+            }
+            _ => None,
+        });
+        let state = testing::state(&node, json!({})).await;
+        // the local nonce is behind the node's
+        state.nonces().next(state.provider(), SIGNER).await.unwrap();
+
+        let (tx_hash, _) = send_with_nonce(&state, transfer(), SIGNER).await.unwrap();
+
+        let nonces: Vec<_> = node
+            .sent()
+            .iter()
+            .map(|(_, tx)| fields(tx).nonce())
+            .collect();
+        assert_eq!(nonces, [6, 5]);
+        assert_eq!(tx_hash, *node.sent()[1].1.tx_hash());
+        assert_eq!(
+            state.nonces().next(state.provider(), SIGNER).await.unwrap(),
+            6
+        );
+    }
+
+    #[tokio::test]
+    async fn should_track_a_transaction_the_node_already_knows() {
+        let node = MockNode::new(|method, _| match method {
+            "eth_sendRawTransaction" => Some(Reply::Rpc(-32000, "already known".into())),
+            _ => None,
+        });
+        let state = testing::state(&node, json!({})).await;
+
+        let (tx_hash, _) = send_with_nonce(&state, transfer(), SIGNER).await.unwrap();
+
+        assert_eq!(node.sent().len(), 1);
+        assert_eq!(tx_hash, *node.sent()[0].1.tx_hash());
+    }
+
+    #[tokio::test]
+    async fn should_sum_up_the_collected_chunks() {
+        let base = token(U256::from(1000), U256::from(1000));
+        let collects = AtomicUsize::new(0);
+        let node = MockNode::new(move |method, params| match method {
+            "eth_sendRawTransaction"
+                if sends(params, DisperseCollect::collectERC20Call::SELECTOR)
+                    && collects.fetch_add(1, Ordering::SeqCst) == 1 =>
+            {
+                Some(Reply::Rpc(-32000, "insufficient funds for gas".into()))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({})).await;
+        let spenders: Vec<_> = (1..=3).map(Address::repeat_byte).collect();
+        let mut request = collect_request(100);
+        request["spenders"] = spenders
+            .iter()
+            .map(|a| (a.to_string(), json!({ "amount": "100" })))
+            .collect();
+        let request = serde_json::from_value(request).unwrap();
+
+        let response = collect_erc20_chunked(
+            &state,
+            request,
+            NonZeroUsize::MIN,
+            true,
+            false,
+            TxOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(node.sent().len(), 2);
+        let errors: Vec<_> = response.chunks.iter().map(|c| c.error.as_deref()).collect();
+        assert!(errors[0].is_none() && errors[1].is_some());
+        assert_eq!(errors[2], Some("not sent, a previous chunk failed"));
+        let breakdown = response.spenders.unwrap();
+        assert_eq!(breakdown[&spenders[0]].collected, U256::from(100));
+        assert_eq!(breakdown[&spenders[1]].collected, U256::ZERO);
+    }
+
+    #[tokio::test]
+    async fn should_reject_amounts_above_the_total_supply() {
+        let base = token(U256::from(1000), U256::from(1000));
+        let node = MockNode::new(move |method, params| match method {
+            "eth_call" if call_selector(params) == (TOKEN, IERC20::totalSupplyCall::SELECTOR) => {
+                Some(Reply::data(IERC20::totalSupplyCall::abi_encode_returns(&(
+                    U256::from(1000),
+                ))))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({ "check_total_supply": true })).await;
+
+        let request = serde_json::from_value(collect_request(2000)).unwrap();
+        let result = collect_erc20(&state, request, false, false, TxOptions::default()).await;
+        assert!(matches!(
+            result,
+            Err(DcError::ExceedsTotalSupply { amount, total_supply, .. })
+                if amount == U256::from(2000) && total_supply == U256::from(1000)
+        ));
+        assert!(node.sent().is_empty());
+
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+        collect_erc20(&state, request, false, false, TxOptions::default())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_leave_the_gas_when_dispersing_the_whole_balance() {
+        let balance = U256::from(10).pow(U256::from(18));
+        let node = MockNode::new(move |method, _| match method {
+            "eth_getBalance" => Some(Reply::Ok(json!(balance))),
+            _ => None,
+        });
+        let state = testing::state(&node, json!({})).await;
+        let request = serde_json::from_value(json!({
+            "caller": SIGNER,
+            "recipients": {
+                Address::repeat_byte(1).to_string(): { "fraction": "1", "units": "1" },
+            },
+        }))
+        .unwrap();
+
+        disperse_eth(&state, request, false, TxOptions::default())
+            .await
+            .unwrap();
+
+        let TxEnvelope::Eip1559(signed) = &node.sent()[0].1 else {
+            panic!("expected an EIP-1559 transaction");
+        };
+        let tx = signed.tx();
+        let max_gas_cost = U256::from(tx.gas_limit) * U256::from(tx.max_fee_per_gas);
+        assert!(!max_gas_cost.is_zero());
+        assert_eq!(tx.value + max_gas_cost, balance);
+    }
+
+    #[test]
+    fn should_reject_amounts_overflowing_u256() {
+        let recipients = [
+            (
+                Address::repeat_byte(1),
+                FractionOrAmount::Amount { amount: U256::MAX },
+            ),
+            (
+                Address::repeat_byte(2),
+                FractionOrAmount::Amount {
+                    amount: U256::MAX - U256::from(1),
+                },
+            ),
+        ];
+
+        let result = construct_disperse_recipients(
+            Address::ZERO,
+            U256::MAX,
+            recipients.into_iter(),
+            None,
+            ZeroAmounts::Skip,
+        );
+
+        assert!(matches!(result, Err(DcError::AmountOverflow)));
+    }
+
+    #[tokio::test]
+    async fn should_check_recipients_by_the_recipient_policy() {
+        let node = MockNode::new(|_, _| None);
+        let denied = Address::repeat_byte(1);
+        let state = |policy: &str, contract_recipients: Option<&str>| {
+            let config = json!({
+                "recipient_policy": policy,
+                "recipient_denylist": [denied],
+                "disperse_contract_recipients": contract_recipients,
+            });
+            testing::state(&node, config)
+        };
+
+        let off = state("off", None).await;
+        assert!(validate_recipients(&off, [&Address::ZERO, &denied]).is_ok());
+        assert!(get_contract_recipients(&off, [TOKEN])
+            .await
+            .unwrap()
+            .is_empty());
+
+        let lenient = state("lenient", None).await;
+        assert!(matches!(
+            validate_recipients(&lenient, [&Address::ZERO]),
+            Err(DcError::ZeroAddressRecipient)
+        ));
+        assert!(matches!(
+            validate_recipients(&lenient, [&denied]),
+            Err(DcError::DeniedRecipient(address)) if address == denied
+        ));
+        assert!(get_contract_recipients(&lenient, [TOKEN])
+            .await
+            .unwrap()
+            .is_empty());
+
+        let strict = state("strict", None).await;
+        assert!(matches!(
+            get_contract_recipients(&strict, [SIGNER, TOKEN]).await,
+            Err(DcError::ContractRecipient(TOKEN))
+        ));
+
+        // only the strict policy requires checksums, whatever the request was deserialized with
+        let unchecksummed = SIGNER.to_string().to_lowercase();
+        let request = |recipient: &str| -> DisperseEthRequest {
+            serde_json::from_value(json!({
+                "recipients": { recipient: { "amount": "1" } },
+                "caller": SIGNER,
+            }))
+            .unwrap()
+        };
+        let result = disperse_eth(
+            &strict,
+            request(&unchecksummed),
+            false,
+            TxOptions::default(),
+        );
+        assert!(matches!(
+            result.await,
+            Err(DcError::UnchecksummedRecipient(address)) if address == unchecksummed
+        ));
+        assert!(
+            validate_checksums(&strict, &request(&SIGNER.to_checksum(None)).recipients).is_ok()
+        );
+        assert!(validate_checksums(&lenient, &request(&unchecksummed).recipients).is_ok());
+
+        // an explicit setting overrides the policy
+        let skipping = state("strict", Some("skip")).await;
+        let contracts = get_contract_recipients(&skipping, [SIGNER, TOKEN]).await;
+        assert_eq!(contracts.unwrap(), [TOKEN]);
+    }
+
+    #[tokio::test]
+    async fn should_wait_for_the_requested_confirmations() {
+        let polls = AtomicUsize::new(0);
+        let node = MockNode::new(move |method, _| match method {
+            // mined in block 100, block 102 is reached on the third poll
+            "eth_blockNumber" => {
+                let block = 100 + polls.fetch_add(1, Ordering::SeqCst).min(2);
+                Some(Reply::Ok(json!(format!("{block:#x}"))))
+            }
+            _ => None,
+        });
+        let state = testing::state(&node, json!({})).await;
+        let tx_hash = B256::repeat_byte(1);
+
+        let receipt = wait_for_receipt(&state, tx_hash, 3).await.unwrap();
+        assert_eq!(receipt.transaction_hash, tx_hash);
+        assert_eq!(node.requests("eth_blockNumber").len(), 3);
+
+        let stalled = MockNode::new(|_, _| None);
+        let state = testing::state(&stalled, json!({ "confirmation_timeout_secs": 1 })).await;
+        let result = wait_for_receipt(&state, tx_hash, 2).await;
+        assert!(
+            matches!(result, Err(DcError::ConfirmationTimeout { tx_hash: hash }) if hash == tx_hash)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_report_the_transfers_the_contract_failed() {
+        let (failing, succeeding) = (Address::repeat_byte(9), Address::repeat_byte(8));
+        let base = token(U256::from(1000), U256::from(1000));
+        let node = MockNode::new(move |method, params| match method {
+            "eth_getTransactionReceipt" => {
+                let tx_hash = serde_json::from_value(params[0].clone()).unwrap();
+                let failed = IDisperseOutcomes::TransferFailed {
+                    token: TOKEN,
+                    account: failing,
+                    amount: U256::from(100),
+                    reason: Bytes::from_static(b"blocked"),
+                };
+                let data = failed.encode_log_data();
+                let mut receipt = testing::receipt(tx_hash, true);
+                receipt["logs"] = json!([{
+                    "address": CONTRACT,
+                    "topics": data.topics(),
+                    "data": data.data,
+                    "blockHash": B256::repeat_byte(1),
+                    "blockNumber": "0x64",
+                    "transactionHash": tx_hash,
+                    "transactionIndex": "0x0",
+                    "logIndex": "0x0",
+                    "removed": false,
+                }]);
+                Some(Reply::Ok(receipt))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({})).await;
+        let mut request = disperse_request();
+        request.recipients.insert(
+            succeeding,
+            serde_json::from_value(json!({ "amount": "100" })).unwrap(),
+        );
+
+        let response = disperse_erc20(&state, request, false, false, TxOptions::default())
+            .await
+            .unwrap()
+            .0;
+
+        assert_eq!(response.transfers.keys().collect::<Vec<_>>(), [&succeeding]);
+        let [failed] = &response.failed[..] else {
+            panic!("expected one failed transfer, got {:?}", response.failed);
+        };
+        assert_eq!((failed.address, failed.amount), (failing, U256::from(100)));
+        assert_eq!(failed.reason, Bytes::from_static(b"blocked"));
+    }
+
+    #[tokio::test]
+    async fn should_fail_with_the_revert_reason_of_a_reverted_disperse() {
+        let base = token(U256::from(1000), U256::from(1000));
+        let mined = AtomicBool::new(false);
+        let node = MockNode::new(move |method, params| match method {
+            "eth_sendRawTransaction" => {
+                mined.store(true, Ordering::SeqCst);
+                base(method, params)
+            }
+            "eth_getTransactionReceipt" => {
+                let tx_hash = serde_json::from_value(params[0].clone()).unwrap();
+                Some(Reply::Ok(testing::receipt(tx_hash, false)))
+            }
+            // the replay at the transaction's block
+            "eth_call"
+                if mined.load(Ordering::SeqCst)
+                    && call_selector(params)
+                        == (CONTRACT, DisperseCollect::disperseERC20Call::SELECTOR) =>
+            {
+                Some(Reply::Rpc(3, "execution reverted: TRANSFER_FAILED".into()))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({})).await;
+
+        let result = disperse_erc20(
+            &state,
+            disperse_request(),
+            false,
+            false,
+            TxOptions::default(),
+        )
+        .await;
+
+        let Err(DcError::TransactionReverted { tx_hash, reason }) = result else {
+            panic!("expected a reverted transaction, got {result:?}");
+        };
+        assert_eq!(tx_hash, *node.sent()[0].1.tx_hash());
+        assert_eq!(
+            reason.as_deref(),
+            Some("execution reverted: TRANSFER_FAILED")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_sign_with_the_requested_fee_caps() {
+        let node = token_node(U256::from(1000), U256::from(1000));
+        let state = testing::state(&node, json!({})).await;
+        let options = |max_fee_per_gas, max_priority_fee_per_gas| TxOptions {
+            max_fee_per_gas: Some(max_fee_per_gas),
+            max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+            ..TxOptions::default()
+        };
+
+        disperse_erc20(&state, disperse_request(), false, false, options(100, 7))
+            .await
+            .unwrap();
+
+        let TxEnvelope::Eip1559(signed) = &node.sent()[0].1 else {
+            panic!("expected an EIP-1559 transaction");
+        };
+        assert_eq!(signed.tx().max_fee_per_gas, 100);
+        assert_eq!(signed.tx().max_priority_fee_per_gas, 7);
+
+        let result =
+            disperse_erc20(&state, disperse_request(), false, false, options(7, 100)).await;
+        assert!(matches!(result, Err(DcError::InvalidFeeOverride(_))));
+        assert_eq!(node.sent().len(), 1);
+    }
+
+    #[cfg(feature = "blobs")]
+    #[tokio::test]
+    async fn should_attach_the_requested_blobs() {
+        use alloy::consensus::{Blob, BlobTransactionSidecar, Bytes48};
+
+        use crate::dto::BlobFields;
+
+        let node = token_node(U256::from(1000), U256::from(1000));
+        let state = testing::state(&node, json!({})).await;
+        let sidecar = BlobTransactionSidecar {
+            blobs: vec![Blob::default()],
+            commitments: vec![Bytes48::default()],
+            proofs: vec![Bytes48::default()],
+        };
+        let options = |sidecar| TxOptions {
+            blob: Some(BlobFields {
+                blob_versioned_hashes: Vec::new(),
+                max_fee_per_blob_gas: Some(10),
+                sidecar,
+            }),
+            ..TxOptions::default()
+        };
+
+        disperse_erc20(
+            &state,
+            disperse_request(),
+            false,
+            false,
+            options(Some(sidecar.clone())),
+        )
+        .await
+        .unwrap();
+
+        let TxEnvelope::Eip4844(signed) = &node.sent()[0].1 else {
+            panic!("expected a blob transaction");
+        };
+        let tx = signed.tx().tx();
+        assert_eq!(
+            tx.blob_versioned_hashes,
+            sidecar.versioned_hashes().collect::<Vec<_>>()
+        );
+        assert_eq!(tx.max_fee_per_blob_gas, 10);
+
+        let result = disperse_erc20(&state, disperse_request(), false, false, options(None)).await;
+        assert!(matches!(result, Err(DcError::InvalidBlobFields(_))));
+        assert_eq!(node.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_not_send_the_chunks_after_a_failed_one() {
+        let sent = AtomicUsize::new(0);
+        let node = MockNode::new(move |method, _| match method {
+            "eth_getBalance" => Some(Reply::Ok(json!(U256::from(10).pow(U256::from(20))))),
+            "eth_sendRawTransaction" if sent.fetch_add(1, Ordering::SeqCst) == 1 => {
+                Some(Reply::Rpc(-32000, "insufficient funds for gas".into()))
+            }
+            _ => None,
+        });
+        let state = testing::state(&node, json!({})).await;
+        let recipients: Vec<_> = (1..=3).map(Address::repeat_byte).collect();
+        let request = serde_json::from_value(json!({
+            "caller": SIGNER,
+            "recipients": recipients
+                .iter()
+                .map(|a| (a.to_string(), json!({ "amount": "1" })))
+                .collect::<serde_json::Map<_, _>>(),
+        }))
+        .unwrap();
+
+        let response =
+            disperse_eth_chunked(&state, request, NonZeroUsize::MIN, TxOptions::default())
+                .await
+                .unwrap();
+
+        assert_eq!(node.sent().len(), 2);
+        let [sent, failed, skipped] = &response.chunks[..] else {
+            panic!("expected 3 chunks, got {:?}", response.chunks);
+        };
+        assert!(sent.tx.is_some() && sent.error.is_none());
+        assert!(failed.tx.is_none() && failed.error.is_some());
+        assert_eq!(
+            skipped.error.as_deref(),
+            Some("not sent, a previous chunk failed")
+        );
+        assert_eq!(
+            response.transfers.keys().collect::<Vec<_>>(),
+            [&recipients[0]]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_read_the_token_decimals_once() {
+        let node = token_node(U256::from(1000), U256::from(1000));
+        let request = || -> DisperseErc20Request {
+            serde_json::from_value(json!({
+                "caller": SIGNER,
+                "spender": SIGNER,
+                "token": TOKEN,
+                "recipients": {
+                    Address::repeat_byte(9).to_string(): { "value": "0.0000000000000001" },
+                },
+            }))
+            .unwrap()
+        };
+        let decimals_calls = |node: &MockNode| {
+            node.calls()
+                .iter()
+                .filter(|(to, input)| {
+                    *to == TOKEN && input.starts_with(&IERC20::decimalsCall::SELECTOR)
+                })
+                .count()
+        };
+
+        let state = testing::state(&node, json!({})).await;
+        for _ in 0..2 {
+            let response = disperse_erc20(&state, request(), false, false, TxOptions::default())
+                .await
+                .unwrap();
+            assert_eq!(
+                response.0.transfers[&Address::repeat_byte(9)],
+                U256::from(100)
+            );
+        }
+        assert_eq!(decimals_calls(&node), 1);
+
+        let configured = token_node(U256::from(1000), U256::from(1000));
+        let state = testing::state(
+            &configured,
+            json!({ "token_decimals": { TOKEN.to_string(): 18 } }),
+        )
+        .await;
+        disperse_erc20(&state, request(), false, false, TxOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(decimals_calls(&configured), 0);
+    }
+
+    #[test]
+    fn should_cap_basis_points_at_the_whole_balance() {
+        let disperse = |bps: [u16; 3]| {
+            let recipients = bps.into_iter().enumerate().map(|(i, bps)| {
+                (
+                    Address::repeat_byte(i as u8 + 1),
+                    FractionOrAmount::BasisPoints { bps },
+                )
+            });
+
+            construct_disperse_recipients(
+                Address::ZERO,
+                U256::from(1_000_000),
+                recipients,
+                None,
+                ZeroAmounts::Skip,
+            )
+        };
+
+        let recipients = disperse([5000, 2500, 2500]).unwrap();
+        assert_eq!(recipients.total, U256::from(1_000_000));
+
+        // the remainder stays with the sender
+        let recipients = disperse([5000, 2500, 1]).unwrap();
+        assert_eq!(recipients.amounts[2], U256::from(100));
+        assert_eq!(recipients.total, U256::from(750_100));
+
+        let result = disperse([5000, 2500, 2501]);
+        assert!(matches!(result, Err(DcError::BasisPointsExceeded(10_001))));
+    }
+
+    #[test]
+    fn should_give_the_rounding_remainder_to_the_remainder_recipient() {
+        let third = FractionOrAmount::Fraction(FractionalAmount {
+            fraction: U256::from(1),
+            units: U256::from(3),
+        });
+        let recipients = (1..=3).map(|i| (Address::repeat_byte(i), third));
+        let balance = U256::from(100);
+
+        let recipients = construct_disperse_recipients(
+            Address::ZERO,
+            balance,
+            recipients,
+            Some(Address::repeat_byte(3)),
+            ZeroAmounts::Skip,
+        )
+        .unwrap();
+
+        assert_eq!(
+            recipients.amounts,
+            [U256::from(33), U256::from(33), U256::from(34)]
+        );
+        assert_eq!(recipients.total, balance);
+        assert_eq!(recipients.amounts.iter().sum::<U256>(), balance);
+    }
+
+    #[test]
+    fn should_split_the_balance_by_weight() {
+        let disperse = |weights: [u64; 3]| {
+            let recipients = weights.into_iter().enumerate().map(|(i, weight)| {
+                let weight = U256::from(weight);
+                (
+                    Address::repeat_byte(i as u8 + 1),
+                    FractionOrAmount::Weighted { weight },
+                )
+            });
+
+            construct_disperse_recipients(
+                Address::ZERO,
+                U256::from(100),
+                recipients,
+                None,
+                ZeroAmounts::Skip,
+            )
+        };
+
+        let recipients = disperse([2, 3, 1]).unwrap();
+        assert_eq!(
+            recipients.amounts,
+            [U256::from(33), U256::from(51), U256::from(16)]
+        );
+        assert_eq!(recipients.total, U256::from(100));
+
+        assert!(matches!(disperse([0, 0, 0]), Err(DcError::ZeroTotalWeight)));
+    }
+
+    #[test]
+    fn should_split_the_total_equally() {
+        let (a, b, c) = (
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            Address::repeat_byte(3),
+        );
+        let total = FractionOrAmount::Amount {
+            amount: U256::from(100),
+        };
+
+        let (recipients, remainder_to) = split_equally(vec![a, b, c, b], total, None).unwrap();
+
+        let amounts: Vec<_> = recipients
+            .into_iter()
+            .filter_map(|(recipient, amount)| match amount {
+                FractionOrAmount::Amount { amount } => Some((recipient, amount)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(remainder_to, None);
+        assert_eq!(
+            amounts,
+            [
+                (a, U256::from(34)),
+                (b, U256::from(33)),
+                (c, U256::from(33))
+            ]
+        );
+        assert!(matches!(
+            split_equally(vec![], total, None),
+            Err(DcError::NoRecipients)
+        ));
+    }
+
+    #[tokio::test]
+    async fn should_approve_from_custodial_spenders_before_collecting() {
+        let node = token_node(U256::from(1000), U256::ZERO);
+        let config = json!({ "custodial_spenders": true, "spender_keys": [testing::SPENDER_KEY] });
+        let state = testing::state(&node, config).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+
+        let response = collect_erc20(&state, request, false, false, TxOptions::default())
+            .await
+            .unwrap();
+
+        let sent = node.sent();
+        assert_eq!(sent.len(), 2);
+        let (approver, approve) = &sent[0];
+        assert_eq!(*approver, SPENDER);
+        assert_eq!(fields(approve).to().to(), Some(&TOKEN));
+        let (collector, collect) = &sent[1];
+        assert_eq!(*collector, SIGNER);
+        assert_eq!(fields(collect).to().to(), Some(&CONTRACT));
+        assert_eq!(response.0.transfers[&SPENDER], U256::from(500));
+    }
+
+    #[tokio::test]
+    async fn should_collect_on_simulated_approvals_without_simulating_the_collect() {
+        let node = token_node(U256::from(1000), U256::ZERO);
+        let config = json!({
+            "custodial_spenders": true,
+            "spender_keys": [testing::SPENDER_KEY],
+            "simulate_only": true,
+        });
+        let state = testing::state(&node, config).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+
+        let response = collect_erc20(&state, request, false, false, TxOptions::default())
+            .await
+            .unwrap();
+
+        assert!(response.0.tx.simulated);
+        assert!(node.sent().is_empty());
+        let collect_calls = node
+            .calls()
+            .into_iter()
+            .filter(|(to, input)| {
+                *to == CONTRACT && input.starts_with(&DisperseCollect::collectERC20Call::SELECTOR)
+            })
+            .count();
+        assert_eq!(collect_calls, 0);
+        assert_eq!(response.0.transfers[&SPENDER], U256::from(500));
+    }
+
+    #[tokio::test]
+    async fn should_reject_custodial_spenders_without_a_key() {
+        let node = token_node(U256::from(1000), U256::ZERO);
+        let state = testing::state(&node, json!({ "custodial_spenders": true })).await;
+        let request = serde_json::from_value(collect_request(500)).unwrap();
+
+        let result = collect_erc20(&state, request, false, false, TxOptions::default()).await;
+
+        assert!(
+            matches!(result, Err(DcError::SpenderSignersNotFound(missing)) if missing == [SPENDER])
+        );
+        assert!(node.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_not_top_up_recipients_of_a_reverting_disperse() {
+        let base = token(U256::from(1000), U256::from(1000));
+        let node = MockNode::new(move |method, params| match method {
+            "eth_call"
+                if call_selector(params)
+                    == (CONTRACT, DisperseCollect::disperseERC20Call::SELECTOR) =>
+            {
+                Some(Reply::Rpc(3, "execution reverted".into()))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({ "recipient_topup_wei": 1000 })).await;
+
+        let result = disperse_erc20(
+            &state,
+            disperse_request(),
+            false,
+            false,
+            TxOptions::default(),
+        )
+        .await;
+
+        assert!(matches!(result, Err(DcError::SimulationReverted(_))));
+        assert!(node.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_report_the_top_up_when_the_disperse_fails() {
+        let base = token(U256::from(1000), U256::from(1000));
+        let node = MockNode::new(move |method, params| match method {
+            "eth_sendRawTransaction"
+                if sends(params, DisperseCollect::disperseERC20Call::SELECTOR) =>
+            {
+                Some(Reply::Rpc(-32000, "insufficient funds for gas".into()))
+            }
+            _ => base(method, params),
+        });
+        let state = testing::state(&node, json!({ "recipient_topup_wei": 1000 })).await;
+
+        let result = disperse_erc20(
+            &state,
+            disperse_request(),
+            false,
+            false,
+            TxOptions::default(),
+        )
+        .await;
+
+        let Err(DcError::ToppedUp { top_up, .. }) = result else {
+            panic!("expected the top-up in the error, got {result:?}");
+        };
+        assert_eq!(top_up.transfers[&Address::repeat_byte(9)], U256::from(1000));
+        // the top-up and the rejected disperse
+        assert_eq!(node.sent().len(), 2);
+    }
+
+    #[test]
+    fn should_not_add_the_remainder_recipient_without_a_remainder() {
+        let (a, b, outsider) = (
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            Address::repeat_byte(9),
+        );
+        let total = FractionOrAmount::Amount {
+            amount: U256::from(100),
+        };
+
+        let (recipients, _) = split_equally(vec![a, b], total, Some(outsider)).unwrap();
+
+        assert_eq!(recipients.keys().copied().collect::<Vec<_>>(), [a, b]);
+    }
+
+    #[test]
+    fn should_reject_a_remainder_recipient_of_a_partial_split() {
+        let (a, b, outsider) = (
+            Address::repeat_byte(1),
+            Address::repeat_byte(2),
+            Address::repeat_byte(9),
+        );
+        let fraction = |fraction: u64| {
+            FractionOrAmount::Fraction(FractionalAmount {
+                fraction: U256::from(fraction),
+                units: U256::from(2),
+            })
+        };
+
+        assert!(matches!(
+            split_equally(vec![a, b], fraction(1), Some(outsider)),
+            Err(DcError::PartialSplitRemainder)
+        ));
+
+        let (_, remainder_to) = split_equally(vec![a, b], fraction(1), None).unwrap();
+        assert_eq!(remainder_to, None);
+
+        let (_, remainder_to) = split_equally(vec![a, b], fraction(2), Some(outsider)).unwrap();
+        assert_eq!(remainder_to, Some(outsider));
+    }
+
+    #[tokio::test]
+    async fn should_report_the_receipt_of_a_mined_transaction() {
+        let node = MockNode::new(|method, _| {
+            (method == "eth_blockNumber").then(|| Reply::Ok(json!("0x66")))
+        });
+        let state = testing::state(&node, json!({})).await;
+
+        let status = get_transaction_status(&state, B256::repeat_byte(1), Default::default())
+            .await
+            .unwrap();
+
+        assert_eq!(status.status, TransactionStatus::Success);
+        assert_eq!(status.block_number, Some(100));
+        assert_eq!(status.gas_used, Some(21000));
+        assert_eq!(status.confirmations, Some(2));
+    }
+
+    /// Node where `SIGNER` has a transaction at nonce 0 pending until `mined`
+    fn pending_node(
+        mined: Arc<AtomicBool>,
+        handler: impl Fn(&str, &Value) -> Option<Reply> + Send + Sync + 'static,
+    ) -> MockNode {
+        MockNode::new(move |method, params| match method {
+            "eth_getTransactionCount"
+                if serde_json::from_value::<Address>(params[0].clone()).unwrap() == SIGNER
+                    && (params[1] == "pending" || mined.load(Ordering::SeqCst)) =>
+            {
+                Some(Reply::Ok(json!("0x1")))
+            }
+            _ => handler(method, params),
+        })
+    }
+
+    #[tokio::test]
+    async fn should_only_alert_about_pending_transactions() {
+        let node = pending_node(Default::default(), |_, _| None);
+        let state = testing::state(&node, json!({ "pending_recovery": "alert" })).await;
+
+        recover_pending(&state).await;
+
+        assert!(node.sent().is_empty());
+        assert!(node.requests("txpool_contentFrom").is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_wait_for_pending_transactions() {
+        let mined = Arc::new(AtomicBool::new(false));
+        let node = pending_node(mined.clone(), {
+            let mined = mined.clone();
+            move |method, _| {
+                // mined on the first poll after the gap is found
+                if method == "eth_getTransactionCount" {
+                    mined.store(true, Ordering::SeqCst);
+                }
+                None
+            }
+        });
+        let state = testing::state(&node, json!({ "pending_recovery": "wait" })).await;
+
+        recover_pending(&state).await;
+
+        assert!(mined.load(Ordering::SeqCst));
+        assert!(node.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_replace_pending_transactions_over_their_fees() {
+        let pending_hash = B256::repeat_byte(7);
+        let node = pending_node(Default::default(), move |method, _| match method {
+            "txpool_contentFrom" => Some(Reply::Ok(json!({
+                "pending": { "0": { "hash": pending_hash } },
+                "queued": {},
+            }))),
+            "eth_getTransactionByHash" => Some(Reply::Ok(json!({
+                "hash": pending_hash,
+                "nonce": "0x0",
+                "from": SIGNER,
+                "to": SIGNER,
+                "value": "0x0",
+                "gas": "0x5208",
+                "maxFeePerGas": "0x64",
+                "maxPriorityFeePerGas": "0x10",
+                "input": "0x",
+                "chainId": format!("{:#x}", testing::CHAIN_ID),
+                "type": "0x2",
+            }))),
+            _ => None,
+        });
+        let state = testing::state(&node, json!({ "pending_recovery": "replace" })).await;
+
+        recover_pending(&state).await;
+
+        let sent = node.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, SIGNER);
+        assert_eq!(fields(&sent[0].1).nonce(), 0);
+        let gas = gas_params(&sent[0].1);
+        // 12.5% over the pending 100 and 16 wei, rounded up
+        assert_eq!(gas.max_fee_per_gas, Some(113));
+        assert_eq!(gas.max_priority_fee_per_gas, Some(18));
+    }
+
+    #[tokio::test]
+    async fn should_report_an_underpriced_replacement_at_the_estimated_fees() {
+        // the node doesn't expose its pool
+        let node = pending_node(Default::default(), |method, _| {
+            (method == "eth_sendRawTransaction")
+                .then(|| Reply::Rpc(-32000, "replacement transaction underpriced".into()))
+        });
+        let state = testing::state(&node, json!({})).await;
+        let estimate = state.provider().estimate_eip1559_fees(None).await.unwrap();
+
+        let e = replace_pending(&state, SIGNER, 0..1).await.unwrap_err();
+
+        assert!(e.to_string().contains("underpriced"), "{e}");
+        let gas = gas_params(&node.sent()[0].1);
+        assert_eq!(gas.max_fee_per_gas, Some(estimate.max_fee_per_gas * 2));
+        assert_eq!(
+            gas.max_priority_fee_per_gas,
+            Some(estimate.max_priority_fee_per_gas * 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_give_up_recovery_after_the_timeout() {
+        // the pending transaction is never mined
+        let node = MockNode::new(|method, params| {
+            (method == "eth_getTransactionCount" && params[1] == "pending")
+                .then(|| Reply::Ok(json!("0x1")))
+        });
+        let config = json!({ "pending_recovery": "wait", "recovery_timeout_secs": 1 });
+        let state = testing::state(&node, config).await;
+
+        let recovery = tokio::time::timeout(Duration::from_secs(5), recover_pending(&state)).await;
+
+        assert!(recovery.is_ok());
+        assert!(node.requests("eth_getTransactionCount").len() > 2);
+    }
 }
@@ -1,48 +1,382 @@
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use alloy::network::EthereumWallet;
-use alloy::providers::fillers::{
-    ChainIdFiller, FillProvider, GasFiller, JoinFill, NonceFiller, WalletFiller,
-};
+use alloy::primitives::{Address, U256};
+use alloy::providers::fillers::{ChainIdFiller, FillProvider, GasFiller, JoinFill, WalletFiller};
 use alloy::providers::network::AnyNetwork;
 use alloy::providers::{Identity, RootProvider};
-use alloy::providers::{Provider, ReqwestProvider};
+use alloy::providers::{Provider, ProviderBuilder};
+use alloy::rpc::client::ClientBuilder;
 use alloy::signers::local::PrivateKeySigner;
-use alloy::transports::http::{Client, Http};
+use alloy::transports::{http::reqwest::Url, BoxTransport};
+use anyhow::Context;
 use derive_getters::Getters;
+use tracing::warn;
 
-use crate::config::AppConfig;
-use crate::contracts::DisperseCollectContract;
+use crate::config::{AppConfig, SignerType};
+use crate::contracts::{DisperseCollectContract, Multicall3Contract, MULTICALL3_ADDRESS};
+use crate::dto::TokenResponse;
+use crate::jobs::JobStore;
+use crate::nonce::NonceManager;
+use crate::notify::Notifier;
+use crate::redact::Redacted;
+use crate::service::DcError;
+use crate::stats::StatsTracker;
+use crate::transport::{
+    ConcurrencyLimitLayer, Fallback, RetryLayer, RpcMetricsLayer, TimeoutLayer,
+};
+use crate::usage::UsageTracker;
 
 pub type AppNetwork = AnyNetwork;
 
+pub type AppTransport = BoxTransport;
+
+/// Nonces are managed by [`NonceManager`] instead of a filler
 pub type DefaultProvider = FillProvider<
-    JoinFill<
-        JoinFill<JoinFill<JoinFill<Identity, GasFiller>, NonceFiller>, ChainIdFiller>,
-        WalletFiller<EthereumWallet>,
-    >,
-    RootProvider<Http<Client>, AppNetwork>,
-    Http<Client>,
+    JoinFill<JoinFill<JoinFill<Identity, GasFiller>, ChainIdFiller>, WalletFiller<EthereumWallet>>,
+    RootProvider<AppTransport, AppNetwork>,
+    AppTransport,
     AppNetwork,
 >;
 
+/// Provider, contract and caches of one chain
 #[derive(Clone, Getters)]
-pub struct AppState {
+pub struct ChainState {
+    chain_id: u64,
     provider: DefaultProvider,
     contract: DisperseCollectContract,
+    multicall: Multicall3Contract,
+    decimals: DecimalsCache,
+    metadata: MetadataCache,
+    total_supply: SupplyCache,
+    nonces: NonceManager,
+    /// Explorer transaction URL for the chain
+    explorer_url: Option<Url>,
+}
+
+/// Shared state of a request, with the chain it runs on
+#[derive(Clone, Getters)]
+pub struct AppState {
+    #[getter(skip)]
+    chain: ChainState,
+    /// Every configured chain by chain id, including the default one
+    #[getter(skip)]
+    chains: Arc<BTreeMap<u64, ChainState>>,
+    jobs: JobStore,
+    usage: UsageTracker,
+    stats: StatsTracker,
+    notifier: Option<Arc<Notifier>>,
+    config: Arc<AppConfig>,
+}
+
+/// Per-token value cache, entries are never evicted.
+#[derive(Clone)]
+pub struct TokenCache<T>(Arc<RwLock<HashMap<Address, T>>>);
+
+/// Token decimals are immutable
+pub type DecimalsCache = TokenCache<u8>;
+
+/// Token names, symbols and decimals are immutable
+pub type MetadataCache = TokenCache<TokenResponse>;
+
+/// Total supply may change, entries are re-read when a check against them fails
+pub type SupplyCache = TokenCache<U256>;
+
+impl<T: Clone> TokenCache<T> {
+    pub fn get(&self, token: &Address) -> Option<T> {
+        self.0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(token)
+            .cloned()
+    }
+
+    pub fn insert(&self, token: Address, value: T) {
+        self.0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(token, value);
+    }
+}
+
+impl<T> Default for TokenCache<T> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<T> From<HashMap<Address, T>> for TokenCache<T> {
+    fn from(value: HashMap<Address, T>) -> Self {
+        Self(Arc::new(RwLock::new(value)))
+    }
 }
 
 impl AppState {
-    pub fn init(config: AppConfig) -> anyhow::Result<Arc<Self>> {
-        let signer = PrivateKeySigner::from_str(&config.tx_signer)?;
-        let wallet = EthereumWallet::new(signer);
-        let provider = ReqwestProvider::<AnyNetwork>::builder()
-            .with_recommended_fillers()
-            .wallet(wallet)
-            .on_http(config.rpc_url);
-        let contract = DisperseCollectContract::new(config.contract_address, provider.clone());
+    pub async fn init(config: AppConfig) -> anyhow::Result<Arc<Self>> {
+        let wallet = load_wallet(&config).await?;
+
+        if config.simulate_only {
+            warn!("Simulate-only mode is enabled, transactions will not be broadcast");
+        }
+
+        let rpc_urls = std::iter::once(&config.rpc_url).chain(&config.fallback_rpc_urls);
+        let chain = ChainState::init(
+            &config,
+            &wallet,
+            rpc_urls.cloned().collect(),
+            config.contract_address,
+            config.token_decimals.clone().into(),
+        )
+        .await?;
+        let mut chains = BTreeMap::from([(chain.chain_id, chain.clone())]);
+
+        for (chain_id, chain_config) in &config.chains {
+            let rpc_urls =
+                std::iter::once(&chain_config.rpc_url).chain(&chain_config.fallback_rpc_urls);
+            let state = ChainState::init(
+                &config,
+                &wallet,
+                rpc_urls.cloned().collect(),
+                chain_config.contract_address,
+                DecimalsCache::default(),
+            )
+            .await?;
+
+            if state.chain_id != *chain_id {
+                anyhow::bail!(
+                    "RPC URL of chain {chain_id} is connected to chain {}",
+                    state.chain_id
+                );
+            }
+            if chains.insert(*chain_id, state).is_some() {
+                anyhow::bail!("chain {chain_id} is configured more than once");
+            }
+        }
+
+        if config.testnet_mode {
+            warn!("Testnet mode is enabled");
+        }
+
+        let jobs = JobStore::new(Duration::from_secs(config.job_ttl_secs));
+        let usage = UsageTracker::new(Duration::from_secs(config.caller_budget_window_secs));
+        let stats = StatsTracker::new(Duration::from_secs(config.stats_window_secs));
+
+        let notifier = match &config.notify_redis_url {
+            Some(url) => {
+                let client = redis::Client::open(url.as_str())?;
+                let channel = config.notify_channel.clone();
+                Some(Notifier::new(client, channel, config.redact_logs).into())
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            chain,
+            chains: chains.into(),
+            jobs,
+            usage,
+            stats,
+            notifier,
+            config: config.into(),
+        }
+        .into())
+    }
+
+    /// State of a request on `chain_id`, the default chain if not set
+    pub fn on_chain(self: &Arc<Self>, chain_id: Option<u64>) -> Result<Arc<Self>, DcError> {
+        let Some(chain_id) = chain_id.filter(|id| *id != self.chain.chain_id) else {
+            return Ok(self.clone());
+        };
+
+        let chain = self
+            .chains
+            .get(&chain_id)
+            .ok_or(DcError::UnknownChain(chain_id))?;
+
+        Ok(Self {
+            chain: chain.clone(),
+            ..Self::clone(self)
+        }
+        .into())
+    }
+
+    pub fn chain_ids(&self) -> Vec<u64> {
+        self.chains.keys().copied().collect()
+    }
 
-        Ok(Self { provider, contract }.into())
+    pub fn chain_id(&self) -> u64 {
+        self.chain.chain_id
     }
+
+    pub fn provider(&self) -> &DefaultProvider {
+        &self.chain.provider
+    }
+
+    pub fn contract(&self) -> &DisperseCollectContract {
+        &self.chain.contract
+    }
+
+    pub fn multicall(&self) -> &Multicall3Contract {
+        &self.chain.multicall
+    }
+
+    pub fn decimals(&self) -> &DecimalsCache {
+        &self.chain.decimals
+    }
+
+    pub fn token_metadata(&self) -> &MetadataCache {
+        &self.chain.metadata
+    }
+
+    pub fn total_supply(&self) -> &SupplyCache {
+        &self.chain.total_supply
+    }
+
+    pub fn nonces(&self) -> &NonceManager {
+        &self.chain.nonces
+    }
+
+    pub fn explorer_url(&self) -> &Option<Url> {
+        &self.chain.explorer_url
+    }
+
+    /// `value` as it should appear in the logs, hashed if `redact_logs` is set
+    pub fn redacted<T>(&self, value: T) -> Redacted<T> {
+        Redacted::new(value, self.config.redact_logs)
+    }
+}
+
+impl ChainState {
+    async fn init(
+        config: &AppConfig,
+        wallet: &EthereumWallet,
+        rpc_urls: Vec<Url>,
+        contract_address: Address,
+        decimals: DecimalsCache,
+    ) -> anyhow::Result<Self> {
+        let timeout = TimeoutLayer::new(config.rpc_timeout_ms.map(Duration::from_millis));
+        let transport = Fallback::new(rpc_urls.iter().cloned(), timeout);
+        let is_local = transport.is_local();
+        // outside the concurrency limit, so backing off doesn't hold a slot
+        let retry = RetryLayer::new(config.rpc_retry);
+        let client = match config.max_concurrent_rpc {
+            Some(max) => ClientBuilder::default()
+                .layer(RpcMetricsLayer)
+                .layer(retry)
+                .layer(ConcurrencyLimitLayer::new(max.get()))
+                .transport(transport, is_local)
+                .boxed(),
+            None => ClientBuilder::default()
+                .layer(RpcMetricsLayer)
+                .layer(retry)
+                .transport(transport, is_local)
+                .boxed(),
+        };
+        let client = match config.poll_interval_ms {
+            Some(ms) => client.with_poll_interval(Duration::from_millis(ms)),
+            None => client,
+        };
+        let provider = ProviderBuilder::new()
+            .network::<AppNetwork>()
+            .with_gas_estimation()
+            .filler(ChainIdFiller::default())
+            .wallet(wallet.clone())
+            .on_client(client);
+        let contract = DisperseCollectContract::new(contract_address, provider.clone());
+
+        let chain_id = provider.get_chain_id().await?;
+        config.validate_chain(chain_id)?;
+
+        // reads served by a fallback on another chain would be wrong, unreachable ones may come back later
+        for url in rpc_urls.iter().skip(1) {
+            let host = url.host_str().unwrap_or_default();
+
+            match ProviderBuilder::new()
+                .on_http(url.clone())
+                .get_chain_id()
+                .await
+            {
+                Ok(id) if id != chain_id => {
+                    anyhow::bail!(
+                        "fallback RPC URL of {host} is connected to chain {id}, not {chain_id}"
+                    )
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Fallback RPC endpoint {host} is unreachable: {e}"),
+            }
+        }
+
+        // without this a wrong address only surfaces as confusing errors of the first requests
+        if provider.get_code_at(contract_address).await?.is_empty() {
+            anyhow::bail!(
+                "no contract deployed at {contract_address} on chain {chain_id}, check the configured contract address"
+            );
+        }
+
+        let multicall_address = config
+            .multicall3_addresses
+            .get(&chain_id)
+            .copied()
+            .unwrap_or(MULTICALL3_ADDRESS);
+
+        Ok(Self {
+            chain_id,
+            multicall: Multicall3Contract::new(multicall_address, provider.clone()),
+            provider,
+            contract,
+            decimals,
+            metadata: MetadataCache::default(),
+            total_supply: SupplyCache::default(),
+            nonces: NonceManager::default(),
+            explorer_url: config.explorer_urls.get(&chain_id).cloned(),
+        })
+    }
+}
+
+async fn load_wallet(config: &AppConfig) -> anyhow::Result<EthereumWallet> {
+    let mut wallet = match config.signer_type {
+        SignerType::Local => {
+            let key = config
+                .tx_signer
+                .as_deref()
+                .context("TX_SIGNER is required for the local signer")?;
+
+            EthereumWallet::new(PrivateKeySigner::from_str(key)?)
+        }
+        #[cfg(feature = "aws-kms")]
+        SignerType::AwsKms => {
+            let key_id = config
+                .kms_key_id
+                .clone()
+                .context("KMS_KEY_ID is required for the aws_kms signer")?;
+
+            let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+            if let Some(region) = config.kms_region.clone() {
+                loader = loader.region(aws_config::Region::new(region));
+            }
+            let aws_config = loader.load().await;
+            let client = aws_sdk_kms::Client::new(&aws_config);
+            let signer = alloy::signers::aws::AwsSigner::new(client, key_id, None).await?;
+
+            EthereumWallet::new(signer)
+        }
+        #[cfg(not(feature = "aws-kms"))]
+        SignerType::AwsKms => {
+            anyhow::bail!("aws_kms signer requires building with the aws-kms feature")
+        }
+    };
+
+    for key in &config.spender_keys {
+        let signer = PrivateKeySigner::from_str(key).context("invalid key in SPENDER_KEYS")?;
+        wallet.register_signer(signer);
+    }
+
+    if config.custodial_spenders && config.spender_keys.is_empty() {
+        warn!("CUSTODIAL_SPENDERS is set without SPENDER_KEYS, every collect will fail");
+    }
+
+    Ok(wallet)
 }
@@ -0,0 +1,273 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use alloy::{
+    primitives::{Address, U256},
+    rpc::types::TransactionRequest,
+    serde::WithOtherFields,
+    sol_types::SolCall,
+};
+
+use crate::{
+    contracts::{DisperseCollect, IERC20},
+    dto::{StatsResponse, TokenVolume},
+    service::Operation,
+};
+
+/// Outcome of one sent transaction
+pub struct TransactionRecord {
+    pub operation: Operation,
+    pub success: bool,
+    pub gas_used: u64,
+    /// Fee paid in wei, zero for failed transactions
+    pub fee: U256,
+    /// From handing the transaction to the node until it was confirmed or failed
+    pub duration: Duration,
+    /// Token moved by the transaction, `None` for ETH, and the amount
+    pub moved: Option<(Option<Address>, U256)>,
+}
+
+/// In-memory log of sent transactions, entries older than `retention` are dropped
+#[derive(Clone)]
+pub struct StatsTracker {
+    records: Arc<RwLock<VecDeque<(Instant, TransactionRecord)>>>,
+    retention: Duration,
+}
+
+impl StatsTracker {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            records: Default::default(),
+            retention,
+        }
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    pub fn record(&self, record: TransactionRecord) {
+        let mut records = self.records.write().unwrap_or_else(|e| e.into_inner());
+
+        while records
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() >= self.retention)
+        {
+            records.pop_front();
+        }
+
+        records.push_back((Instant::now(), record));
+    }
+
+    /// Aggregates the transactions of the last `window`, at most `retention`
+    pub fn summary(&self, window: Duration) -> StatsResponse {
+        let window = window.min(self.retention);
+        let records = self.records.read().unwrap_or_else(|e| e.into_inner());
+        let records = records
+            .iter()
+            .filter(|(at, _)| at.elapsed() < window)
+            .map(|(_, record)| record);
+
+        let mut stats = StatsResponse {
+            window_secs: window.as_secs(),
+            transactions: 0,
+            succeeded: 0,
+            failed: 0,
+            success_rate: None,
+            gas_used: 0,
+            fees: U256::ZERO,
+            average_confirmation_ms: None,
+            operations: BTreeMap::new(),
+            volume: Vec::new(),
+        };
+        let mut confirmation = Duration::ZERO;
+        let mut volume = BTreeMap::<Option<Address>, U256>::new();
+
+        for record in records {
+            stats.transactions += 1;
+            *stats.operations.entry(record.operation.name()).or_default() += 1;
+
+            if !record.success {
+                stats.failed += 1;
+                continue;
+            }
+
+            stats.succeeded += 1;
+            stats.gas_used += record.gas_used;
+            stats.fees = stats.fees.saturating_add(record.fee);
+            confirmation += record.duration;
+
+            if let Some((token, amount)) = record.moved {
+                let total = volume.entry(token).or_default();
+                *total = total.saturating_add(amount);
+            }
+        }
+
+        if stats.transactions > 0 {
+            stats.success_rate = Some(stats.succeeded as f64 / stats.transactions as f64);
+        }
+        if stats.succeeded > 0 {
+            stats.average_confirmation_ms =
+                Some((confirmation / stats.succeeded as u32).as_millis() as u64);
+        }
+        stats.volume = volume
+            .into_iter()
+            .map(|(token, amount)| TokenVolume { token, amount })
+            .collect();
+
+        stats
+    }
+}
+
+/// Token and total amount `tx` of `operation` moves, `None` for operations that don't move value
+pub fn moved_value(
+    operation: Operation,
+    tx: &WithOtherFields<TransactionRequest>,
+) -> Option<(Option<Address>, U256)> {
+    let input = tx
+        .input
+        .input()
+        .map(|input| input.as_ref())
+        .unwrap_or_default();
+    let sum = |amounts: Vec<U256>| {
+        amounts
+            .into_iter()
+            .fold(U256::ZERO, |acc, amount| acc.saturating_add(amount))
+    };
+
+    match operation {
+        Operation::DisperseEth | Operation::TransferEth => Some((None, tx.value?)),
+        Operation::DisperseErc20 => {
+            let call = DisperseCollect::disperseERC20Call::abi_decode(input, true).ok()?;
+            Some((Some(call.tokenAddress), sum(call.amounts)))
+        }
+        Operation::CollectErc20 => {
+            let call = DisperseCollect::collectERC20Call::abi_decode(input, true).ok()?;
+            Some((Some(call.tokenAddress), sum(call.amounts)))
+        }
+        Operation::TransferErc20 => {
+            let call = IERC20::transferCall::abi_decode(input, true).ok()?;
+            let token = tx.to?.to().copied()?;
+            Some((Some(token), call.amount))
+        }
+        Operation::Approve | Operation::Permit => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use alloy::{
+        primitives::{address, Address, U256},
+        rpc::types::TransactionRequest,
+        serde::WithOtherFields,
+        sol_types::SolCall,
+    };
+
+    use super::{moved_value, StatsTracker, TransactionRecord};
+    use crate::{contracts::DisperseCollect, service::Operation};
+
+    const TOKEN: Address = address!("5FbDB2315678afecb367f032d93F642f64180aa3");
+
+    fn record(
+        operation: Operation,
+        success: bool,
+        moved: Option<(Option<Address>, U256)>,
+    ) -> TransactionRecord {
+        TransactionRecord {
+            operation,
+            success,
+            gas_used: if success { 21_000 } else { 0 },
+            fee: if success {
+                U256::from(1_000)
+            } else {
+                U256::ZERO
+            },
+            duration: Duration::from_millis(if success { 200 } else { 50 }),
+            moved,
+        }
+    }
+
+    #[test]
+    fn should_aggregate_transactions_of_the_window() {
+        let stats = StatsTracker::new(Duration::from_secs(3600));
+
+        stats.record(record(
+            Operation::DisperseEth,
+            true,
+            Some((None, U256::from(5))),
+        ));
+        stats.record(record(
+            Operation::DisperseEth,
+            true,
+            Some((None, U256::from(7))),
+        ));
+        stats.record(record(
+            Operation::DisperseErc20,
+            true,
+            Some((Some(TOKEN), U256::from(3))),
+        ));
+        stats.record(record(
+            Operation::DisperseErc20,
+            false,
+            Some((Some(TOKEN), U256::from(100))),
+        ));
+
+        let summary = stats.summary(Duration::from_secs(60));
+
+        assert_eq!(summary.window_secs, 60);
+        assert_eq!(
+            (summary.transactions, summary.succeeded, summary.failed),
+            (4, 3, 1)
+        );
+        assert_eq!(summary.success_rate, Some(0.75));
+        assert_eq!(summary.gas_used, 63_000);
+        assert_eq!(summary.fees, U256::from(3_000));
+        assert_eq!(summary.average_confirmation_ms, Some(200));
+        assert_eq!(summary.operations["disperseEth"], 2);
+        assert_eq!(summary.operations["disperseErc20"], 2);
+
+        let volume: Vec<_> = summary.volume.iter().map(|v| (v.token, v.amount)).collect();
+        assert_eq!(
+            volume,
+            [(None, U256::from(12)), (Some(TOKEN), U256::from(3))]
+        );
+    }
+
+    #[test]
+    fn should_cap_the_window_at_the_retention() {
+        let stats = StatsTracker::new(Duration::from_millis(20));
+
+        stats.record(record(Operation::TransferEth, true, None));
+        std::thread::sleep(Duration::from_millis(30));
+
+        let summary = stats.summary(Duration::from_secs(3600));
+
+        assert_eq!(summary.window_secs, 0);
+        assert_eq!(summary.transactions, 0);
+        assert_eq!(summary.success_rate, None);
+        assert_eq!(summary.average_confirmation_ms, None);
+    }
+
+    #[test]
+    fn should_sum_the_amounts_of_a_disperse() {
+        let call = DisperseCollect::disperseERC20Call {
+            spender: Address::ZERO,
+            tokenAddress: TOKEN,
+            recipients: vec![Address::repeat_byte(1), Address::repeat_byte(2)],
+            amounts: vec![U256::from(10), U256::from(32)],
+        };
+        let tx =
+            WithOtherFields::new(TransactionRequest::default().input(call.abi_encode().into()));
+
+        assert_eq!(
+            moved_value(Operation::DisperseErc20, &tx),
+            Some((Some(TOKEN), U256::from(42)))
+        );
+        assert_eq!(moved_value(Operation::Approve, &tx), None);
+    }
+}
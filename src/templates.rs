@@ -0,0 +1,128 @@
+use crate::{config::ErrorTemplates, service::DcError};
+
+/// Error whose client-facing message can be replaced by one of the [`ErrorTemplates`], with the values
+/// of its placeholders
+#[derive(Debug, Clone)]
+pub struct Template {
+    name: TemplateName,
+    placeholders: Vec<(&'static str, String)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TemplateName {
+    InsufficientFunds,
+    InvalidFractionalAmount,
+    TokenNotFound,
+}
+
+impl Template {
+    /// Template of `e`, `None` if its message can't be configured
+    pub fn of(e: &DcError) -> Option<Self> {
+        let (name, placeholders) = match e {
+            DcError::InsufficientFunds {
+                required,
+                available,
+                address,
+            } => (
+                TemplateName::InsufficientFunds,
+                vec![
+                    ("address", address.to_string()),
+                    ("required", required.to_string()),
+                    ("available", available.to_string()),
+                ],
+            ),
+            DcError::InvalidFractionalAmount(e) => (
+                TemplateName::InvalidFractionalAmount,
+                vec![
+                    ("fraction", e.0.fraction.to_string()),
+                    ("units", e.0.units.to_string()),
+                ],
+            ),
+            DcError::TokenNotFound(address) => (
+                TemplateName::TokenNotFound,
+                vec![("address", address.to_string())],
+            ),
+            _ => return None,
+        };
+
+        Some(Self { name, placeholders })
+    }
+
+    /// Message rendered from the configured template, `None` if there is none
+    pub fn render(&self, templates: &ErrorTemplates) -> Option<String> {
+        let template = match self.name {
+            TemplateName::InsufficientFunds => &templates.insufficient_funds,
+            TemplateName::InvalidFractionalAmount => &templates.invalid_fractional_amount,
+            TemplateName::TokenNotFound => &templates.token_not_found,
+        };
+
+        template
+            .as_deref()
+            .map(|template| render(template, &self.placeholders))
+    }
+}
+
+/// Client-facing message of `e`, rendered from its template in `templates` if there is one
+pub fn message(templates: &ErrorTemplates, e: &DcError) -> String {
+    Template::of(e)
+        .and_then(|template| template.render(templates))
+        .unwrap_or_else(|| e.to_string())
+}
+
+/// Replaces every `{name}` in `template` with its value
+fn render(template: &str, placeholders: &[(&str, String)]) -> String {
+    placeholders
+        .iter()
+        .fold(template.to_owned(), |message, (name, value)| {
+            message.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::{address, Address, U256};
+
+    use super::{message, render};
+    use crate::{config::ErrorTemplates, service::DcError};
+
+    const OWNER: Address = address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+
+    #[test]
+    fn should_substitute_placeholders() {
+        let placeholders = [("address", OWNER.to_string()), ("required", "5".into())];
+
+        assert_eq!(
+            render(
+                "{address} needs {required}, {required} in total",
+                &placeholders
+            ),
+            format!("{OWNER} needs 5, 5 in total")
+        );
+        assert_eq!(
+            render("{unknown} {address", &placeholders),
+            "{unknown} {address"
+        );
+
+        let templates = ErrorTemplates {
+            insufficient_funds: Some("{address}: {required} needed, {available} available".into()),
+            ..Default::default()
+        };
+        let insufficient = DcError::InsufficientFunds {
+            required: U256::from(5),
+            available: U256::from(3),
+            address: OWNER,
+        };
+        assert_eq!(
+            message(&templates, &insufficient),
+            format!("{OWNER}: 5 needed, 3 available")
+        );
+
+        // errors without a configured template keep their message
+        let not_found = DcError::TokenNotFound(OWNER);
+        assert_eq!(message(&templates, &not_found), not_found.to_string());
+        assert_eq!(
+            message(&ErrorTemplates::default(), &insufficient),
+            insufficient.to_string()
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Mock node and configuration shared by the unit tests
+
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use alloy::{
+    consensus::{Transaction, TxEnvelope},
+    eips::eip2718::Decodable2718,
+    primitives::{address, keccak256, Address, Bytes, B256},
+    rpc::{
+        json_rpc::{ErrorPayload, RequestPacket, Response, ResponsePacket, ResponsePayload},
+        types::TransactionRequest,
+    },
+    transports::{http::reqwest::Url, TransportError, TransportErrorKind, TransportFut},
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response as HttpResponse},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tower::Service;
+
+use crate::{config::AppConfig, state::AppState};
+
+pub const CHAIN_ID: u64 = 31337;
+pub const CONTRACT: Address = address!("5FbDB2315678afecb367f032d93F642f64180aa3");
+pub const TOKEN: Address = address!("e7f1725E7734CE288F8367e1Bb143E90bb3F0512");
+/// First anvil account, the relayer signing with `TX_SIGNER`
+pub const SIGNER_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+pub const SIGNER: Address = address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+/// Second anvil account
+pub const SPENDER_KEY: &str = "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+pub const SPENDER: Address = address!("70997970C51812dc3A010C7d01b50e0d17dc79C8");
+
+/// Answer of the mock node to one request
+pub enum Reply {
+    Ok(Value),
+    /// JSON-RPC error response
+    Rpc(i64, String),
+    /// The node never answers
+    Stall,
+    /// The request fails in the transport, an HTTP error is answered with its status when served over HTTP
+    Transport(TransportErrorKind),
+}
+
+impl Reply {
+    /// Return data of an `eth_call`
+    pub fn data(data: impl Into<Bytes>) -> Self {
+        Self::Ok(json!(data.into()))
+    }
+}
+
+type Handler = dyn Fn(&str, &Value) -> Option<Reply> + Send + Sync;
+
+/// Node answering requests with a handler, falling back to a healthy chain without state for requests
+/// the handler returns `None` for. Every request is recorded.
+#[derive(Clone)]
+pub struct MockNode {
+    handler: Arc<Handler>,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+impl MockNode {
+    pub fn new(handler: impl Fn(&str, &Value) -> Option<Reply> + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            requests: Default::default(),
+        }
+    }
+
+    /// Params of every request of `method`, in order
+    pub fn requests(&self, method: &str) -> Vec<Value> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+
+    /// Broadcast transactions with their signer
+    pub fn sent(&self) -> Vec<(Address, TxEnvelope)> {
+        self.requests("eth_sendRawTransaction")
+            .into_iter()
+            .map(|params| {
+                let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+                let envelope = TxEnvelope::decode_2718(&mut raw.as_ref()).unwrap();
+                (envelope.recover_signer().unwrap(), envelope)
+            })
+            .collect()
+    }
+
+    /// Target and calldata of `eth_call`s
+    pub fn calls(&self) -> Vec<(Address, Bytes)> {
+        self.requests("eth_call").iter().map(call_input).collect()
+    }
+
+    /// Serves the node over HTTP on a local port
+    pub async fn serve(&self) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let node = self.clone();
+        let app = Router::new().route(
+            "/",
+            post(move |Json(request): Json<Value>| async move { node.answer_http(request).await }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        url.parse().unwrap()
+    }
+
+    async fn answer_http(&self, request: Value) -> HttpResponse {
+        let batch = request.is_array();
+        let requests = match request {
+            Value::Array(requests) => requests,
+            request => vec![request],
+        };
+        let mut responses = Vec::with_capacity(requests.len());
+
+        for request in requests {
+            let method = request["method"].as_str().unwrap_or_default();
+            let mut response = json!({ "jsonrpc": "2.0", "id": request["id"] });
+
+            match self.reply(method, request["params"].clone()) {
+                Reply::Ok(value) => response["result"] = value,
+                Reply::Rpc(code, message) => {
+                    response["error"] = json!({ "code": code, "message": message })
+                }
+                Reply::Stall => std::future::pending().await,
+                Reply::Transport(TransportErrorKind::HttpError(e)) => {
+                    let status = StatusCode::from_u16(e.status).unwrap();
+                    return (status, e.body).into_response();
+                }
+                Reply::Transport(e) => {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+                }
+            }
+
+            responses.push(response);
+        }
+
+        match batch {
+            true => Json(Value::Array(responses)).into_response(),
+            false => Json(responses.remove(0)).into_response(),
+        }
+    }
+
+    fn reply(&self, method: &str, params: Value) -> Reply {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((method.to_owned(), params.clone()));
+
+        (self.handler)(method, &params).unwrap_or_else(|| default_reply(method, &params))
+    }
+}
+
+/// Target and calldata of an `eth_call` or `eth_estimateGas`
+pub fn call_input(params: &Value) -> (Address, Bytes) {
+    let request: TransactionRequest = serde_json::from_value(params[0].clone()).unwrap();
+    let to = request
+        .to
+        .and_then(|to| to.to().copied())
+        .unwrap_or_default();
+
+    (to, request.input.input().cloned().unwrap_or_default())
+}
+
+/// Selector of an `eth_call` and its target
+pub fn call_selector(params: &Value) -> (Address, [u8; 4]) {
+    let (to, input) = call_input(params);
+    let selector = input.get(..4).map(|s| s.try_into().unwrap());
+
+    (to, selector.unwrap_or_default())
+}
+
+/// Successful receipt of `tx_hash` mined in block 100
+pub fn receipt(tx_hash: B256, status: bool) -> Value {
+    json!({
+        "transactionHash": tx_hash,
+        "transactionIndex": "0x0",
+        "blockHash": B256::repeat_byte(1),
+        "blockNumber": "0x64",
+        "from": SIGNER,
+        "to": CONTRACT,
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "effectiveGasPrice": "0x2",
+        "contractAddress": null,
+        "logs": [],
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "status": if status { "0x1" } else { "0x0" },
+        "type": "0x2",
+    })
+}
+
+fn default_reply(method: &str, params: &Value) -> Reply {
+    let value = match method {
+        "eth_chainId" => json!(format!("{CHAIN_ID:#x}")),
+        "eth_blockNumber" => json!("0x64"),
+        "eth_getCode" => {
+            let address: Address = serde_json::from_value(params[0].clone()).unwrap();
+            json!(if [CONTRACT, TOKEN].contains(&address) {
+                "0x6080"
+            } else {
+                "0x"
+            })
+        }
+        "eth_getBalance" | "eth_getTransactionCount" => json!("0x0"),
+        "eth_estimateGas" => json!("0x30000"),
+        "eth_gasPrice" | "eth_maxPriorityFeePerGas" => json!("0x1"),
+        "eth_feeHistory" => json!({
+            "oldestBlock": "0x5b",
+            "baseFeePerGas": vec!["0x1"; 11],
+            "gasUsedRatio": vec![0.5; 10],
+            "reward": vec![vec!["0x1"]; 10],
+        }),
+        "eth_sendRawTransaction" => {
+            let raw: Bytes = serde_json::from_value(params[0].clone()).unwrap();
+            json!(keccak256(raw))
+        }
+        "eth_getTransactionReceipt" => {
+            let tx_hash: B256 = serde_json::from_value(params[0].clone()).unwrap();
+            receipt(tx_hash, true)
+        }
+        "eth_getTransactionByHash" => Value::Null,
+        _ => return Reply::Rpc(-32601, format!("method {method} not mocked")),
+    };
+
+    Reply::Ok(value)
+}
+
+impl Service<RequestPacket> for MockNode {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let requests = match request {
+            RequestPacket::Single(request) => vec![request],
+            RequestPacket::Batch(requests) => requests,
+        };
+        let mut responses = Vec::with_capacity(requests.len());
+
+        for request in &requests {
+            let params = request
+                .params()
+                .map(|params| serde_json::from_str(params.get()).unwrap())
+                .unwrap_or(Value::Null);
+
+            let payload = match self.reply(request.method(), params) {
+                Reply::Ok(value) => {
+                    ResponsePayload::Success(serde_json::value::to_raw_value(&value).unwrap())
+                }
+                Reply::Rpc(code, message) => ResponsePayload::Failure(ErrorPayload {
+                    code,
+                    message,
+                    data: None,
+                }),
+                Reply::Stall => return Box::pin(std::future::pending()),
+                Reply::Transport(e) => return Box::pin(async move { Err(e.into()) }),
+            };
+
+            responses.push(Response {
+                id: request.id().clone(),
+                payload,
+            });
+        }
+
+        let packet = match responses.len() {
+            1 => ResponsePacket::Single(responses.remove(0)),
+            _ => ResponsePacket::Batch(responses),
+        };
+
+        Box::pin(async move { Ok(packet) })
+    }
+}
+
+/// Configuration of a local signer on the mock chain, `overrides` are merged over it
+pub fn config(overrides: Value) -> AppConfig {
+    let mut config = json!({
+        "rpc_url": "http://localhost:8545",
+        "contract_address": CONTRACT,
+        "port": 0,
+        "tx_signer": SIGNER_KEY,
+        "use_access_lists": false,
+    });
+    if let Value::Object(overrides) = overrides {
+        config.as_object_mut().unwrap().extend(overrides);
+    }
+
+    serde_json::from_value(config).unwrap()
+}
+
+/// State on the mock chain, served by `node`
+pub async fn state(node: &MockNode, overrides: Value) -> Arc<AppState> {
+    let mut config = json!({ "rpc_url": node.serve().await });
+    if let Value::Object(overrides) = overrides {
+        config.as_object_mut().unwrap().extend(overrides);
+    }
+
+    AppState::init(self::config(config)).await.unwrap()
+}
+
+/// Fields of a sent transaction regardless of its type
+pub fn fields(envelope: &TxEnvelope) -> &dyn Transaction {
+    match envelope {
+        TxEnvelope::Legacy(tx) => tx.tx(),
+        TxEnvelope::Eip2930(tx) => tx.tx(),
+        TxEnvelope::Eip1559(tx) => tx.tx(),
+        TxEnvelope::Eip4844(tx) => tx.tx(),
+        _ => unreachable!("unsupported transaction type"),
+    }
+}
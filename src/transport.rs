@@ -0,0 +1,561 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use alloy::{
+    rpc::json_rpc::{RequestPacket, ResponsePacket, RpcError},
+    transports::{
+        http::{
+            reqwest::{self, Url},
+            Http,
+        },
+        utils::guess_local_url,
+        BoxTransport, Transport, TransportError, TransportErrorKind, TransportFut,
+    },
+};
+use tokio::sync::Semaphore;
+use tower::{Layer, Service, ServiceBuilder};
+use tracing::{debug, warn};
+
+use crate::{config::RpcRetry, metrics};
+
+/// Methods that broadcast a transaction, resending them once they may have reached the node could
+/// submit the transaction twice
+const BROADCAST_METHODS: &[&str] = &["eth_sendRawTransaction", "eth_sendTransaction"];
+
+/// HTTP transport over several nodes. Requests go to the first one and fail over to the next in order
+/// if it returns a transport error, e.g. an HTTP error, a refused connection or an [`RpcTimeout`]
+#[derive(Clone)]
+pub struct Fallback {
+    endpoints: Arc<[(Url, BoxTransport)]>,
+}
+
+impl Fallback {
+    /// `timeout` limits each endpoint on its own, so a stalled node is skipped
+    pub fn new(urls: impl IntoIterator<Item = Url>, timeout: TimeoutLayer) -> Self {
+        Self::from_endpoints(urls.into_iter().map(|url| {
+            let transport = ServiceBuilder::new()
+                .layer(timeout)
+                .service(Http::new(url.clone()));
+
+            (url, transport.boxed())
+        }))
+    }
+
+    fn from_endpoints(endpoints: impl IntoIterator<Item = (Url, BoxTransport)>) -> Self {
+        Self {
+            endpoints: endpoints.into_iter().collect(),
+        }
+    }
+
+    /// Whether the nodes run locally, which makes the client poll more often
+    pub fn is_local(&self) -> bool {
+        self.endpoints
+            .iter()
+            .all(|(url, _)| guess_local_url(url.as_str()))
+    }
+}
+
+impl Service<RequestPacket> for Fallback {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // HTTP transports are always ready, each request picks its endpoint
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let endpoints = self.endpoints.clone();
+
+        Box::pin(async move {
+            let (last, fallbacks) = endpoints
+                .split_last()
+                .expect("at least one RPC endpoint is configured");
+
+            for (url, transport) in fallbacks {
+                match transport.clone().call(request.clone()).await {
+                    Err(RpcError::Transport(e)) => {
+                        warn!(
+                            "RPC endpoint {} failed, trying the next one: {e}",
+                            host(url)
+                        );
+                    }
+                    result => {
+                        debug!("RPC request served by {}", host(url));
+                        return result;
+                    }
+                }
+            }
+
+            let (url, transport) = last;
+            debug!("RPC request served by {}", host(url));
+            transport.clone().call(request).await
+        })
+    }
+}
+
+/// Host of an RPC endpoint, the path and query may hold an API key
+fn host(url: &Url) -> &str {
+    url.host_str().unwrap_or_default()
+}
+
+/// Bounds the number of in-flight RPC requests across all clones of the transport
+#[derive(Clone)]
+pub struct ConcurrencyLimitLayer {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+        }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConcurrencyLimit {
+            inner,
+            semaphore: self.semaphore.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    semaphore: Arc<Semaphore>,
+}
+
+impl<S> Service<RequestPacket> for ConcurrencyLimit<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let semaphore = self.semaphore.clone();
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .expect("semaphore is never closed");
+
+            inner.call(request).await
+        })
+    }
+}
+
+/// Error of an RPC request that didn't complete within the [`TimeoutLayer`]'s timeout
+#[derive(Debug, thiserror::Error)]
+#[error("RPC request timed out after {0:?}")]
+pub struct RpcTimeout(Duration);
+
+impl RpcTimeout {
+    pub fn is_timeout(kind: &TransportErrorKind) -> bool {
+        matches!(kind, TransportErrorKind::Custom(e) if e.is::<Self>())
+    }
+}
+
+/// Fails RPC requests that take longer than `timeout` with [`RpcTimeout`], requests are not limited if `None`
+#[derive(Clone, Copy)]
+pub struct TimeoutLayer {
+    timeout: Option<Duration>,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = Timeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Timeout {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Timeout<S> {
+    inner: S,
+    timeout: Option<Duration>,
+}
+
+impl<S> Service<RequestPacket> for Timeout<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let timeout = self.timeout;
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            let Some(timeout) = timeout else {
+                return inner.call(request).await;
+            };
+
+            tokio::time::timeout(timeout, inner.call(request))
+                .await
+                .unwrap_or_else(|_| Err(TransportErrorKind::custom(RpcTimeout(timeout))))
+        })
+    }
+}
+
+/// Resends RPC requests failing with a transient transport error, with exponential backoff and jitter
+#[derive(Clone, Copy)]
+pub struct RetryLayer {
+    policy: RpcRetry,
+}
+
+impl RetryLayer {
+    pub fn new(policy: RpcRetry) -> Self {
+        Self { policy }
+    }
+}
+
+impl<S> Layer<S> for RetryLayer {
+    type Service = Retry<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Retry {
+            inner,
+            policy: self.policy,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Retry<S> {
+    inner: S,
+    policy: RpcRetry,
+}
+
+impl<S> Service<RequestPacket> for Retry<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let policy = self.policy;
+        let mut inner = self.inner.clone();
+        let broadcast = match &request {
+            RequestPacket::Single(request) => BROADCAST_METHODS.contains(&request.method()),
+            RequestPacket::Batch(requests) => requests
+                .iter()
+                .any(|request| BROADCAST_METHODS.contains(&request.method())),
+        };
+
+        Box::pin(async move {
+            let mut backoff = Duration::from_millis(policy.initial_backoff_ms);
+            let mut retries = 0;
+
+            loop {
+                match inner.call(request.clone()).await {
+                    Err(RpcError::Transport(e))
+                        if retries < policy.max_retries && is_transient(&e, broadcast) =>
+                    {
+                        retries += 1;
+                        let delay = with_jitter(backoff);
+                        debug!("Retrying RPC request in {delay:?}, attempt {retries}: {e}");
+
+                        tokio::time::sleep(delay).await;
+                        backoff = backoff.saturating_mul(2);
+                    }
+                    result => return result,
+                }
+            }
+        })
+    }
+}
+
+/// Rate limits, unavailable gateways, timeouts and failed connections. A broadcast is only resent if
+/// the connection failed, the node can't have received it then
+fn is_transient(e: &TransportErrorKind, broadcast: bool) -> bool {
+    let reqwest_error = match e {
+        TransportErrorKind::Custom(e) => e.downcast_ref::<reqwest::Error>(),
+        _ => None,
+    };
+    if reqwest_error.is_some_and(reqwest::Error::is_connect) {
+        return true;
+    }
+    if broadcast {
+        return false;
+    }
+
+    match e {
+        TransportErrorKind::HttpError(e) => matches!(e.status, 429 | 502 | 503 | 504),
+        e => {
+            e.is_retry_err()
+                || RpcTimeout::is_timeout(e)
+                || reqwest_error.is_some_and(reqwest::Error::is_timeout)
+        }
+    }
+}
+
+/// Random delay between half of `backoff` and `backoff`, so clients rate limited together don't retry together
+fn with_jitter(backoff: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let half = backoff / 2;
+
+    half + half.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Records the latency of every RPC request in [`metrics::RPC_DURATION`], batches are labeled `batch`
+#[derive(Clone, Copy)]
+pub struct RpcMetricsLayer;
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcMetrics { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcMetrics<S> {
+    inner: S,
+}
+
+impl<S> Service<RequestPacket> for RpcMetrics<S>
+where
+    S: Transport + Clone,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let method = match &request {
+            RequestPacket::Single(request) => request.method().to_owned(),
+            RequestPacket::Batch(_) => "batch".to_owned(),
+        };
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let response = inner.call(request).await;
+            metrics::RPC_DURATION.record(&[("method", &method)], started.elapsed().as_secs_f64());
+
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use alloy::{
+        primitives::U64,
+        rpc::client::ClientBuilder,
+        transports::{http::reqwest::Url, HttpError, RpcError, Transport, TransportErrorKind},
+    };
+    use serde_json::json;
+
+    use super::{
+        is_transient, ConcurrencyLimitLayer, Fallback, RetryLayer, RpcTimeout, TimeoutLayer,
+    };
+    use crate::{
+        config::RpcRetry,
+        testing::{MockNode, Reply, CHAIN_ID},
+    };
+
+    fn unavailable() -> Reply {
+        Reply::Transport(TransportErrorKind::HttpError(HttpError {
+            status: 503,
+            body: String::new(),
+        }))
+    }
+
+    fn retry(max_retries: u32) -> RetryLayer {
+        RetryLayer::new(RpcRetry {
+            max_retries,
+            initial_backoff_ms: 1,
+        })
+    }
+
+    #[tokio::test]
+    async fn should_hold_requests_beyond_the_concurrency_limit() {
+        let node = MockNode::new(|method, _| match method {
+            "eth_blockNumber" => Some(Reply::Stall),
+            _ => None,
+        });
+        let client = ClientBuilder::default()
+            .layer(ConcurrencyLimitLayer::new(1))
+            .transport(node.clone(), true);
+
+        let stalled = tokio::spawn(client.request::<_, U64>("eth_blockNumber", ()));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let queued = client.request::<_, U64>("eth_chainId", ());
+        assert!(tokio::time::timeout(Duration::from_millis(50), queued)
+            .await
+            .is_err());
+        assert!(node.requests("eth_chainId").is_empty());
+
+        stalled.abort();
+        let chain_id = client.request::<_, U64>("eth_chainId", ()).await;
+        assert_eq!(chain_id.unwrap(), U64::from(CHAIN_ID));
+    }
+
+    #[tokio::test]
+    async fn should_time_out_requests_the_node_never_answers() {
+        let node = MockNode::new(|method, _| match method {
+            "eth_blockNumber" => Some(Reply::Stall),
+            _ => Some(Reply::Ok(json!("0x1"))),
+        });
+        let client = ClientBuilder::default()
+            .layer(TimeoutLayer::new(Some(Duration::from_millis(10))))
+            .transport(node, true);
+
+        let result = client.request::<_, U64>("eth_blockNumber", ()).await;
+        let Err(RpcError::Transport(e)) = result else {
+            panic!("expected a timeout, got {result:?}");
+        };
+        assert!(RpcTimeout::is_timeout(&e));
+
+        let result = client.request::<_, U64>("eth_chainId", ()).await;
+        assert_eq!(result.unwrap(), U64::from(1));
+    }
+
+    #[test]
+    fn should_only_retry_broadcasts_that_never_reached_the_node() {
+        let timeout = || TransportErrorKind::Custom(Box::new(RpcTimeout(Duration::from_secs(1))));
+        let http = |status| {
+            TransportErrorKind::HttpError(HttpError {
+                status,
+                body: String::new(),
+            })
+        };
+        let (unavailable, rejected) = (|| http(503), || http(400));
+
+        assert!(is_transient(&timeout(), false));
+        assert!(is_transient(&unavailable(), false));
+        assert!(!is_transient(&rejected(), false));
+
+        assert!(!is_transient(&timeout(), true));
+        assert!(!is_transient(&unavailable(), true));
+    }
+
+    #[tokio::test]
+    async fn should_retry_until_the_node_recovers() {
+        let attempts = AtomicUsize::new(0);
+        let node = MockNode::new(move |method, _| match method {
+            "eth_blockNumber" if attempts.fetch_add(1, Ordering::SeqCst) < 2 => Some(unavailable()),
+            _ => None,
+        });
+        let client = ClientBuilder::default()
+            .layer(retry(3))
+            .transport(node.clone(), true);
+
+        let block = client.request::<_, U64>("eth_blockNumber", ()).await;
+        assert_eq!(block.unwrap(), U64::from(100));
+        assert_eq!(node.requests("eth_blockNumber").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn should_give_up_after_the_last_retry() {
+        let node = MockNode::new(|_, _| Some(unavailable()));
+        let client = ClientBuilder::default()
+            .layer(retry(2))
+            .transport(node.clone(), true);
+
+        let result = client.request::<_, U64>("eth_blockNumber", ()).await;
+        assert!(matches!(
+            result,
+            Err(RpcError::Transport(TransportErrorKind::HttpError(_)))
+        ));
+        assert_eq!(node.requests("eth_blockNumber").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn should_not_resend_a_broadcast_the_node_may_have_received() {
+        let node = MockNode::new(|_, _| Some(unavailable()));
+        let client = ClientBuilder::default()
+            .layer(retry(3))
+            .transport(node.clone(), true);
+
+        let result = client
+            .request::<_, U64>("eth_sendRawTransaction", ("0x02",))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(node.requests("eth_sendRawTransaction").len(), 1);
+    }
+
+    fn fallback(nodes: &[&MockNode]) -> Fallback {
+        Fallback::from_endpoints(nodes.iter().enumerate().map(|(i, node)| {
+            let url = Url::parse(&format!("http://node-{i}:8545")).unwrap();
+            (url, (*node).clone().boxed())
+        }))
+    }
+
+    #[tokio::test]
+    async fn should_fail_over_to_the_next_node_on_a_transport_error() {
+        let (down, up) = (
+            MockNode::new(|_, _| Some(unavailable())),
+            MockNode::new(|_, _| None),
+        );
+        let client = ClientBuilder::default().transport(fallback(&[&down, &up]), true);
+
+        let block = client.request::<_, U64>("eth_blockNumber", ()).await;
+        assert_eq!(block.unwrap(), U64::from(100));
+        assert_eq!(down.requests("eth_blockNumber").len(), 1);
+        assert_eq!(up.requests("eth_blockNumber").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_not_fail_over_on_an_error_response() {
+        let rejecting = MockNode::new(|_, _| Some(Reply::Rpc(-32000, "execution reverted".into())));
+        let up = MockNode::new(|_, _| None);
+        let client = ClientBuilder::default().transport(fallback(&[&rejecting, &up]), true);
+
+        let result = client.request::<_, U64>("eth_blockNumber", ()).await;
+        assert!(matches!(result, Err(RpcError::ErrorResp(_))));
+        assert!(up.requests("eth_blockNumber").is_empty());
+    }
+}
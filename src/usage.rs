@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use alloy::primitives::{Address, U256};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CallerUsage {
+    /// Sum of `gasUsed * effectiveGasPrice` in the current window
+    pub spent: U256,
+    pub transactions: u64,
+    window_start: Instant,
+}
+
+/// In-memory gas spend per caller, reset every `window`
+#[derive(Clone)]
+pub struct UsageTracker {
+    usage: Arc<RwLock<HashMap<Address, CallerUsage>>>,
+    window: Duration,
+}
+
+impl UsageTracker {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            usage: Default::default(),
+            window,
+        }
+    }
+
+    pub fn get(&self, caller: &Address) -> Option<CallerUsage> {
+        self.usage
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(caller)
+            .filter(|u| u.window_start.elapsed() < self.window)
+            .copied()
+    }
+
+    pub fn record(&self, caller: Address, cost: U256) {
+        let mut usage = self.usage.write().unwrap_or_else(|e| e.into_inner());
+
+        let entry = usage.entry(caller).or_insert(CallerUsage {
+            spent: U256::ZERO,
+            transactions: 0,
+            window_start: Instant::now(),
+        });
+
+        if entry.window_start.elapsed() >= self.window {
+            *entry = CallerUsage {
+                spent: U256::ZERO,
+                transactions: 0,
+                window_start: Instant::now(),
+            };
+        }
+
+        entry.spent = entry.spent.saturating_add(cost);
+        entry.transactions += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use alloy::primitives::{address, Address, U256};
+
+    use super::UsageTracker;
+
+    const CALLER: Address = address!("f39Fd6e51aad88F6F4ce6aB8827279cffFb92266");
+
+    #[test]
+    fn should_sum_spend_within_the_window() {
+        let tracker = UsageTracker::new(Duration::from_secs(3600));
+
+        tracker.record(CALLER, U256::from(100));
+        tracker.record(CALLER, U256::from(50));
+
+        let usage = tracker.get(&CALLER).unwrap();
+        assert_eq!(usage.spent, U256::from(150));
+        assert_eq!(usage.transactions, 2);
+        assert!(tracker.get(&Address::ZERO).is_none());
+    }
+
+    #[test]
+    fn should_reset_spend_once_the_window_passes() {
+        let tracker = UsageTracker::new(Duration::from_millis(20));
+
+        tracker.record(CALLER, U256::MAX);
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(tracker.get(&CALLER).is_none());
+
+        tracker.record(CALLER, U256::from(7));
+        let usage = tracker.get(&CALLER).unwrap();
+        assert_eq!(usage.spent, U256::from(7));
+        assert_eq!(usage.transactions, 1);
+    }
+}